@@ -0,0 +1,600 @@
+use crate::client::AirlyClient;
+use crate::transport::Transport;
+use crate::types::{self, AveragedValues, GeoPoint, IndexType, MeasurementKind};
+
+const SECONDS_PER_HOUR: i64 = 3600;
+const DEFAULT_INDEX: &str = "AIRLY_CAQI";
+const DEFAULT_MAX_WINDOWS: usize = 3;
+const LEVEL_PENALTY: f64 = 12.0;
+const UNACCEPTABLE_LEVEL_PENALTY: f64 = 30.0;
+const PM25_PENALTY: f64 = 20.0;
+const PM10_PENALTY: f64 = 10.0;
+const TEMPERATURE_PENALTY: f64 = 20.0;
+const WIND_PENALTY: f64 = 15.0;
+
+/// Language of reasons, also requested from Airly for Index.advice
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Language {
+    #[serde(rename = "en")]
+    En,
+    #[serde(rename = "pl")]
+    Pl,
+}
+
+impl Language {
+    /// # Returns language code as sent in Accept-Language header
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::En => "en",
+            Language::Pl => "pl",
+        }
+    }
+
+    fn level_name(self, level: &str) -> String {
+        let names = match self {
+            Language::En => ["very low", "low", "medium", "high", "very high", "extreme", "airmageddon"],
+            Language::Pl => ["bardzo niski", "niski", "średni", "wysoki", "bardzo wysoki", "ekstremalny", "airmageddon"],
+        };
+        types::level_rank(level).map_or(level.to_string(), |rank| names[rank].to_string())
+    }
+}
+
+/// Limits hour has to meet to be recommended
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Sensitivity {
+    /// The worst acceptable index level, position in types::INDEX_LEVELS
+    #[serde(rename = "maxLevel")]
+    pub max_level: usize,
+    /// The highest acceptable PM2.5 in µg/m³
+    #[serde(rename = "pm25Limit")]
+    pub pm25_limit: f64,
+    /// The highest acceptable PM10 in µg/m³
+    #[serde(rename = "pm10Limit")]
+    pub pm10_limit: f64,
+    /// Comfortable temperature range in °C, checked with weather
+    #[serde(rename = "minTemperature")]
+    pub min_temperature: f64,
+    #[serde(rename = "maxTemperature")]
+    pub max_temperature: f64,
+    /// The strongest comfortable wind in m/s, checked with weather
+    #[serde(rename = "maxWindMs")]
+    pub max_wind_ms: f64,
+}
+
+/// Who advice is meant for
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Profile {
+    #[serde(rename = "general")]
+    General,
+    /// Asthma and other respiratory conditions, cold air is a trigger too
+    #[serde(rename = "asthma")]
+    Asthma,
+    #[serde(rename = "children")]
+    Children,
+    #[serde(rename = "elderly")]
+    Elderly,
+}
+
+impl Profile {
+    /// # Returns limits of this profile, sensitive groups accept LOW index level and WHO 2021 daily PM levels
+    pub fn sensitivity(self) -> Sensitivity {
+        let (max_level, pm25_limit, pm10_limit, min_temperature, max_temperature, max_wind_ms) = match self {
+            Profile::General => (2, 25.0, 50.0, -10.0, 30.0, 10.0),
+            Profile::Asthma => (1, 15.0, 45.0, 0.0, 30.0, 8.0),
+            Profile::Children => (1, 15.0, 45.0, 0.0, 28.0, 8.0),
+            Profile::Elderly => (1, 15.0, 45.0, 5.0, 27.0, 6.0),
+        };
+        Sensitivity {
+            max_level,
+            pm25_limit,
+            pm10_limit,
+            min_temperature,
+            max_temperature,
+            max_wind_ms,
+        }
+    }
+}
+
+/// Settings of advisor
+#[derive(Debug, Clone)]
+pub struct AdvisorOptions {
+    sensitivity: Sensitivity,
+    weather: bool,
+    index: String,
+    language: Language,
+    min_hours: usize,
+    max_windows: usize,
+    period: Option<(i64, i64)>,
+}
+
+impl Default for AdvisorOptions {
+    fn default() -> Self {
+        AdvisorOptions::new(Profile::General)
+    }
+}
+
+impl AdvisorOptions {
+    pub fn new(profile: Profile) -> Self {
+        Self {
+            sensitivity: profile.sensitivity(),
+            weather: false,
+            index: DEFAULT_INDEX.to_string(),
+            language: Language::En,
+            min_hours: 1,
+            max_windows: DEFAULT_MAX_WINDOWS,
+            period: None,
+        }
+    }
+
+    /// Replaces limits of profile with own ones
+    pub fn with_sensitivity(mut self, sensitivity: Sensitivity) -> Self {
+        self.sensitivity = sensitivity;
+        self
+    }
+
+    /// Sets whether temperature and wind are scored too, off by default
+    pub fn with_weather(mut self, weather: bool) -> Self {
+        self.weather = weather;
+        self
+    }
+
+    /// Sets index hours are scored by, AIRLY_CAQI by default
+    pub fn with_index(mut self, index: &str) -> Self {
+        self.index = index.to_string();
+        self
+    }
+
+    /// Sets language of reasons and of Airly advice fetched by advise_installation and advise_point
+    pub fn with_language(mut self, language: Language) -> Self {
+        self.language = language;
+        self
+    }
+
+    /// Sets the shortest recommended window in hours, 1 by default
+    pub fn with_min_hours(mut self, min_hours: usize) -> Self {
+        self.min_hours = min_hours.max(1);
+        self
+    }
+
+    /// Sets number of recommended windows, 3 by default
+    pub fn with_max_windows(mut self, max_windows: usize) -> Self {
+        self.max_windows = max_windows;
+        self
+    }
+
+    /// Limits scanned hours, e.g. to the rest of today
+    ///
+    /// # Arguments:
+    /// * from - first hour considered, unix timestamp in seconds, inclusive
+    /// * till - end of period, unix timestamp in seconds, exclusive
+    ///
+    pub fn with_period(mut self, from: i64, till: i64) -> Self {
+        self.period = Some((from, till));
+        self
+    }
+}
+
+/// Why hour is or is not recommended
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reason {
+    IndexLevel { index: String, level: String, acceptable: bool },
+    Pm25 { value: f64, limit: f64 },
+    Pm10 { value: f64, limit: f64 },
+    Temperature { value: f64, min: f64, max: f64 },
+    Wind { speed_ms: f64, max: f64 },
+}
+
+impl Reason {
+    /// # Returns true if reason does not prevent recommending hour
+    pub fn acceptable(&self) -> bool {
+        match self {
+            Reason::IndexLevel { acceptable, .. } => *acceptable,
+            Reason::Pm25 { value, limit } | Reason::Pm10 { value, limit } => value <= limit,
+            Reason::Temperature { value, min, max } => value >= min && value <= max,
+            Reason::Wind { speed_ms, max } => speed_ms <= max,
+        }
+    }
+
+    /// # Returns reason described in given language
+    pub fn text(&self, language: Language) -> String {
+        let ok = self.acceptable();
+        match (self, language) {
+            (Reason::IndexLevel { index, level, .. }, Language::En) => {
+                format!("{} level {}{}", index, language.level_name(level), if ok { "" } else { ", too high" })
+            }
+            (Reason::IndexLevel { index, level, .. }, Language::Pl) => {
+                format!("poziom {} {}{}", index, language.level_name(level), if ok { "" } else { ", zbyt wysoki" })
+            }
+            (Reason::Pm25 { value, limit }, _) => pm_text("PM2.5", *value, *limit, language),
+            (Reason::Pm10 { value, limit }, _) => pm_text("PM10", *value, *limit, language),
+            (Reason::Temperature { value, min, max }, Language::En) => match ok {
+                true => format!("temperature {:.1} °C comfortable", value),
+                false => format!("temperature {:.1} °C outside {}-{} °C", value, min, max),
+            },
+            (Reason::Temperature { value, min, max }, Language::Pl) => match ok {
+                true => format!("temperatura {:.1} °C komfortowa", value),
+                false => format!("temperatura {:.1} °C poza {}-{} °C", value, min, max),
+            },
+            (Reason::Wind { speed_ms, max }, Language::En) => match ok {
+                true => format!("wind {:.1} m/s calm enough", speed_ms),
+                false => format!("wind {:.1} m/s stronger than {} m/s", speed_ms, max),
+            },
+            (Reason::Wind { speed_ms, max }, Language::Pl) => match ok {
+                true => format!("wiatr {:.1} m/s umiarkowany", speed_ms),
+                false => format!("wiatr {:.1} m/s silniejszy niż {} m/s", speed_ms, max),
+            },
+        }
+    }
+}
+
+fn pm_text(name: &str, value: f64, limit: f64, language: Language) -> String {
+    match (value <= limit, language) {
+        (true, Language::En) => format!("{} {:.1} µg/m³ within {}", name, value, limit),
+        (false, Language::En) => format!("{} {:.1} µg/m³ above {}", name, value, limit),
+        (true, Language::Pl) => format!("{} {:.1} µg/m³ w normie {}", name, value, limit),
+        (false, Language::Pl) => format!("{} {:.1} µg/m³ powyżej {}", name, value, limit),
+    }
+}
+
+/// Scored forecast hour
+#[derive(Debug, Clone, PartialEq)]
+pub struct HourAdvice {
+    /// Start of hour, unix timestamp in seconds
+    pub from: i64,
+    /// 0 to 100, the higher the better
+    pub score: f64,
+    pub reasons: Vec<Reason>,
+    /// Advice of Airly index for the hour, in language it was fetched in
+    pub advice: Option<String>,
+}
+
+impl HourAdvice {
+    /// # Returns true if every reason is acceptable
+    pub fn acceptable(&self) -> bool {
+        self.reasons.iter().all(Reason::acceptable)
+    }
+}
+
+/// Recommended consecutive hours
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    /// Start of the first hour, unix timestamp in seconds
+    pub from: i64,
+    /// End of the last hour, unix timestamp in seconds
+    pub till: i64,
+    /// Mean score of hours
+    pub score: f64,
+    pub hours: Vec<HourAdvice>,
+}
+
+impl Window {
+    /// # Returns reasons of the best scored hour described in given language
+    pub fn reasons(&self, language: Language) -> Vec<String> {
+        self.best_hour().reasons.iter().map(|reason| reason.text(language)).collect()
+    }
+
+    /// # Returns Airly advice of the best scored hour
+    pub fn advice(&self) -> Option<&str> {
+        self.best_hour().advice.as_deref()
+    }
+
+    fn best_hour(&self) -> &HourAdvice {
+        self.hours
+            .iter()
+            .fold(&self.hours[0], |best, hour| if hour.score > best.score { hour } else { best })
+    }
+}
+
+/// Scored hours and recommended windows
+#[derive(Debug, Clone, PartialEq)]
+pub struct Advice {
+    /// Every scanned hour in order of time
+    pub hours: Vec<HourAdvice>,
+    /// Recommended windows, the best first
+    pub windows: Vec<Window>,
+}
+
+impl Advice {
+    /// # Returns the best window, None if no hour meets limits
+    pub fn best(&self) -> Option<&Window> {
+        self.windows.first()
+    }
+}
+
+/// Scores single hour
+///
+/// # Returns HourAdvice, None if period has no timestamp
+///
+pub fn score(values: &AveragedValues, options: &AdvisorOptions) -> Option<HourAdvice> {
+    let from = values.from_timestamp()?;
+    let limits = options.sensitivity;
+    let mut score = 100.0;
+    let mut reasons = Vec::new();
+    let index = values.indexes.iter().find(|i| i.name.as_deref() == Some(options.index.as_str()));
+    if let Some((level, rank)) = index.and_then(|i| Some((i.level.clone()?, i.level_rank()?))) {
+        let acceptable = rank <= limits.max_level;
+        score -= LEVEL_PENALTY * rank as f64;
+        if !acceptable {
+            score -= UNACCEPTABLE_LEVEL_PENALTY;
+        }
+        reasons.push(Reason::IndexLevel {
+            index: options.index.clone(),
+            level,
+            acceptable,
+        });
+    }
+    if let Some(value) = values.value(&MeasurementKind::Pm25) {
+        score -= (PM25_PENALTY * value / limits.pm25_limit).min(3.0 * PM25_PENALTY);
+        reasons.push(Reason::Pm25 {
+            value,
+            limit: limits.pm25_limit,
+        });
+    }
+    if let Some(value) = values.value(&MeasurementKind::Pm10) {
+        score -= (PM10_PENALTY * value / limits.pm10_limit).min(3.0 * PM10_PENALTY);
+        reasons.push(Reason::Pm10 {
+            value,
+            limit: limits.pm10_limit,
+        });
+    }
+    if options.weather {
+        if let Some(value) = values.value(&MeasurementKind::Temperature) {
+            let reason = Reason::Temperature {
+                value,
+                min: limits.min_temperature,
+                max: limits.max_temperature,
+            };
+            if !reason.acceptable() {
+                let off = (limits.min_temperature - value).max(value - limits.max_temperature);
+                score -= TEMPERATURE_PENALTY + off;
+            }
+            reasons.push(reason);
+        }
+        if let Some(wind) = values.wind() {
+            let reason = Reason::Wind {
+                speed_ms: wind.speed_ms(),
+                max: limits.max_wind_ms,
+            };
+            if !reason.acceptable() {
+                score -= WIND_PENALTY;
+            }
+            reasons.push(reason);
+        }
+    }
+    Some(HourAdvice {
+        from,
+        score: score.clamp(0.0, 100.0),
+        reasons,
+        advice: index.and_then(|i| i.advice.clone()),
+    })
+}
+
+/// Scans forecast for hours meeting limits of profile
+///
+/// # Arguments:
+/// * forecast - hourly forecast, e.g. Measurements.forecast
+/// * options - profile, weather and period scanned
+///
+/// # Returns scored hours and windows of consecutive acceptable hours, the best mean score first
+///
+pub fn advise(forecast: &[AveragedValues], options: &AdvisorOptions) -> Advice {
+    let mut hours: Vec<HourAdvice> = forecast
+        .iter()
+        .filter_map(|values| score(values, options))
+        .filter(|hour| match options.period {
+            Some((from, till)) => hour.from >= from && hour.from < till,
+            None => true,
+        })
+        .collect();
+    hours.sort_by_key(|hour| hour.from);
+    let mut windows: Vec<Window> = Vec::new();
+    let mut current: Vec<HourAdvice> = Vec::new();
+    for hour in hours.iter().cloned().map(Some).chain(std::iter::once(None)) {
+        let continues = match (&hour, current.last()) {
+            (Some(hour), Some(last)) => hour.acceptable() && hour.from - last.from == SECONDS_PER_HOUR,
+            _ => false,
+        };
+        if !continues {
+            if current.len() >= options.min_hours {
+                windows.push(Window {
+                    from: current[0].from,
+                    till: current[current.len() - 1].from + SECONDS_PER_HOUR,
+                    score: current.iter().map(|h| h.score).sum::<f64>() / current.len() as f64,
+                    hours: current.clone(),
+                });
+            }
+            current.clear();
+        }
+        if let Some(hour) = hour.filter(HourAdvice::acceptable) {
+            current.push(hour);
+        }
+    }
+    windows.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    windows.truncate(options.max_windows);
+    Advice { hours, windows }
+}
+
+fn index_type(options: &AdvisorOptions) -> IndexType {
+    IndexType {
+        name: Some(options.index.clone()),
+        level: None,
+    }
+}
+
+/// Fetches forecast of installation, with wind, in language of options and scans it
+///
+/// # Returns Advice or Error if measurements could not be fetched
+///
+pub fn advise_installation<T: Transport>(
+    client: &AirlyClient<T>,
+    id: u32,
+    options: &AdvisorOptions,
+) -> Result<Advice, Box<dyn std::error::Error>> {
+    let measurements = client
+        .clone()
+        .with_language(options.language.code())
+        .get_installation_measurements_with_wind(id, index_type(options))?;
+    Ok(advise(&measurements.forecast, options))
+}
+
+/// Fetches forecast interpolated for point in language of options and scans it
+///
+/// # Returns Advice or Error if measurements could not be fetched
+///
+pub fn advise_point<T: Transport>(
+    client: &AirlyClient<T>,
+    point: GeoPoint,
+    options: &AdvisorOptions,
+) -> Result<Advice, Box<dyn std::error::Error>> {
+    let measurements = client
+        .clone()
+        .with_language(options.language.code())
+        .get_measurements_point(index_type(options), point)?;
+    Ok(advise(&measurements.forecast, options))
+}
+
+#[cfg(test)]
+mod test_advisor {
+    use super::{advise, advise_installation, AdvisorOptions, Language, Profile, Reason};
+    use crate::cache::{MemoryCache, ResponseCache};
+    use crate::client::AirlyClient;
+    use crate::mock::MockAirly;
+    use crate::stub::{StubServer, INSTALLATION_JSON, MEASUREMENTS_JSON};
+    use crate::types::{AveragedValues, Index, Installation, Measurements, Value};
+
+    /// 2019-12-10T00:00:00Z
+    const MIDNIGHT: i64 = 1_575_936_000;
+
+    fn value(name: &str, value: f64) -> Value {
+        Value {
+            name: Some(name.to_string()),
+            value: Some(value),
+        }
+    }
+
+    fn hour(h: i64, level: &str, pm25: f64, temperature: f64) -> AveragedValues {
+        AveragedValues {
+            from_date_time: Some(chrono::DateTime::from_timestamp(MIDNIGHT + h * 3600, 0).unwrap().to_rfc3339()),
+            till_date_time: None,
+            values: vec![
+                value("PM25", pm25),
+                value("PM10", pm25 * 1.5),
+                value("TEMPERATURE", temperature),
+                value("WIND_SPEED", 10.0),
+                value("WIND_BEARING", 180.0),
+            ],
+            indexes: vec![Index {
+                name: Some("AIRLY_CAQI".to_string()),
+                value: Some(pm25 * 2.0),
+                level: Some(level.to_string()),
+                description: None,
+                advice: Some(format!("advice for {}", level)),
+                color: None,
+            }],
+            standards: Vec::new(),
+        }
+    }
+
+    /// Smoggy night and morning, clean early afternoon, fair evening getting cold
+    fn forecast() -> Vec<AveragedValues> {
+        (0..24)
+            .map(|h| match h {
+                12..=14 => hour(h, "VERY_LOW", 8.0, 6.0),
+                15..=16 => hour(h, "LOW", 14.0, 4.0),
+                19..=21 => hour(h, "LOW", 20.0, -2.0),
+                _ => hour(h, "HIGH", 60.0, 2.0),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_windows_per_profile() {
+        let general = advise(&forecast(), &AdvisorOptions::new(Profile::General));
+        assert_eq!(general.hours.len(), 24);
+        assert_eq!(general.windows.len(), 2);
+        let best = general.best().unwrap();
+        assert_eq!((best.from, best.till), (MIDNIGHT + 12 * 3600, MIDNIGHT + 17 * 3600));
+        assert_eq!(general.windows[1].from, MIDNIGHT + 19 * 3600);
+        assert_eq!(best.advice(), Some("advice for VERY_LOW"));
+
+        // PM2.5 of 20 is too much for children
+        let children = advise(&forecast(), &AdvisorOptions::new(Profile::Children));
+        assert_eq!(children.windows.len(), 1);
+        // and so is the cold with weather scored
+        let elderly = AdvisorOptions::new(Profile::Elderly).with_weather(true);
+        let elderly = advise(&forecast(), &elderly);
+        assert_eq!(elderly.windows.len(), 1);
+        assert_eq!((elderly.windows[0].from, elderly.windows[0].till), (MIDNIGHT + 12 * 3600, MIDNIGHT + 15 * 3600));
+        let cold = &elderly.hours[15];
+        assert!(cold.reasons.contains(&Reason::Temperature {
+            value: 4.0,
+            min: 5.0,
+            max: 27.0,
+        }));
+
+        let evening = AdvisorOptions::new(Profile::General).with_period(MIDNIGHT + 18 * 3600, MIDNIGHT + 24 * 3600);
+        assert_eq!(advise(&forecast(), &evening).windows.len(), 1);
+        let long = AdvisorOptions::new(Profile::General).with_min_hours(4);
+        assert_eq!(advise(&forecast(), &long).windows.len(), 1);
+    }
+
+    #[test]
+    fn test_localized_reasons() {
+        let advice = advise(&forecast(), &AdvisorOptions::new(Profile::Asthma).with_weather(true));
+        let reasons = advice.best().unwrap().reasons(Language::En);
+        assert_eq!(
+            reasons,
+            vec![
+                "AIRLY_CAQI level very low",
+                "PM2.5 8.0 µg/m³ within 15",
+                "PM10 12.0 µg/m³ within 45",
+                "temperature 6.0 °C comfortable",
+                "wind 2.8 m/s calm enough",
+            ]
+        );
+        let smog = &advice.hours[0];
+        assert!(!smog.acceptable());
+        assert_eq!(smog.reasons[0].text(Language::Pl), "poziom AIRLY_CAQI wysoki, zbyt wysoki");
+        assert_eq!(smog.reasons[1].text(Language::Pl), "PM2.5 60.0 µg/m³ powyżej 15");
+    }
+
+    #[test]
+    fn test_fetched_forecast_in_language() {
+        let mock = MockAirly::start().unwrap();
+        let installation: Installation = serde_json::from_str(INSTALLATION_JSON).unwrap();
+        let mut measurements: Measurements = serde_json::from_str(MEASUREMENTS_JSON).unwrap();
+        measurements.forecast = forecast();
+        mock.add_installation(installation);
+        mock.set_measurements(18, measurements);
+        let client = mock.client("0123456789abcdef0123456789abcdef").unwrap();
+        let options = AdvisorOptions::new(Profile::General).with_language(Language::Pl);
+        let advice = advise_installation(&client, 18, &options).unwrap();
+        assert_eq!(advice.windows.len(), 2);
+        let requests = mock.requests();
+        assert_eq!(requests.last().unwrap().header("accept-language"), Some("pl"));
+        assert_eq!(client.language(), "en");
+    }
+
+    #[test]
+    fn test_cached_advice_per_language() {
+        let server = StubServer::start_with(|request| {
+            let mut measurements: Measurements = serde_json::from_str(MEASUREMENTS_JSON).unwrap();
+            measurements.forecast = forecast();
+            for hour in measurements.forecast.iter_mut() {
+                hour.indexes[0].advice = Some(format!("advice in {}", request.header("accept-language").unwrap()));
+            }
+            (200, Vec::new(), serde_json::to_string(&measurements).unwrap())
+        });
+        let client = AirlyClient::new("0123456789abcdef0123456789abcdef".to_string())
+            .unwrap()
+            .with_base_url(&server.base_url())
+            .with_cache(ResponseCache::new(MemoryCache::default()));
+        let english = advise_installation(&client, 18, &AdvisorOptions::new(Profile::General)).unwrap();
+        let polish = AdvisorOptions::new(Profile::General).with_language(Language::Pl);
+        let polish = advise_installation(&client, 18, &polish).unwrap();
+        assert_eq!(english.best().unwrap().advice(), Some("advice in en"));
+        assert_eq!(polish.best().unwrap().advice(), Some("advice in pl"));
+        advise_installation(&client, 18, &AdvisorOptions::new(Profile::General)).unwrap();
+        assert_eq!(server.requests().len(), 2);
+    }
+}
@@ -0,0 +1,348 @@
+use crate::types::{self, MeasurementKind};
+use std::collections::HashMap;
+
+pub mod notify;
+
+fn default_for_hours() -> u32 {
+    1
+}
+
+/// What is compared against threshold
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Condition {
+    /// Measurement value above limit, e.g. PM25 above 50
+    Value { measurement: String, above: f64 },
+    /// Index level at or above given level, e.g. AIRLY_CAQI at least HIGH
+    #[serde(rename_all = "camelCase")]
+    IndexLevel { index: String, at_least: String },
+    /// Standard percent of pollutant above limit, e.g. PM10 above 100 percent
+    StandardPercent { pollutant: String, above: f64 },
+}
+
+impl Condition {
+    /// # Returns value of the compared metric in given period, index levels are ranked, see types::level_rank
+    pub fn metric(&self, values: &types::AveragedValues) -> Option<f64> {
+        match self {
+            Condition::Value { measurement, .. } => {
+                values.value(&MeasurementKind::from_name(measurement))
+            }
+            Condition::IndexLevel { index, .. } => values
+                .indexes
+                .iter()
+                .find(|i| i.name.as_ref() == Some(index))
+                .and_then(|i| i.level_rank())
+                .map(|rank| rank as f64),
+            Condition::StandardPercent { pollutant, .. } => values
+                .standards
+                .iter()
+                .filter(|s| s.pollutant.as_ref() == Some(pollutant))
+                .filter_map(|s| s.percent)
+                .fold(None, |max: Option<f64>, p| Some(max.map_or(p, |m| m.max(p)))),
+        }
+    }
+
+    fn exceeded(&self, metric: f64) -> bool {
+        match self {
+            Condition::Value { above, .. } | Condition::StandardPercent { above, .. } => metric > *above,
+            Condition::IndexLevel { at_least, .. } => {
+                matches!(types::level_rank(at_least), Some(rank) if metric >= rank as f64)
+            }
+        }
+    }
+
+    fn cleared(&self, metric: f64, hysteresis: f64) -> bool {
+        match self {
+            Condition::Value { above, .. } | Condition::StandardPercent { above, .. } => {
+                metric <= *above - hysteresis
+            }
+            Condition::IndexLevel { at_least, .. } => {
+                !matches!(types::level_rank(at_least), Some(rank) if metric >= rank as f64 - hysteresis)
+            }
+        }
+    }
+}
+
+/// Alert rule, fires when condition holds for given number of consecutive hours
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Rule {
+    /// Unique name of the rule
+    pub name: String,
+    pub condition: Condition,
+    /// Number of consecutive hours condition must hold before alert fires
+    #[serde(rename = "forHours", default = "default_for_hours")]
+    pub for_hours: u32,
+    /// Margin metric must drop below threshold by before alert resolves, avoids flapping
+    #[serde(default)]
+    pub hysteresis: f64,
+    /// Whether forecast is checked to warn ahead of time
+    #[serde(rename = "includeForecast", default)]
+    pub include_forecast: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum EventKind {
+    /// Condition held for required duration
+    Fired,
+    /// Metric dropped below threshold by hysteresis margin
+    Resolved,
+    /// Forecast predicts condition to hold for required duration
+    Forecast,
+}
+
+/// Alert state change of rule for installation
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Event {
+    pub rule: String,
+    pub installation: u32,
+    pub kind: EventKind,
+    /// Metric value that caused the event
+    pub value: f64,
+    /// Start of the hour the event refers to, as returned by api
+    #[serde(rename = "fromDateTime")]
+    pub from_date_time: Option<String>,
+}
+
+impl Event {
+    /// # Returns human readable description of the event
+    pub fn message(&self) -> String {
+        let what = match self.kind {
+            EventKind::Fired => "fired",
+            EventKind::Resolved => "resolved",
+            EventKind::Forecast => "forecast",
+        };
+        format!(
+            "Airly alert {} {} for installation {}: value {} at {}",
+            self.rule,
+            what,
+            self.installation,
+            self.value,
+            self.from_date_time.as_deref().unwrap_or("unknown time")
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct State {
+    firing: bool,
+    forecast_sent: bool,
+}
+
+/// Evaluates rules against measurements of installations and remembers which alerts are firing
+#[derive(Debug, Clone)]
+pub struct AlertEngine {
+    rules: Vec<Rule>,
+    states: HashMap<(String, u32), State>,
+}
+
+impl AlertEngine {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self {
+            rules,
+            states: HashMap::new(),
+        }
+    }
+
+    /// Evaluates all rules against fresh measurements of installation
+    ///
+    /// # Arguments:
+    /// * installation - id of the installation measurements come from
+    /// * measurements - history, current and forecast values
+    ///
+    /// # Returns events of alerts that changed state, empty if nothing changed
+    ///
+    pub fn evaluate(&mut self, installation: u32, measurements: &types::Measurements) -> Vec<Event> {
+        let observed: Vec<&types::AveragedValues> = measurements
+            .history
+            .iter()
+            .chain(measurements.current.iter())
+            .collect();
+        let mut events = Vec::new();
+        for rule in &self.rules {
+            let state = self
+                .states
+                .entry((rule.name.clone(), installation))
+                .or_default();
+            let event = |kind, values: &types::AveragedValues, value| Event {
+                rule: rule.name.clone(),
+                installation,
+                kind,
+                value,
+                from_date_time: values.from_date_time.clone(),
+            };
+            let latest = observed
+                .last()
+                .and_then(|values| rule.condition.metric(values).map(|m| (*values, m)));
+            let run = trailing_run(&rule.condition, &observed);
+            if state.firing {
+                if let Some((values, metric)) = latest {
+                    if rule.condition.cleared(metric, rule.hysteresis) {
+                        state.firing = false;
+                        events.push(event(EventKind::Resolved, values, metric));
+                    }
+                }
+                continue;
+            }
+            if let Some((values, metric)) = latest {
+                if run >= rule.for_hours as usize {
+                    state.firing = true;
+                    state.forecast_sent = false;
+                    events.push(event(EventKind::Fired, values, metric));
+                    continue;
+                }
+            }
+            if rule.include_forecast {
+                match forecast_crossing(rule, run, &measurements.forecast) {
+                    Some((values, metric)) if !state.forecast_sent => {
+                        state.forecast_sent = true;
+                        events.push(event(EventKind::Forecast, values, metric));
+                    }
+                    None => state.forecast_sent = false,
+                    _ => {}
+                }
+            }
+        }
+        events
+    }
+
+    /// # Returns names of rules currently firing for installation
+    pub fn firing(&self, installation: u32) -> Vec<String> {
+        self.rules
+            .iter()
+            .filter(|rule| {
+                matches!(
+                    self.states.get(&(rule.name.clone(), installation)),
+                    Some(state) if state.firing
+                )
+            })
+            .map(|rule| rule.name.clone())
+            .collect()
+    }
+}
+
+/// Counts consecutive hours at the end of series in which condition holds
+fn trailing_run(condition: &Condition, series: &[&types::AveragedValues]) -> usize {
+    series
+        .iter()
+        .rev()
+        .take_while(|values| matches!(condition.metric(values), Some(m) if condition.exceeded(m)))
+        .count()
+}
+
+/// Finds first forecast hour at which condition will have held long enough
+fn forecast_crossing<'a>(
+    rule: &Rule,
+    observed_run: usize,
+    forecast: &'a [types::AveragedValues],
+) -> Option<(&'a types::AveragedValues, f64)> {
+    let mut run = observed_run;
+    for values in forecast {
+        match rule.condition.metric(values) {
+            Some(metric) if rule.condition.exceeded(metric) => {
+                run += 1;
+                if run >= rule.for_hours as usize {
+                    return Some((values, metric));
+                }
+            }
+            _ => run = 0,
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test_alert {
+    use super::{AlertEngine, Condition, EventKind, Rule};
+    use crate::types::{AveragedValues, Index, Measurements, Value};
+
+    fn hour(h: u32, pm25: f64, level: &str) -> AveragedValues {
+        AveragedValues {
+            from_date_time: Some(format!("2019-12-09T{:02}:00:00.000Z", h)),
+            till_date_time: None,
+            values: vec![Value {
+                name: Some("PM25".to_string()),
+                value: Some(pm25),
+            }],
+            indexes: vec![Index {
+                name: Some("AIRLY_CAQI".to_string()),
+                value: None,
+                level: Some(level.to_string()),
+                description: None,
+                advice: None,
+                color: None,
+            }],
+            standards: Vec::new(),
+        }
+    }
+
+    fn measurements(pm25: &[f64], forecast: &[f64]) -> Measurements {
+        let mut observed: Vec<AveragedValues> = pm25
+            .iter()
+            .enumerate()
+            .map(|(h, v)| hour(h as u32, *v, "LOW"))
+            .collect();
+        let current = observed.pop();
+        Measurements {
+            current,
+            history: observed,
+            forecast: forecast
+                .iter()
+                .enumerate()
+                .map(|(h, v)| hour((pm25.len() + h) as u32, *v, "LOW"))
+                .collect(),
+        }
+    }
+
+    fn pm25_rule(for_hours: u32, include_forecast: bool) -> Rule {
+        Rule {
+            name: "pm25".to_string(),
+            condition: Condition::Value {
+                measurement: "PM25".to_string(),
+                above: 50.0,
+            },
+            for_hours,
+            hysteresis: 5.0,
+            include_forecast,
+        }
+    }
+
+    #[test]
+    fn test_fires_after_duration_and_resolves_with_hysteresis() {
+        let mut engine = AlertEngine::new(vec![pm25_rule(2, false)]);
+        assert!(engine.evaluate(18, &measurements(&[10.0, 60.0], &[])).is_empty());
+        let events = engine.evaluate(18, &measurements(&[10.0, 60.0, 55.0], &[]));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, EventKind::Fired);
+        assert_eq!(events[0].value, 55.0);
+        assert_eq!(engine.firing(18), vec!["pm25".to_string()]);
+        assert!(engine.evaluate(18, &measurements(&[60.0, 55.0, 48.0], &[])).is_empty());
+        let events = engine.evaluate(18, &measurements(&[55.0, 48.0, 44.0], &[]));
+        assert_eq!(events[0].kind, EventKind::Resolved);
+        assert!(engine.firing(18).is_empty());
+        assert!(engine.firing(19).is_empty());
+    }
+
+    #[test]
+    fn test_forecast_warns_once() {
+        let mut engine = AlertEngine::new(vec![pm25_rule(2, true)]);
+        let events = engine.evaluate(18, &measurements(&[10.0, 20.0], &[30.0, 70.0, 80.0]));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, EventKind::Forecast);
+        assert_eq!(events[0].from_date_time.as_deref(), Some("2019-12-09T04:00:00.000Z"));
+        assert!(engine.evaluate(18, &measurements(&[10.0, 20.0], &[30.0, 70.0, 80.0])).is_empty());
+    }
+
+    #[test]
+    fn test_index_level_rule_from_json() {
+        let rule: Rule = serde_json::from_str(
+            r#"{"name": "caqi", "condition": {"type": "indexLevel", "index": "AIRLY_CAQI", "atLeast": "HIGH"}}"#,
+        )
+        .unwrap();
+        let mut engine = AlertEngine::new(vec![rule]);
+        let mut high = measurements(&[10.0], &[]);
+        high.current = Some(hour(1, 10.0, "VERY_HIGH"));
+        assert_eq!(engine.evaluate(18, &high)[0].value, 4.0);
+        let low = measurements(&[10.0], &[]);
+        assert_eq!(engine.evaluate(18, &low)[0].kind, EventKind::Resolved);
+    }
+}
@@ -0,0 +1,254 @@
+use super::Event;
+use crate::transport::{DefaultTransport, HttpRequest, Transport};
+use serde_json::json;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Delivers alert events
+pub trait Notifier {
+    fn notify(&self, event: &Event) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// Posts event serialized to json to given url
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier<T: Transport = DefaultTransport> {
+    url: String,
+    transport: T,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: &str) -> Self {
+        WebhookNotifier::with_transport(url, DefaultTransport::default())
+    }
+}
+
+impl<T: Transport> WebhookNotifier<T> {
+    pub fn with_transport(url: &str, transport: T) -> Self {
+        Self {
+            url: url.to_string(),
+            transport,
+        }
+    }
+}
+
+impl<T: Transport> Notifier for WebhookNotifier<T> {
+    fn notify(&self, event: &Event) -> Result<(), Box<dyn std::error::Error>> {
+        post_json(&self.transport, &self.url, &serde_json::to_value(event)?)
+    }
+}
+
+/// Posts event message as Slack compatible incoming webhook payload
+#[derive(Debug, Clone)]
+pub struct SlackNotifier<T: Transport = DefaultTransport> {
+    url: String,
+    transport: T,
+}
+
+impl SlackNotifier {
+    pub fn new(url: &str) -> Self {
+        SlackNotifier::with_transport(url, DefaultTransport::default())
+    }
+}
+
+impl<T: Transport> SlackNotifier<T> {
+    pub fn with_transport(url: &str, transport: T) -> Self {
+        Self {
+            url: url.to_string(),
+            transport,
+        }
+    }
+}
+
+impl<T: Transport> Notifier for SlackNotifier<T> {
+    fn notify(&self, event: &Event) -> Result<(), Box<dyn std::error::Error>> {
+        post_json(&self.transport, &self.url, &json!({ "text": event.message() }))
+    }
+}
+
+fn post_json<T: Transport>(
+    transport: &T,
+    url: &str,
+    body: &serde_json::Value,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let res = transport.send(HttpRequest::post_json(url, body))?;
+    if !res.is_success() {
+        return Err(format!("Webhook {} responded with {}", url, res.status).into());
+    }
+    Ok(())
+}
+
+/// Sends event message by email through SMTP relay accepting unauthenticated plain text connections
+#[derive(Debug, Clone)]
+pub struct SmtpNotifier {
+    addr: String,
+    from: String,
+    to: Vec<String>,
+}
+
+impl SmtpNotifier {
+    /// Constructs SmtpNotifier
+    ///
+    /// # Arguments:
+    /// * addr - relay address, e.g. localhost:25
+    /// * from - sender address
+    /// * to - recipient addresses
+    ///
+    pub fn new(addr: &str, from: &str, to: Vec<String>) -> Self {
+        Self {
+            addr: addr.to_string(),
+            from: from.to_string(),
+            to,
+        }
+    }
+}
+
+impl Notifier for SmtpNotifier {
+    fn notify(&self, event: &Event) -> Result<(), Box<dyn std::error::Error>> {
+        let stream = TcpStream::connect(&self.addr)?;
+        stream.set_read_timeout(Some(SMTP_TIMEOUT))?;
+        let mut session = SmtpSession {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        };
+        session.expect(220)?;
+        session.command("HELO airly-rs", 250)?;
+        session.command(&format!("MAIL FROM:<{}>", self.from), 250)?;
+        for recipient in &self.to {
+            session.command(&format!("RCPT TO:<{}>", recipient), 250)?;
+        }
+        session.command("DATA", 354)?;
+        let message = event.message();
+        let mut data = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n",
+            self.from,
+            self.to.join(", "),
+            message
+        );
+        for line in message.lines() {
+            if line.starts_with('.') {
+                data.push('.');
+            }
+            data.push_str(line);
+            data.push_str("\r\n");
+        }
+        data.push('.');
+        session.command(&data, 250)?;
+        session.command("QUIT", 221)
+    }
+}
+
+struct SmtpSession {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl SmtpSession {
+    fn command(&mut self, line: &str, code: u16) -> Result<(), Box<dyn std::error::Error>> {
+        self.writer.write_all(format!("{}\r\n", line).as_bytes())?;
+        self.expect(code)
+    }
+
+    fn expect(&mut self, code: u16) -> Result<(), Box<dyn std::error::Error>> {
+        loop {
+            let mut reply = String::new();
+            if self.reader.read_line(&mut reply)? == 0 {
+                return Err(Box::new(Error::new(ErrorKind::UnexpectedEof, "SMTP server closed connection")));
+            }
+            if !reply.starts_with(&code.to_string()) {
+                return Err(format!("Unexpected SMTP reply, expected {}: {}", code, reply.trim()).into());
+            }
+            // multiline replies continue with dash after the code
+            if reply.as_bytes().get(3) != Some(&b'-') {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_notify {
+    use super::{Notifier, SlackNotifier, SmtpNotifier, WebhookNotifier};
+    use crate::alert::{Event, EventKind};
+    use crate::stub::StubServer;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn event() -> Event {
+        Event {
+            rule: "pm25".to_string(),
+            installation: 18,
+            kind: EventKind::Fired,
+            value: 55.0,
+            from_date_time: Some("2019-12-09T10:00:00.000Z".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_webhook_and_slack() {
+        let server = StubServer::start(|_| (200, "ok".to_string()));
+        let root = server.base_url().replace("/v2", "");
+        WebhookNotifier::new(&format!("{}/hook", root)).notify(&event()).unwrap();
+        SlackNotifier::new(&format!("{}/slack", root)).notify(&event()).unwrap();
+        let bodies = server.bodies();
+        let hook: serde_json::Value = serde_json::from_str(&bodies[0]).unwrap();
+        assert_eq!(hook["kind"], "Fired");
+        assert_eq!(hook["installation"], 18);
+        let slack: serde_json::Value = serde_json::from_str(&bodies[1]).unwrap();
+        assert_eq!(slack["text"], event().message());
+    }
+
+    #[test]
+    fn test_webhook_error_status() {
+        let server = StubServer::start(|_| (500, String::new()));
+        assert!(WebhookNotifier::new(&server.base_url()).notify(&event()).is_err());
+    }
+
+    #[test]
+    fn test_smtp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let relay = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            stream.write_all(b"220-relay.local ESMTP\r\n220 ready\r\n").unwrap();
+            let mut transcript = Vec::new();
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end().to_string();
+                let reply: &[u8] = if in_data {
+                    if line == "." {
+                        in_data = false;
+                        b"250 queued\r\n"
+                    } else {
+                        b""
+                    }
+                } else if line == "DATA" {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                } else if line == "QUIT" {
+                    b"221 bye\r\n"
+                } else {
+                    b"250 ok\r\n"
+                };
+                stream.write_all(reply).unwrap();
+                transcript.push(line);
+            }
+            transcript
+        });
+        SmtpNotifier::new(&addr, "alerts@example.com", vec!["ops@example.com".to_string()])
+            .notify(&event())
+            .unwrap();
+        let transcript = relay.join().unwrap();
+        assert!(transcript.contains(&"RCPT TO:<ops@example.com>".to_string()));
+        assert!(transcript.contains(&format!("Subject: {}", event().message())));
+        assert_eq!(transcript.last().unwrap(), "QUIT");
+    }
+}
@@ -0,0 +1,495 @@
+use crate::store::{Source, Store};
+use crate::types::{AveragedValues, MeasurementKind};
+use chrono::{DateTime, Datelike, NaiveDate};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+fn default_min_hours_per_day() -> usize {
+    18
+}
+
+fn default_min_annual_coverage() -> f64 {
+    0.9
+}
+
+/// Period limit value is averaged over
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Averaging {
+    /// Every hourly value is compared with limit
+    #[serde(rename = "hourly")]
+    Hourly,
+    /// Means of calendar days, UTC, are compared with limit
+    #[serde(rename = "daily")]
+    Daily,
+    /// Mean of calendar year is compared with limit
+    #[serde(rename = "annual")]
+    Annual,
+}
+
+impl Averaging {
+    pub fn name(self) -> &'static str {
+        match self {
+            Averaging::Hourly => "hourly",
+            Averaging::Daily => "daily",
+            Averaging::Annual => "annual",
+        }
+    }
+}
+
+/// Single limit of air quality standard
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Limit {
+    /// Name of standard, e.g. EU or WHO 2021
+    pub standard: String,
+    /// Airly measurement name, e.g. PM10
+    pub pollutant: String,
+    pub averaging: Averaging,
+    /// Limit value in µg/m³
+    pub limit: f64,
+    /// Hours or days a year limit may be exceeded, ignored for annual limits
+    #[serde(rename = "allowedExceedances", default)]
+    pub allowed_exceedances: u32,
+}
+
+impl Limit {
+    fn new(standard: &str, pollutant: MeasurementKind, averaging: Averaging, limit: f64, allowed: u32) -> Self {
+        Self {
+            standard: standard.to_string(),
+            pollutant: pollutant.name().to_string(),
+            averaging,
+            limit,
+            allowed_exceedances: allowed,
+        }
+    }
+}
+
+/// Limits reports are checked against, usually read from json file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StandardsTable {
+    pub limits: Vec<Limit>,
+    /// Hours of data day needs to count in daily means, 18 (75%) by default
+    #[serde(rename = "minHoursPerDay", default = "default_min_hours_per_day")]
+    pub min_hours_per_day: usize,
+    /// Part of hours of the year with data a year needs to be assessed against any limit, 0.9 by default as
+    /// EU requires, with less data only more exceedances than allowed are conclusive
+    #[serde(rename = "minAnnualCoverage", default = "default_min_annual_coverage")]
+    pub min_annual_coverage: f64,
+}
+
+impl Default for StandardsTable {
+    /// EU and WHO 2021 limits
+    fn default() -> Self {
+        let mut limits = StandardsTable::eu().limits;
+        limits.extend(StandardsTable::who_2021().limits);
+        StandardsTable {
+            limits,
+            min_hours_per_day: default_min_hours_per_day(),
+            min_annual_coverage: default_min_annual_coverage(),
+        }
+    }
+}
+
+impl StandardsTable {
+    /// Limit values of EU directive 2008/50/EC
+    pub fn eu() -> Self {
+        use self::Averaging::*;
+        use crate::types::MeasurementKind::*;
+        let limits = vec![
+            Limit::new("EU", Pm10, Daily, 50.0, 35),
+            Limit::new("EU", Pm10, Annual, 40.0, 0),
+            Limit::new("EU", Pm25, Annual, 25.0, 0),
+            Limit::new("EU", No2, Hourly, 200.0, 18),
+            Limit::new("EU", No2, Annual, 40.0, 0),
+            Limit::new("EU", So2, Hourly, 350.0, 24),
+            Limit::new("EU", So2, Daily, 125.0, 3),
+        ];
+        StandardsTable {
+            limits,
+            min_hours_per_day: default_min_hours_per_day(),
+            min_annual_coverage: default_min_annual_coverage(),
+        }
+    }
+
+    /// WHO 2021 air quality guideline levels, daily levels are 99th percentile so 3 days a year may exceed them
+    pub fn who_2021() -> Self {
+        use self::Averaging::*;
+        use crate::types::MeasurementKind::*;
+        let limits = vec![
+            Limit::new("WHO 2021", Pm25, Daily, 15.0, 3),
+            Limit::new("WHO 2021", Pm25, Annual, 5.0, 0),
+            Limit::new("WHO 2021", Pm10, Daily, 45.0, 3),
+            Limit::new("WHO 2021", Pm10, Annual, 15.0, 0),
+            Limit::new("WHO 2021", No2, Daily, 25.0, 3),
+            Limit::new("WHO 2021", No2, Annual, 10.0, 0),
+            Limit::new("WHO 2021", So2, Daily, 40.0, 3),
+        ];
+        StandardsTable {
+            limits,
+            min_hours_per_day: default_min_hours_per_day(),
+            min_annual_coverage: default_min_annual_coverage(),
+        }
+    }
+
+    /// Reads standards table from json file
+    ///
+    /// # Returns StandardsTable or Error if file is missing or invalid
+    ///
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = fs::read_to_string(path)?;
+        let table: StandardsTable = serde_json::from_str(&text)?;
+        Ok(table)
+    }
+}
+
+/// Mean of single calendar day
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DailyMean {
+    /// Midnight starting the day, UTC unix timestamp in seconds
+    pub day: i64,
+    pub mean: f64,
+    pub hours: usize,
+}
+
+/// Averages hourly values of pollutant by calendar day, UTC
+///
+/// # Arguments:
+/// * series - hourly values, e.g. Store::series
+/// * pollutant - measurement averaged
+/// * min_hours - hours of data day needs, days with less are skipped
+///
+/// # Returns means sorted by day
+///
+pub fn daily_means(series: &[AveragedValues], pollutant: &MeasurementKind, min_hours: usize) -> Vec<DailyMean> {
+    daily(&hourly(series, pollutant), min_hours)
+}
+
+fn hourly(series: &[AveragedValues], pollutant: &MeasurementKind) -> Vec<(i64, f64)> {
+    series
+        .iter()
+        .filter_map(|values| Some((values.from_timestamp()?, values.value(pollutant)?)))
+        .collect()
+}
+
+fn daily(hourly: &[(i64, f64)], min_hours: usize) -> Vec<DailyMean> {
+    let mut days: BTreeMap<i64, (f64, usize)> = BTreeMap::new();
+    for (at, value) in hourly {
+        let day = days.entry(at.div_euclid(SECONDS_PER_DAY) * SECONDS_PER_DAY).or_default();
+        day.0 += value;
+        day.1 += 1;
+    }
+    days.into_iter()
+        .filter(|(_, (_, hours))| *hours >= min_hours.max(1))
+        .map(|(day, (sum, hours))| DailyMean {
+            day,
+            mean: sum / hours as f64,
+            hours,
+        })
+        .collect()
+}
+
+fn year(at: i64) -> Option<i32> {
+    DateTime::from_timestamp(at, 0).map(|d| d.year())
+}
+
+fn hours_in_year(year: i32) -> usize {
+    match NaiveDate::from_ymd_opt(year, 2, 29) {
+        Some(_) => 366 * 24,
+        None => 365 * 24,
+    }
+}
+
+/// Compliance with single limit in single year
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    pub limit: Limit,
+    pub year: i32,
+    /// Hours with pollutant measured
+    pub hours: usize,
+    /// Part of hours of the year with pollutant measured
+    pub coverage: f64,
+    /// Days with enough hours to count in daily means
+    pub valid_days: usize,
+    /// Mean of all hourly values of the year
+    pub mean: f64,
+    /// The highest hourly value or daily mean, for hourly or daily limits
+    pub max: Option<f64>,
+    /// Hours or days above limit, None for annual limits
+    pub exceedances: Option<u32>,
+    /// None if limit can not be assessed, coverage is below StandardsTable.min_annual_coverage and
+    /// exceedances found so far are allowed
+    pub compliant: Option<bool>,
+}
+
+/// Compliance of single installation or point with standards table
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub source: Source,
+    /// Rows by year, then in order of standards table, limits of pollutants not measured are left out
+    pub rows: Vec<Row>,
+}
+
+impl Report {
+    /// Checks hourly series against every limit of table
+    ///
+    /// # Arguments:
+    /// * source - installation or point series comes from
+    /// * series - hourly values, e.g. Store::series
+    /// * table - limits checked
+    ///
+    pub fn evaluate(source: Source, series: &[AveragedValues], table: &StandardsTable) -> Self {
+        let mut rows = Vec::new();
+        for limit in table.limits.iter() {
+            let mut by_year: BTreeMap<i32, Vec<(i64, f64)>> = BTreeMap::new();
+            for (at, value) in hourly(series, &MeasurementKind::from_name(&limit.pollutant)) {
+                if let Some(year) = year(at) {
+                    by_year.entry(year).or_default().push((at, value));
+                }
+            }
+            for (year, hourly) in by_year {
+                let days = daily(&hourly, table.min_hours_per_day);
+                let mean = hourly.iter().map(|(_, v)| v).sum::<f64>() / hourly.len() as f64;
+                let coverage = hourly.len() as f64 / hours_in_year(year) as f64;
+                let assessable = coverage >= table.min_annual_coverage;
+                let values: Vec<f64> = match limit.averaging {
+                    Averaging::Hourly => hourly.iter().map(|(_, v)| *v).collect(),
+                    Averaging::Daily => days.iter().map(|d| d.mean).collect(),
+                    Averaging::Annual => Vec::new(),
+                };
+                let (max, exceedances, compliant) = match limit.averaging {
+                    Averaging::Annual => (None, None, Some(mean <= limit.limit).filter(|_| assessable)),
+                    _ => {
+                        let exceedances = values.iter().filter(|v| **v > limit.limit).count() as u32;
+                        let max = values.iter().cloned().fold(None, |max: Option<f64>, v| {
+                            Some(max.map_or(v, |max| max.max(v)))
+                        });
+                        let compliant = match exceedances > limit.allowed_exceedances {
+                            true => Some(false),
+                            false => Some(true).filter(|_| assessable),
+                        };
+                        (max, Some(exceedances), compliant)
+                    }
+                };
+                rows.push(Row {
+                    limit: limit.clone(),
+                    year,
+                    hours: hourly.len(),
+                    coverage,
+                    valid_days: days.len(),
+                    mean,
+                    max,
+                    exceedances,
+                    compliant,
+                });
+            }
+        }
+        rows.sort_by_key(|row| row.year);
+        Self { source, rows }
+    }
+
+    /// Checks archived series of source against every limit of table
+    ///
+    /// # Returns report, Error if archive could not be read
+    ///
+    pub fn from_store<S: Store + ?Sized>(
+        store: &S,
+        source: &Source,
+        table: &StandardsTable,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Report::evaluate(source.clone(), &store.series(source)?, table))
+    }
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map_or(String::new(), |v| v.to_string())
+}
+
+/// Writes reports as CSV, one line per installation, year and limit
+pub fn to_csv(reports: &[Report]) -> String {
+    let mut csv = String::from(
+        "source,year,standard,pollutant,averaging,limit,allowed_exceedances,hours,coverage,valid_days,mean,max,exceedances,compliant\n",
+    );
+    for report in reports {
+        for row in report.rows.iter() {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{},{:.3},{},{:.1},{},{},{}",
+                report.source.key(),
+                row.year,
+                row.limit.standard,
+                row.limit.pollutant,
+                row.limit.averaging.name(),
+                row.limit.limit,
+                row.limit.allowed_exceedances,
+                row.hours,
+                row.coverage,
+                row.valid_days,
+                row.mean,
+                optional(row.max.map(|max| format!("{:.1}", max))),
+                optional(row.exceedances),
+                optional(row.compliant)
+            );
+        }
+    }
+    csv
+}
+
+/// Writes reports as Markdown, one table per installation
+pub fn to_markdown(reports: &[Report]) -> String {
+    let mut markdown = String::new();
+    for report in reports {
+        let _ = writeln!(markdown, "### {}\n", report.source.key());
+        markdown.push_str("| Year | Standard | Pollutant | Averaging | Limit | Mean | Max | Exceedances | Compliant |\n");
+        markdown.push_str("|---|---|---|---|---|---|---|---|---|\n");
+        for row in report.rows.iter() {
+            let exceedances = row
+                .exceedances
+                .map(|count| format!("{} / {}", count, row.limit.allowed_exceedances));
+            let _ = writeln!(
+                markdown,
+                "| {} | {} | {} | {} | {} | {:.1} | {} | {} | {} |",
+                row.year,
+                row.limit.standard,
+                row.limit.pollutant,
+                row.limit.averaging.name(),
+                row.limit.limit,
+                row.mean,
+                optional(row.max.map(|max| format!("{:.1}", max))),
+                optional(exceedances),
+                match row.compliant {
+                    Some(true) => "yes".to_string(),
+                    Some(false) => "**no**".to_string(),
+                    None => format!("not assessable, {:.0}% of hours", row.coverage * 100.0),
+                }
+            );
+        }
+        markdown.push('\n');
+    }
+    markdown
+}
+
+#[cfg(test)]
+mod test_compliance {
+    use super::{daily_means, to_csv, to_markdown, Averaging, Report, StandardsTable};
+    use crate::store::{MemoryStore, Record, Source, Store};
+    use crate::types::{AveragedValues, MeasurementKind, Measurements, Value};
+
+    /// 2019-01-01T00:00:00Z
+    const NEW_YEAR: i64 = 1_546_300_800;
+
+    fn hour(at: i64, pm10: f64) -> AveragedValues {
+        AveragedValues {
+            from_date_time: Some(chrono::DateTime::from_timestamp(at, 0).unwrap().to_rfc3339()),
+            till_date_time: None,
+            values: vec![Value {
+                name: Some("PM10".to_string()),
+                value: Some(pm10),
+            }],
+            indexes: Vec::new(),
+            standards: Vec::new(),
+        }
+    }
+
+    /// 40 smoggy days, 10 clean ones and 10 hours of the next day
+    fn series() -> Vec<AveragedValues> {
+        (0..50 * 24 + 10)
+            .map(|h| hour(NEW_YEAR + h * 3600, if h < 40 * 24 { 60.0 } else if h < 50 * 24 { 20.0 } else { 100.0 }))
+            .collect()
+    }
+
+    #[test]
+    fn test_daily_means_skip_incomplete_days() {
+        let days = daily_means(&series(), &MeasurementKind::Pm10, 18);
+        assert_eq!(days.len(), 50);
+        assert_eq!(days[0].day, NEW_YEAR);
+        assert_eq!(days[39].mean, 60.0);
+        assert_eq!(days[49].hours, 24);
+        assert_eq!(daily_means(&series(), &MeasurementKind::Pm10, 10).len(), 51);
+    }
+
+    #[test]
+    fn test_short_series_not_assessable() {
+        // a week of clean air, one smoggy day
+        let week: Vec<AveragedValues> = (0..7 * 24)
+            .map(|h| hour(NEW_YEAR + h * 3600, if h < 24 { 80.0 } else { 20.0 }))
+            .collect();
+        let report = Report::evaluate(Source::Installation(18), &week, &StandardsTable::default());
+        let compliant = |standard: &str, averaging: Averaging| {
+            let row = report
+                .rows
+                .iter()
+                .find(|row| row.limit.standard == standard && row.limit.averaging == averaging)
+                .unwrap();
+            row.compliant
+        };
+        assert_eq!(compliant("EU", Averaging::Daily), None);
+        assert_eq!(compliant("EU", Averaging::Annual), None);
+        assert_eq!(compliant("WHO 2021", Averaging::Daily), None);
+
+        let mut table = StandardsTable::default();
+        table
+            .limits
+            .retain(|limit| limit.standard == "WHO 2021" && limit.averaging == Averaging::Daily && limit.pollutant == "PM10");
+        table.limits[0].allowed_exceedances = 0;
+        let report = Report::evaluate(Source::Installation(18), &week, &table);
+        assert_eq!(report.rows[0].exceedances, Some(1));
+        assert_eq!(report.rows[0].compliant, Some(false));
+    }
+
+    #[test]
+    fn test_eu_and_who_report() {
+        let mut store = MemoryStore::new();
+        store
+            .save(&Record {
+                source: Source::Installation(18),
+                fetched_at: NEW_YEAR,
+                measurements: Measurements {
+                    current: None,
+                    history: series(),
+                    forecast: Vec::new(),
+                },
+            })
+            .unwrap();
+        let source = Source::Installation(18);
+        let report = Report::from_store(&store, &source, &StandardsTable::default()).unwrap();
+        // only PM10 limits, other pollutants are not measured
+        assert_eq!(report.rows.len(), 4);
+        let eu_daily = &report.rows[0];
+        assert_eq!((eu_daily.limit.standard.as_str(), eu_daily.limit.averaging), ("EU", Averaging::Daily));
+        assert_eq!(eu_daily.year, 2019);
+        assert_eq!(eu_daily.valid_days, 50);
+        assert_eq!(eu_daily.exceedances, Some(40));
+        assert_eq!(eu_daily.max, Some(60.0));
+        assert_eq!(eu_daily.compliant, Some(false));
+        let eu_annual = &report.rows[1];
+        assert_eq!(eu_annual.exceedances, None);
+        assert!(eu_annual.mean > 40.0 && eu_annual.coverage < 0.9);
+        assert_eq!(eu_annual.compliant, None);
+        let mut partial = StandardsTable::eu();
+        partial.min_annual_coverage = 0.1;
+        let partial = Report::from_store(&store, &source, &partial).unwrap();
+        assert_eq!(partial.rows[1].compliant, Some(false));
+        assert_eq!(partial.rows[0].compliant, Some(false));
+
+        let table: StandardsTable = serde_json::from_str(
+            r#"{"limits": [{"standard": "local", "pollutant": "PM10", "averaging": "daily", "limit": 70, "allowedExceedances": 1}]}"#,
+        )
+        .unwrap();
+        assert_eq!(table.min_hours_per_day, 18);
+        assert_eq!(table.min_annual_coverage, 0.9);
+        let local = Report::from_store(&store, &source, &table).unwrap();
+        assert_eq!(local.rows[0].exceedances, Some(0));
+        assert_eq!(local.rows[0].compliant, None);
+
+        let csv = to_csv(&[report.clone(), local]);
+        assert_eq!(csv.lines().count(), 6);
+        assert!(csv.contains("\ninstallation-18,2019,EU,PM10,daily,50,35,1210,0.138,50,52.4,60.0,40,false\n"));
+        assert!(csv.contains("\ninstallation-18,2019,EU,PM10,annual,40,0,1210,0.138,50,52.4,,,\n"));
+        let markdown = to_markdown(&[report]);
+        assert!(markdown.starts_with("### installation-18\n"));
+        assert!(markdown.contains("| 2019 | EU | PM10 | daily | 50 | 52.4 | 60.0 | 40 / 35 | **no** |"));
+        assert!(markdown.contains("| 2019 | EU | PM10 | annual | 40 | 52.4 |  |  | not assessable, 14% of hours |"));
+    }
+}
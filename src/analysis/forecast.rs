@@ -0,0 +1,298 @@
+use super::Metric;
+use crate::store::{Record, Source, Store};
+use crate::types::AveragedValues;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+const SECONDS_PER_HOUR: i64 = 3600;
+
+/// Errors of forecasts against later observations, error is forecast minus observation
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ErrorStats {
+    pub count: usize,
+    sum_abs: f64,
+    sum_squared: f64,
+    sum: f64,
+    /// Compared index levels, zero for measurements
+    pub levels: usize,
+    /// Index levels forecast right
+    pub level_hits: usize,
+}
+
+impl ErrorStats {
+    fn add(&mut self, forecast: f64, observed: f64) {
+        let error = forecast - observed;
+        self.count += 1;
+        self.sum_abs += error.abs();
+        self.sum_squared += error * error;
+        self.sum += error;
+    }
+
+    fn add_level(&mut self, forecast: &str, observed: &str) {
+        self.levels += 1;
+        if forecast == observed {
+            self.level_hits += 1;
+        }
+    }
+
+    /// # Returns mean absolute error, None without compared values
+    pub fn mae(&self) -> Option<f64> {
+        self.mean(self.sum_abs)
+    }
+
+    /// # Returns root mean squared error, None without compared values
+    pub fn rmse(&self) -> Option<f64> {
+        self.mean(self.sum_squared).map(f64::sqrt)
+    }
+
+    /// # Returns mean error, positive when forecasts are too high, None without compared values
+    pub fn bias(&self) -> Option<f64> {
+        self.mean(self.sum)
+    }
+
+    /// # Returns share of index levels forecast right, None without compared levels
+    pub fn hit_rate(&self) -> Option<f64> {
+        if self.levels == 0 {
+            return None;
+        }
+        Some(self.level_hits as f64 / self.levels as f64)
+    }
+
+    fn mean(&self, sum: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        Some(sum / self.count as f64)
+    }
+}
+
+/// Errors of single metric at single horizon
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    pub metric: Metric,
+    /// Hours between the hour forecast was issued in and the hour it predicts, 1 for the next hour
+    pub horizon_hours: i64,
+    pub stats: ErrorStats,
+}
+
+/// Forecast accuracy of single installation or point
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub source: Source,
+    /// Number of forecasts issued
+    pub forecasts: usize,
+    /// Number of forecast hours with observation to compare with
+    pub compared_hours: usize,
+    /// Rows sorted by metric name and horizon
+    pub rows: Vec<Row>,
+}
+
+impl Report {
+    /// Evaluates forecasts archived with records against observed series
+    ///
+    /// # Arguments:
+    /// * source - installation or point records come from
+    /// * records - records in order they were fetched, their forecasts issued at fetched_at
+    /// * observed - hourly series, e.g. Store::series
+    ///
+    pub fn evaluate(source: Source, records: &[Record], observed: &[AveragedValues]) -> Self {
+        let observed: HashMap<i64, &AveragedValues> = observed
+            .iter()
+            .filter_map(|values| Some((values.from_timestamp()?, values)))
+            .collect();
+        // the latest forecast of an hour issued within the same hour wins
+        let mut forecasts = BTreeMap::new();
+        let mut issued = 0;
+        for record in records.iter().filter(|r| !r.measurements.forecast.is_empty()) {
+            issued += 1;
+            let issue_hour = record.fetched_at.div_euclid(SECONDS_PER_HOUR) * SECONDS_PER_HOUR;
+            for values in record.measurements.forecast.iter() {
+                if let Some(from) = values.from_timestamp() {
+                    let horizon = (from - issue_hour) / SECONDS_PER_HOUR;
+                    if horizon > 0 {
+                        forecasts.insert((from, horizon), values);
+                    }
+                }
+            }
+        }
+        let mut stats: HashMap<(Metric, i64), ErrorStats> = HashMap::new();
+        let mut compared_hours = 0;
+        for ((from, horizon), forecast) in forecasts {
+            let observed = match observed.get(&from) {
+                Some(observed) => observed,
+                None => continue,
+            };
+            compared_hours += 1;
+            for value in forecast.values.iter() {
+                if let (Some(kind), Some(predicted)) = (value.kind(), value.value) {
+                    if let Some(actual) = observed.value(&kind) {
+                        stats
+                            .entry((Metric::Measurement(kind), horizon))
+                            .or_default()
+                            .add(predicted, actual);
+                    }
+                }
+            }
+            for index in forecast.indexes.iter() {
+                let name = match &index.name {
+                    Some(name) => name,
+                    None => continue,
+                };
+                let actual = match observed.indexes.iter().find(|i| i.name.as_ref() == Some(name)) {
+                    Some(actual) => actual,
+                    None => continue,
+                };
+                let entry = stats.entry((Metric::Index(name.clone()), horizon)).or_default();
+                if let (Some(predicted), Some(actual)) = (index.value, actual.value) {
+                    entry.add(predicted, actual);
+                }
+                if let (Some(predicted), Some(actual)) = (&index.level, &actual.level) {
+                    entry.add_level(predicted, actual);
+                }
+            }
+        }
+        let mut rows: Vec<Row> = stats
+            .into_iter()
+            .map(|((metric, horizon_hours), stats)| Row {
+                metric,
+                horizon_hours,
+                stats,
+            })
+            .collect();
+        rows.sort_by(|a, b| (a.metric.name(), a.horizon_hours).cmp(&(b.metric.name(), b.horizon_hours)));
+        Self {
+            source,
+            forecasts: issued,
+            compared_hours,
+            rows,
+        }
+    }
+
+    /// Evaluates forecasts archived for source, e.g. by airly_collector, against its archived history
+    ///
+    /// # Returns report, Error if archive could not be read
+    ///
+    pub fn from_store<S: Store + ?Sized>(store: &S, source: &Source) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Report::evaluate(source.clone(), &store.load(source)?, &store.series(source)?))
+    }
+
+    /// # Returns row of given metric and horizon, None if nothing was compared
+    pub fn row(&self, metric: &Metric, horizon_hours: i64) -> Option<&Row> {
+        self.rows
+            .iter()
+            .find(|row| row.metric == *metric && row.horizon_hours == horizon_hours)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{}: {} forecasts, {} hours compared",
+            self.source.key(),
+            self.forecasts,
+            self.compared_hours
+        )?;
+        writeln!(
+            f,
+            "{:<16} {:>7} {:>6} {:>8} {:>8} {:>8} {:>8}",
+            "metric", "horizon", "n", "mae", "rmse", "bias", "hit rate"
+        )?;
+        let cell = |value: Option<f64>| value.map_or("-".to_string(), |v| format!("{:.2}", v));
+        for row in self.rows.iter() {
+            writeln!(
+                f,
+                "{:<16} {:>6}h {:>6} {:>8} {:>8} {:>8} {:>8}",
+                row.metric.name(),
+                row.horizon_hours,
+                row.stats.count,
+                cell(row.stats.mae()),
+                cell(row.stats.rmse()),
+                cell(row.stats.bias()),
+                cell(row.stats.hit_rate())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_forecast {
+    use super::{Metric, Report};
+    use crate::store::{MemoryStore, Record, Source, Store};
+    use crate::types::{AveragedValues, Index, MeasurementKind, Measurements, Value};
+
+    const HOUR: i64 = 3600;
+    /// 2019-12-10T10:00:00Z
+    const TEN: i64 = 1_575_972_000;
+
+    fn hour(from: i64, pm25: f64, caqi: f64, level: &str) -> AveragedValues {
+        AveragedValues {
+            from_date_time: Some(chrono::DateTime::from_timestamp(from, 0).unwrap().to_rfc3339()),
+            till_date_time: None,
+            values: vec![Value {
+                name: Some("PM25".to_string()),
+                value: Some(pm25),
+            }],
+            indexes: vec![Index {
+                name: Some("AIRLY_CAQI".to_string()),
+                value: Some(caqi),
+                level: Some(level.to_string()),
+                description: None,
+                advice: None,
+                color: None,
+            }],
+            standards: Vec::new(),
+        }
+    }
+
+    fn record(fetched_at: i64, history: Vec<AveragedValues>, forecast: Vec<AveragedValues>) -> Record {
+        Record {
+            source: Source::Installation(18),
+            fetched_at,
+            measurements: Measurements {
+                current: None,
+                history,
+                forecast,
+            },
+        }
+    }
+
+    #[test]
+    fn test_errors_per_kind_and_horizon() {
+        let mut store = MemoryStore::new();
+        // issued 10:20, predicts 11:00 and 12:00
+        let first = vec![hour(TEN + HOUR, 20.0, 30.0, "LOW"), hour(TEN + 2 * HOUR, 40.0, 50.0, "MEDIUM")];
+        store.save(&record(TEN + 1200, Vec::new(), first)).unwrap();
+        // issued 11:05, predicts 12:00 again and 13:00 never observed
+        let second = vec![hour(TEN + 2 * HOUR, 34.0, 45.0, "LOW"), hour(TEN + 3 * HOUR, 10.0, 10.0, "VERY_LOW")];
+        store.save(&record(TEN + HOUR + 300, Vec::new(), second)).unwrap();
+        let observed = vec![hour(TEN + HOUR, 25.0, 38.0, "LOW"), hour(TEN + 2 * HOUR, 30.0, 45.0, "LOW")];
+        store.save(&record(TEN + 3 * HOUR, observed, Vec::new())).unwrap();
+
+        let report = Report::from_store(&store, &Source::Installation(18)).unwrap();
+        assert_eq!(report.forecasts, 2);
+        assert_eq!(report.compared_hours, 3);
+        let pm25 = Metric::Measurement(MeasurementKind::Pm25);
+        let next_hour = report.row(&pm25, 1).unwrap().stats;
+        assert_eq!(next_hour.count, 2);
+        assert!((next_hour.mae().unwrap() - 4.5).abs() < 1e-9);
+        assert!((next_hour.bias().unwrap() + 0.5).abs() < 1e-9);
+        assert!((next_hour.rmse().unwrap() - 20.5f64.sqrt()).abs() < 1e-9);
+        assert_eq!(next_hour.hit_rate(), None);
+        let two_hours = report.row(&pm25, 2).unwrap().stats;
+        assert_eq!(two_hours.count, 1);
+        assert!((two_hours.bias().unwrap() - 10.0).abs() < 1e-9);
+
+        let caqi = Metric::Index("AIRLY_CAQI".to_string());
+        assert_eq!(report.row(&caqi, 1).unwrap().stats.hit_rate(), Some(1.0));
+        assert_eq!(report.row(&caqi, 2).unwrap().stats.hit_rate(), Some(0.0));
+        assert!(report.row(&pm25, 3).is_none());
+        assert_eq!(report.rows.len(), 4);
+        assert_eq!(report.rows[0].metric, caqi);
+
+        let text = report.to_string();
+        assert!(text.starts_with("installation-18: 2 forecasts, 3 hours compared"));
+        assert!(text.contains("PM25"));
+    }
+}
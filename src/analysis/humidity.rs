@@ -0,0 +1,210 @@
+use crate::types::{AveragedValues, MeasurementKind};
+
+fn default_max_humidity() -> f64 {
+    95.0
+}
+
+/// Ratio of dry particle density to water density, ρp/ρw, as taken by Crilley et al. (2018)
+fn default_density_ratio() -> f64 {
+    1.65
+}
+
+/// Model of particle growth with relative humidity, rh below is fraction 0-1
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "model")]
+pub enum Model {
+    /// κ-Köhler growth factor of Crilley et al. (2018), corrected = pm / (1 + (κ / ρ) / (1 / rh - 1)), κ around
+    /// 0.4 for urban aerosol, ρ ratio of particle to water density, 1.65 by default
+    #[serde(rename = "kappaKohler")]
+    KappaKohler {
+        kappa: f64,
+        #[serde(rename = "densityRatio", default = "default_density_ratio")]
+        density_ratio: f64,
+    },
+    /// Empirical growth factor, corrected = pm / (1 + a · rh^b / (1 - rh)), a = 0.25 and b = 2 by Chakrabarti
+    #[serde(rename = "empirical")]
+    Empirical { a: f64, b: f64 },
+    /// Linear regression against reference, corrected = pm · pm_coefficient + RH% · humidity_coefficient + intercept
+    #[serde(rename = "linear")]
+    Linear {
+        #[serde(rename = "pmCoefficient")]
+        pm_coefficient: f64,
+        #[serde(rename = "humidityCoefficient")]
+        humidity_coefficient: f64,
+        intercept: f64,
+    },
+}
+
+/// Humidity correction of PM values, usually read from analysis config
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HumidityCorrection {
+    #[serde(flatten)]
+    pub model: Model,
+    /// Relative humidity in % growth factor models are capped at, they grow without bound at 100%
+    #[serde(rename = "maxHumidity", default = "default_max_humidity")]
+    pub max_humidity: f64,
+}
+
+impl Default for HumidityCorrection {
+    /// κ-Köhler with κ = 0.4 and density ratio 1.65
+    fn default() -> Self {
+        HumidityCorrection::new(Model::KappaKohler {
+            kappa: 0.4,
+            density_ratio: default_density_ratio(),
+        })
+    }
+}
+
+/// PM value with its humidity corrected counterpart
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorrectedValue {
+    pub kind: MeasurementKind,
+    pub original: f64,
+    /// None when humidity was not measured in the same period
+    pub corrected: Option<f64>,
+}
+
+/// Corrected PM values of single period
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorrectedPeriod {
+    /// Left bound of averaging period, unix timestamp in seconds
+    pub from: Option<i64>,
+    /// Relative humidity in % used for correction
+    pub humidity: Option<f64>,
+    /// PM1, PM25 and PM10 values measured in the period
+    pub values: Vec<CorrectedValue>,
+}
+
+impl CorrectedPeriod {
+    /// # Returns corrected value of given kind, None if not measured or humidity is missing
+    pub fn corrected(&self, kind: &MeasurementKind) -> Option<f64> {
+        self.values.iter().find(|v| v.kind == *kind).and_then(|v| v.corrected)
+    }
+}
+
+impl HumidityCorrection {
+    pub fn new(model: Model) -> Self {
+        Self {
+            model,
+            max_humidity: default_max_humidity(),
+        }
+    }
+
+    /// Sets relative humidity in % growth factor models are capped at, 95 by default
+    pub fn with_max_humidity(mut self, max_humidity: f64) -> Self {
+        self.max_humidity = max_humidity;
+        self
+    }
+
+    /// Corrects single PM value
+    ///
+    /// # Arguments:
+    /// * pm - measured PM concentration in µg/m³
+    /// * humidity - relative humidity in %
+    ///
+    /// # Returns corrected concentration, never negative
+    ///
+    pub fn apply(&self, pm: f64, humidity: f64) -> f64 {
+        let rh = humidity.clamp(0.0, self.max_humidity.clamp(0.0, 99.0)) / 100.0;
+        let corrected = match self.model {
+            Model::KappaKohler { kappa, density_ratio } => pm / (1.0 + kappa * rh / (density_ratio * (1.0 - rh))),
+            Model::Empirical { a, b } => pm / (1.0 + a * rh.powf(b) / (1.0 - rh)),
+            Model::Linear {
+                pm_coefficient,
+                humidity_coefficient,
+                intercept,
+            } => pm * pm_coefficient + humidity.max(0.0) * humidity_coefficient + intercept,
+        };
+        corrected.max(0.0)
+    }
+
+    /// Corrects PM1, PM25 and PM10 values of period with HUMIDITY of the same period
+    ///
+    /// # Returns originals paired with corrected values
+    ///
+    pub fn correct(&self, values: &AveragedValues) -> CorrectedPeriod {
+        let humidity = values.value(&MeasurementKind::Humidity);
+        let corrected = [MeasurementKind::Pm1, MeasurementKind::Pm25, MeasurementKind::Pm10]
+            .iter()
+            .filter_map(|kind| {
+                let original = values.value(kind)?;
+                Some(CorrectedValue {
+                    kind: kind.clone(),
+                    original,
+                    corrected: humidity.map(|humidity| self.apply(original, humidity)),
+                })
+            })
+            .collect();
+        CorrectedPeriod {
+            from: values.from_timestamp(),
+            humidity,
+            values: corrected,
+        }
+    }
+
+    /// Corrects every period of series, e.g. Measurements.history or Store::series
+    pub fn correct_series(&self, series: &[AveragedValues]) -> Vec<CorrectedPeriod> {
+        series.iter().map(|values| self.correct(values)).collect()
+    }
+}
+
+#[cfg(test)]
+mod test_humidity {
+    use super::{HumidityCorrection, Model};
+    use crate::cassette::{self, Cassette};
+    use crate::client::AirlyClient;
+    use crate::types::{IndexType, MeasurementKind};
+
+    #[test]
+    fn test_models() {
+        let kappa = HumidityCorrection::default();
+        assert!((kappa.apply(50.0, 50.0) - 50.0 / (1.0 + 0.4 / 1.65)).abs() < 1e-9);
+        // growth factor about 1.97 at 80%
+        assert!((kappa.apply(50.0, 80.0) - 50.0 / (1.0 + 0.32 / 0.33)).abs() < 1e-9);
+        assert_eq!(kappa.apply(50.0, 0.0), 50.0);
+        // capped at 95%
+        assert_eq!(kappa.apply(50.0, 100.0), kappa.apply(50.0, 95.0));
+        let chakrabarti = HumidityCorrection::new(Model::Empirical { a: 0.25, b: 2.0 });
+        assert!((chakrabarti.apply(40.0, 80.0) - 40.0 / 1.8).abs() < 1e-9);
+        let linear = HumidityCorrection::new(Model::Linear {
+            pm_coefficient: 0.524,
+            humidity_coefficient: -0.0862,
+            intercept: 5.75,
+        });
+        assert!((linear.apply(30.0, 60.0) - 16.298).abs() < 1e-9);
+        assert_eq!(linear.apply(0.0, 100.0), 0.0);
+
+        let config: HumidityCorrection =
+            serde_json::from_str(r#"{"model": "empirical", "a": 0.25, "b": 2, "maxHumidity": 90}"#).unwrap();
+        assert_eq!(config, chakrabarti.with_max_humidity(90.0));
+        let config: HumidityCorrection = serde_json::from_str(r#"{"model": "kappaKohler", "kappa": 0.4}"#).unwrap();
+        assert_eq!(config, kappa);
+        let dense: HumidityCorrection =
+            serde_json::from_str(r#"{"model": "kappaKohler", "kappa": 0.4, "densityRatio": 1.0}"#).unwrap();
+        assert!((dense.apply(50.0, 50.0) - 50.0 / 1.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_correct_fetched_history() {
+        let client = AirlyClient::new("0000000000000000000000000000abcd".to_string())
+            .unwrap()
+            .with_cassette(Cassette::replay(cassette::fixture("installation_measurements")).unwrap());
+        let caqi = IndexType {
+            name: Some("AIRLY_CAQI".to_string()),
+            level: None,
+        };
+        let measurements = client.get_installation_measurements_with_wind(34, caqi).unwrap();
+        let periods = HumidityCorrection::default().correct_series(&measurements.history);
+        assert_eq!(periods.len(), measurements.history.len());
+        for (period, values) in periods.iter().zip(measurements.history.iter()) {
+            assert_eq!(period.from, values.from_timestamp());
+            let original = values.value(&MeasurementKind::Pm25);
+            assert_eq!(period.values.iter().find(|v| v.kind == MeasurementKind::Pm25).map(|v| v.original), original);
+            if let (Some(original), Some(corrected)) = (original, period.corrected(&MeasurementKind::Pm25)) {
+                assert!(corrected <= original);
+                assert!(period.humidity.is_some());
+            }
+        }
+        assert!(periods.iter().any(|p| p.corrected(&MeasurementKind::Pm10).is_some()));
+    }
+}
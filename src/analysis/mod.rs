@@ -0,0 +1,51 @@
+//! Analyses of measurement series, fetched history or archive built by collector
+
+use crate::types::{AveragedValues, MeasurementKind};
+
+pub mod compliance;
+pub mod forecast;
+pub mod humidity;
+pub mod quality;
+pub mod ranking;
+pub mod rose;
+
+/// Quantity analysed, measurement value or index
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Metric {
+    Measurement(MeasurementKind),
+    /// Index with given name, e.g. AIRLY_CAQI
+    Index(String),
+}
+
+impl Metric {
+    pub fn name(&self) -> &str {
+        match self {
+            Metric::Measurement(kind) => kind.name(),
+            Metric::Index(name) => name,
+        }
+    }
+
+    /// # Returns value of this metric in given period, None if not measured
+    pub fn value(&self, values: &AveragedValues) -> Option<f64> {
+        match self {
+            Metric::Measurement(kind) => values.value(kind),
+            Metric::Index(name) => values
+                .indexes
+                .iter()
+                .find(|index| index.name.as_ref() == Some(name))
+                .and_then(|index| index.value),
+        }
+    }
+
+    /// # Returns index level in given period, None for measurements
+    pub fn level(&self, values: &AveragedValues) -> Option<String> {
+        match self {
+            Metric::Measurement(_) => None,
+            Metric::Index(name) => values
+                .indexes
+                .iter()
+                .find(|index| index.name.as_ref() == Some(name))
+                .and_then(|index| index.level.clone()),
+        }
+    }
+}
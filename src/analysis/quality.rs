@@ -0,0 +1,468 @@
+use crate::client::{AirlyClient, BatchOptions};
+use crate::store::{Source, Store};
+use crate::transport::Transport;
+use crate::types::{self, AveragedValues, MeasurementKind, Measurements};
+use std::collections::{BTreeMap, HashMap};
+
+const SECONDS_PER_HOUR: i64 = 3600;
+const ORDERING_TOLERANCE: f64 = 0.5;
+
+/// Reason value is suspicious
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Flag {
+    /// Value outside plausible range of its kind, e.g. humidity above 100%
+    OutOfRange,
+    /// The same value repeated for many consecutive hours, sensor likely stuck
+    Flatline,
+    /// Value far from values of surrounding hours
+    Spike,
+    /// PM1 above PM2.5 or PM2.5 above PM10 in the same hour
+    Ordering,
+    /// Value far from values of neighbouring installations in the same hour
+    NeighbourDeviation,
+}
+
+/// Value with at least one quality flag
+#[derive(Debug, Clone, PartialEq)]
+pub struct Flagged {
+    /// Start of hour value was averaged over, unix timestamp in seconds
+    pub at: i64,
+    pub kind: MeasurementKind,
+    pub value: f64,
+    /// Flags sorted, without duplicates
+    pub flags: Vec<Flag>,
+}
+
+/// Thresholds of quality checks
+#[derive(Debug, Clone)]
+pub struct QualityOptions {
+    ranges: HashMap<MeasurementKind, (f64, f64)>,
+    flatline_hours: usize,
+    spike_window_hours: usize,
+    spike_min_jump: f64,
+    spike_ratio: f64,
+    neighbour_min_diff: f64,
+    neighbour_ratio: f64,
+    min_neighbours: usize,
+}
+
+impl Default for QualityOptions {
+    fn default() -> Self {
+        use crate::types::MeasurementKind::*;
+        let ranges = vec![
+            (Pm1, (0.0, 1000.0)),
+            (Pm25, (0.0, 1000.0)),
+            (Pm10, (0.0, 1500.0)),
+            (Temperature, (-50.0, 60.0)),
+            (Humidity, (0.0, 100.0)),
+            (Pressure, (850.0, 1100.0)),
+            (WindSpeed, (0.0, 200.0)),
+            (WindBearing, (0.0, 360.0)),
+            (No2, (0.0, 1000.0)),
+            (O3, (0.0, 1000.0)),
+            (So2, (0.0, 1000.0)),
+            (Co, (0.0, 50_000.0)),
+        ];
+        Self {
+            ranges: ranges.into_iter().collect(),
+            flatline_hours: 6,
+            spike_window_hours: 3,
+            spike_min_jump: 25.0,
+            spike_ratio: 2.0,
+            neighbour_min_diff: 15.0,
+            neighbour_ratio: 1.0,
+            min_neighbours: 2,
+        }
+    }
+}
+
+impl QualityOptions {
+    /// Sets plausible range of kind, inclusive
+    pub fn with_range(mut self, kind: MeasurementKind, min: f64, max: f64) -> Self {
+        self.ranges.insert(kind, (min, max));
+        self
+    }
+
+    /// Sets number of consecutive hours with the same value considered flatline, 6 by default
+    pub fn with_flatline_hours(mut self, flatline_hours: usize) -> Self {
+        self.flatline_hours = flatline_hours.max(2);
+        self
+    }
+
+    /// Sets spike thresholds, value is spike when it differs from median of surrounding hours by more than
+    /// both min_jump and ratio times the median, 25 and 2 by default
+    ///
+    /// # Arguments:
+    /// * window_hours - hours taken on each side of checked value, 3 by default
+    /// * min_jump - absolute difference from median
+    /// * ratio - difference from median relative to median
+    ///
+    pub fn with_spike(mut self, window_hours: usize, min_jump: f64, ratio: f64) -> Self {
+        self.spike_window_hours = window_hours.max(1);
+        self.spike_min_jump = min_jump;
+        self.spike_ratio = ratio;
+        self
+    }
+
+    /// Sets neighbour thresholds, value deviates when it differs from median of neighbours by more than both
+    /// min_diff and ratio times the median, 15 and 1 by default
+    ///
+    /// # Arguments:
+    /// * min_diff - absolute difference from median
+    /// * ratio - difference from median relative to median
+    /// * min_neighbours - neighbours measuring the same kind needed to compare, 2 by default
+    ///
+    pub fn with_neighbours(mut self, min_diff: f64, ratio: f64, min_neighbours: usize) -> Self {
+        self.neighbour_min_diff = min_diff;
+        self.neighbour_ratio = ratio;
+        self.min_neighbours = min_neighbours.max(1);
+        self
+    }
+
+    fn deviates(value: f64, reference: f64, min: f64, ratio: f64) -> bool {
+        let difference = (value - reference).abs();
+        difference > min && difference > ratio * reference.abs()
+    }
+}
+
+/// Flags collected per hour and kind
+#[derive(Default)]
+struct Flags(BTreeMap<(i64, String), Flagged>);
+
+impl Flags {
+    fn add(&mut self, at: i64, kind: &MeasurementKind, value: f64, flag: Flag) {
+        let flagged = self.0.entry((at, kind.name().to_string())).or_insert_with(|| Flagged {
+            at,
+            kind: kind.clone(),
+            value,
+            flags: Vec::new(),
+        });
+        if !flagged.flags.contains(&flag) {
+            flagged.flags.push(flag);
+            flagged.flags.sort();
+        }
+    }
+
+    fn into_vec(self) -> Vec<Flagged> {
+        self.0.into_values().collect()
+    }
+}
+
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let middle = values.len() / 2;
+    Some(match values.len() % 2 {
+        0 => (values[middle - 1] + values[middle]) / 2.0,
+        _ => values[middle],
+    })
+}
+
+/// Runs range, flatline, spike and ordering checks over hourly series
+///
+/// # Arguments:
+/// * series - hourly values sorted by time, e.g. Measurements.history or Store::series
+/// * options - thresholds of checks
+///
+/// # Returns flagged values sorted by hour and kind name, values without timestamp are not checked
+///
+pub fn check_series(series: &[AveragedValues], options: &QualityOptions) -> Vec<Flagged> {
+    let mut flags = Flags::default();
+    let mut by_kind: HashMap<MeasurementKind, Vec<(i64, f64)>> = HashMap::new();
+    for values in series {
+        let at = match values.from_timestamp() {
+            Some(at) => at,
+            None => continue,
+        };
+        for value in values.values.iter() {
+            if let (Some(kind), Some(measured)) = (value.kind(), value.value) {
+                by_kind.entry(kind).or_default().push((at, measured));
+            }
+        }
+        check_ordering(values, at, &mut flags);
+    }
+    for (kind, hours) in by_kind.iter() {
+        if let Some((min, max)) = options.ranges.get(kind) {
+            for (at, value) in hours.iter().filter(|(_, v)| v < min || v > max) {
+                flags.add(*at, kind, *value, Flag::OutOfRange);
+            }
+        }
+        check_flatline(kind, hours, options, &mut flags);
+        if *kind != MeasurementKind::WindBearing {
+            check_spikes(kind, hours, options, &mut flags);
+        }
+    }
+    flags.into_vec()
+}
+
+fn check_ordering(values: &AveragedValues, at: i64, flags: &mut Flags) {
+    let sizes = [MeasurementKind::Pm1, MeasurementKind::Pm25, MeasurementKind::Pm10];
+    for pair in sizes.windows(2) {
+        if let (Some(smaller), Some(larger)) = (values.value(&pair[0]), values.value(&pair[1])) {
+            if smaller > larger + ORDERING_TOLERANCE {
+                flags.add(at, &pair[0], smaller, Flag::Ordering);
+                flags.add(at, &pair[1], larger, Flag::Ordering);
+            }
+        }
+    }
+}
+
+fn check_flatline(kind: &MeasurementKind, hours: &[(i64, f64)], options: &QualityOptions, flags: &mut Flags) {
+    let mut start = 0;
+    for end in 1..=hours.len() {
+        let continues = end < hours.len()
+            && hours[end].0 - hours[end - 1].0 == SECONDS_PER_HOUR
+            && hours[end].1 == hours[start].1;
+        if !continues {
+            if end - start >= options.flatline_hours {
+                for (at, value) in hours[start..end].iter() {
+                    flags.add(*at, kind, *value, Flag::Flatline);
+                }
+            }
+            start = end;
+        }
+    }
+}
+
+/// Compares every hour with median of hours around it, window slides along series sorted by time
+fn check_spikes(kind: &MeasurementKind, hours: &[(i64, f64)], options: &QualityOptions, flags: &mut Flags) {
+    let window = options.spike_window_hours as i64 * SECONDS_PER_HOUR;
+    let (mut first, mut last) = (0, 0);
+    for (at, value) in hours.iter() {
+        while hours[first].0 < at - window {
+            first += 1;
+        }
+        while last < hours.len() && hours[last].0 <= at + window {
+            last += 1;
+        }
+        let around: Vec<f64> = hours[first..last]
+            .iter()
+            .filter(|(other, _)| other != at)
+            .map(|(_, v)| *v)
+            .collect();
+        if around.len() < 2 {
+            continue;
+        }
+        if let Some(reference) = median(around) {
+            if QualityOptions::deviates(*value, reference, options.spike_min_jump, options.spike_ratio) {
+                flags.add(*at, kind, *value, Flag::Spike);
+            }
+        }
+    }
+}
+
+/// Runs series checks over history and current values of measurements
+pub fn check_measurements(measurements: &Measurements, options: &QualityOptions) -> Vec<Flagged> {
+    let mut series = measurements.history.clone();
+    series.extend(measurements.current.clone());
+    check_series(&series, options)
+}
+
+/// Runs series checks over archived series of source
+///
+/// # Returns flagged values, Error if archive could not be read
+///
+pub fn check_store<S: Store + ?Sized>(
+    store: &S,
+    source: &Source,
+    options: &QualityOptions,
+) -> Result<Vec<Flagged>, Box<dyn std::error::Error>> {
+    Ok(check_series(&store.series(source)?, options))
+}
+
+/// Compares values with values of neighbouring installations in the same hour
+///
+/// # Arguments:
+/// * values - values of checked installation, e.g. Measurements.current
+/// * neighbours - values of nearby installations, e.g. from neighbours_current
+/// * options - thresholds of comparison
+///
+/// # Returns values deviating from median of neighbours, flagged NeighbourDeviation
+///
+pub fn check_neighbours(values: &AveragedValues, neighbours: &[AveragedValues], options: &QualityOptions) -> Vec<Flagged> {
+    let mut flags = Flags::default();
+    let at = values.from_timestamp().unwrap_or_default();
+    for value in values.values.iter() {
+        let (kind, measured) = match (value.kind(), value.value) {
+            (Some(kind), Some(measured)) if kind != MeasurementKind::WindBearing => (kind, measured),
+            _ => continue,
+        };
+        let around: Vec<f64> = neighbours.iter().filter_map(|n| n.value(&kind)).collect();
+        if around.len() < options.min_neighbours {
+            continue;
+        }
+        if let Some(reference) = median(around) {
+            if QualityOptions::deviates(measured, reference, options.neighbour_min_diff, options.neighbour_ratio) {
+                flags.add(at, &kind, measured, Flag::NeighbourDeviation);
+            }
+        }
+    }
+    flags.into_vec()
+}
+
+/// Fetches current values of installations around given one, to compare with in check_neighbours
+///
+/// # Arguments:
+/// * client - client used for get_nearest and batch of measurements
+/// * installation - installation checked, left out of neighbours
+/// * radius_km - distance neighbours are searched within
+/// * max_results - maximal number of neighbours
+/// * index_type - type of index of the measurements
+/// * options - concurrency and rate limits of batch
+///
+/// # Returns current values of neighbours that answered, Error if nearest installations could not be fetched
+///
+pub fn neighbours_current<T: Transport + 'static>(
+    client: &AirlyClient<T>,
+    installation: &types::Installation,
+    radius_km: u32,
+    max_results: u32,
+    index_type: types::IndexType,
+    options: &BatchOptions,
+) -> Result<Vec<AveragedValues>, Box<dyn std::error::Error>> {
+    let circle = types::GeoCircle::new(installation.location, radius_km)?;
+    let ids: Vec<u32> = client
+        .clone()
+        .get_nearest(circle, max_results + 1)?
+        .iter()
+        .filter(|neighbour| neighbour.id != installation.id)
+        .map(|neighbour| neighbour.id as u32)
+        .take(max_results as usize)
+        .collect();
+    let batch = client.get_installations_measurements(&ids, index_type, false, options)?;
+    Ok(batch
+        .into_iter()
+        .filter_map(|(id, result)| match result {
+            Ok(measurements) => measurements.current,
+            Err(err) => {
+                warn!("Skipping neighbour {}: {}", id, err);
+                None
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test_quality {
+    use super::{check_neighbours, check_series, neighbours_current, Flag, Flagged, QualityOptions};
+    use crate::client::BatchOptions;
+    use crate::mock::MockAirly;
+    use crate::stub::{INSTALLATION_JSON, MEASUREMENTS_JSON};
+    use crate::types::{AveragedValues, GeoPoint, IndexType, Installation, MeasurementKind, Measurements, Value};
+
+    /// 2019-12-10T00:00:00Z
+    const MIDNIGHT: i64 = 1_575_936_000;
+
+    fn hour(at: i64, values: &[(&str, f64)]) -> AveragedValues {
+        AveragedValues {
+            from_date_time: Some(chrono::DateTime::from_timestamp(at, 0).unwrap().to_rfc3339()),
+            till_date_time: None,
+            values: values
+                .iter()
+                .map(|(name, value)| Value {
+                    name: Some(name.to_string()),
+                    value: Some(*value),
+                })
+                .collect(),
+            indexes: Vec::new(),
+            standards: Vec::new(),
+        }
+    }
+
+    fn series() -> Vec<AveragedValues> {
+        (0..24)
+            .map(|h| {
+                let mut pm1 = 10.0 + (h % 3) as f64;
+                let mut pm25 = 20.0 + (h % 4) as f64;
+                let mut pm10 = 30.0 + (h % 5) as f64;
+                let mut humidity = 60.0 + (h % 7) as f64;
+                match h {
+                    2 => humidity = 105.0,
+                    5 => pm1 = 25.0,
+                    10 => {
+                        pm25 = 300.0;
+                        pm10 = 350.0;
+                    }
+                    12..=19 => humidity = 100.0,
+                    _ => (),
+                }
+                let values = [
+                    ("PM1", pm1),
+                    ("PM25", pm25),
+                    ("PM10", pm10),
+                    ("HUMIDITY", humidity),
+                    ("TEMPERATURE", 5.0 + h as f64 * 0.5),
+                ];
+                hour(MIDNIGHT + h * 3600, &values)
+            })
+            .collect()
+    }
+
+    fn flags_of(flagged: &[Flagged], h: i64, kind: MeasurementKind) -> Vec<Flag> {
+        flagged
+            .iter()
+            .find(|f| f.at == MIDNIGHT + h * 3600 && f.kind == kind)
+            .map_or(Vec::new(), |f| f.flags.clone())
+    }
+
+    #[test]
+    fn test_series_checks() {
+        let flagged = check_series(&series(), &QualityOptions::default());
+        assert_eq!(flags_of(&flagged, 2, MeasurementKind::Humidity), vec![Flag::OutOfRange]);
+        assert_eq!(flags_of(&flagged, 5, MeasurementKind::Pm1), vec![Flag::Ordering]);
+        assert_eq!(flags_of(&flagged, 5, MeasurementKind::Pm25), vec![Flag::Ordering]);
+        assert_eq!(flags_of(&flagged, 10, MeasurementKind::Pm25), vec![Flag::Spike]);
+        assert_eq!(flags_of(&flagged, 10, MeasurementKind::Pm10), vec![Flag::Spike]);
+        let stuck = flagged.iter().filter(|f| f.flags.contains(&Flag::Flatline)).count();
+        assert_eq!(stuck, 8);
+        assert_eq!(flagged.len(), 13);
+        assert!(flagged.iter().all(|f| f.kind != MeasurementKind::Temperature));
+
+        let relaxed = QualityOptions::default()
+            .with_range(MeasurementKind::Humidity, 0.0, 110.0)
+            .with_flatline_hours(9);
+        assert_eq!(check_series(&series(), &relaxed).len(), 4);
+    }
+
+    #[test]
+    fn test_neighbours() {
+        let own = hour(MIDNIGHT, &[("PM25", 80.0), ("PM10", 40.0), ("WIND_BEARING", 10.0)]);
+        let neighbours = vec![
+            hour(MIDNIGHT, &[("PM25", 20.0), ("PM10", 35.0), ("WIND_BEARING", 200.0)]),
+            hour(MIDNIGHT, &[("PM25", 25.0), ("PM10", 30.0), ("WIND_BEARING", 190.0)]),
+            hour(MIDNIGHT, &[("PM25", 22.0), ("PM10", 38.0)]),
+        ];
+        let flagged = check_neighbours(&own, &neighbours, &QualityOptions::default());
+        assert_eq!(flagged.len(), 1);
+        assert_eq!(flagged[0].kind, MeasurementKind::Pm25);
+        assert_eq!(flagged[0].flags, vec![Flag::NeighbourDeviation]);
+        let lonely = QualityOptions::default().with_neighbours(15.0, 1.0, 4);
+        assert!(check_neighbours(&own, &neighbours, &lonely).is_empty());
+    }
+
+    #[test]
+    fn test_neighbours_fetched_with_nearest() {
+        let mock = MockAirly::start().unwrap();
+        let installation: Installation = serde_json::from_str(INSTALLATION_JSON).unwrap();
+        let measurements: Measurements = serde_json::from_str(MEASUREMENTS_JSON).unwrap();
+        for (id, lat) in [(18, 50.06), (19, 50.061), (20, 50.062), (21, 50.5)].iter() {
+            let mut neighbour = installation.clone();
+            neighbour.id = *id;
+            neighbour.location = GeoPoint::new(*lat, 19.94).unwrap();
+            mock.add_installation(neighbour);
+            mock.set_measurements(*id, measurements.clone());
+        }
+        let client = mock.client("0123456789abcdef0123456789abcdef").unwrap();
+        let caqi = IndexType {
+            name: Some("AIRLY_CAQI".to_string()),
+            level: None,
+        };
+        let mut checked = installation.clone();
+        checked.location = GeoPoint::new(50.06, 19.94).unwrap();
+        let neighbours = neighbours_current(&client, &checked, 3, 5, caqi, &BatchOptions::default()).unwrap();
+        assert_eq!(neighbours.len(), 2);
+        assert_eq!(mock.requests_to("measurements/installation"), 2);
+    }
+}
@@ -0,0 +1,356 @@
+use super::Metric;
+use crate::client::{AirlyClient, BatchOptions};
+use crate::transport::Transport;
+use crate::types::{AveragedValues, GeoCircle, GeoPoint, IndexType, Installation};
+use std::cmp::Ordering;
+use std::fmt::Write;
+
+const DEFAULT_INDEX_TYPE: &str = "AIRLY_CAQI";
+
+/// Installation ranked by current value
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub installation: Installation,
+    /// Distance from center of ranking in km
+    pub distance_km: f64,
+    pub value: f64,
+    /// Index level, None when ranked by measurement
+    pub level: Option<String>,
+    /// Position in ranking, 1 for the cleanest, installations with equal values share rank
+    pub rank: usize,
+    /// Percent of other installations with lower value, 0 for the cleanest, 100 for the dirtiest
+    pub percentile: f64,
+}
+
+impl Entry {
+    /// # Returns address as shown in tables, city, street and number
+    pub fn address(&self) -> String {
+        let address = &self.installation.address;
+        format!("{}, {} {}", address.city, address.street, address.number)
+            .trim_end()
+            .trim_end_matches(',')
+            .to_string()
+    }
+}
+
+/// Installation compared with median of its neighbours
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub id: i32,
+    pub value: f64,
+    /// Median value of the other ranked installations
+    pub neighbours_median: f64,
+    /// Value minus neighbours median, positive when installation is dirtier
+    pub difference: f64,
+    /// Value relative to neighbours median, None when median is 0
+    pub ratio: Option<f64>,
+    pub rank: usize,
+    pub percentile: f64,
+    /// Number of neighbours compared with
+    pub neighbours: usize,
+}
+
+/// Installations around a point ranked from the cleanest to the dirtiest by current value of metric
+#[derive(Debug, Clone)]
+pub struct Ranking {
+    pub metric: Metric,
+    pub center: GeoPoint,
+    /// Entries sorted from the cleanest, closer first among equal values
+    pub entries: Vec<Entry>,
+    /// Ids of installations without current value of metric
+    pub missing: Vec<i32>,
+}
+
+fn median(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut values = values.to_vec();
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    let middle = values.len() / 2;
+    Some(match values.len() % 2 {
+        0 => (values[middle - 1] + values[middle]) / 2.0,
+        _ => values[middle],
+    })
+}
+
+impl Ranking {
+    /// Ranks installations by their current values
+    ///
+    /// # Arguments:
+    /// * metric - index or measurement installations are ranked by
+    /// * center - point distances are measured from
+    /// * installations - installations paired with their current values
+    ///
+    pub fn new(metric: Metric, center: GeoPoint, installations: Vec<(Installation, AveragedValues)>) -> Self {
+        let mut missing = Vec::new();
+        let mut entries = Vec::new();
+        for (installation, current) in installations {
+            match metric.value(&current) {
+                Some(value) => entries.push(Entry {
+                    distance_km: center.distance_km(installation.location),
+                    level: metric.level(&current),
+                    installation,
+                    value,
+                    rank: 0,
+                    percentile: 0.0,
+                }),
+                None => missing.push(installation.id),
+            }
+        }
+        entries.sort_by(|a, b| {
+            a.value
+                .partial_cmp(&b.value)
+                .unwrap_or(Ordering::Equal)
+                .then(a.distance_km.partial_cmp(&b.distance_km).unwrap_or(Ordering::Equal))
+        });
+        let values: Vec<f64> = entries.iter().map(|entry| entry.value).collect();
+        let others = values.len().saturating_sub(1).max(1) as f64;
+        for entry in entries.iter_mut() {
+            let lower = values.iter().filter(|value| **value < entry.value).count();
+            entry.rank = lower + 1;
+            entry.percentile = 100.0 * lower as f64 / others;
+        }
+        Self {
+            metric,
+            center,
+            entries,
+            missing,
+        }
+    }
+
+    /// Fetches installations around point with get_nearest and ranks them by their current values
+    ///
+    /// # Arguments:
+    /// * client - client used for get_nearest and batch of measurements
+    /// * circle - area installations are searched within
+    /// * max_results - maximal number of installations
+    /// * metric - index or measurement installations are ranked by, CAQI is requested for measurements
+    /// * options - concurrency and rate limits of batch
+    ///
+    /// # Returns Ranking, Error if nearest installations could not be fetched, installations failing to
+    /// fetch measurements are listed as missing
+    ///
+    pub fn fetch<T: Transport + 'static>(
+        client: &AirlyClient<T>,
+        circle: GeoCircle,
+        max_results: u32,
+        metric: Metric,
+        options: &BatchOptions,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let installations = client.clone().get_nearest(circle, max_results)?;
+        let ids: Vec<u32> = installations.iter().map(|installation| installation.id as u32).collect();
+        let index_type = IndexType {
+            name: Some(match &metric {
+                Metric::Index(name) => name.clone(),
+                Metric::Measurement(_) => DEFAULT_INDEX_TYPE.to_string(),
+            }),
+            level: None,
+        };
+        let batch = client.get_installations_measurements(&ids, index_type, false, options)?;
+        let mut missing = Vec::new();
+        let mut current = Vec::new();
+        for (installation, (id, result)) in installations.into_iter().zip(batch) {
+            match result.map(|measurements| measurements.current) {
+                Ok(Some(values)) => current.push((installation, values)),
+                Ok(None) => missing.push(installation.id),
+                Err(err) => {
+                    warn!("No measurements of installation {}: {}", id, err);
+                    missing.push(installation.id);
+                }
+            }
+        }
+        let mut ranking = Ranking::new(metric, circle.get_point(), current);
+        ranking.missing.extend(missing);
+        Ok(ranking)
+    }
+
+    /// # Returns entries not further than radius_km from center, the cleanest first
+    pub fn within(&self, radius_km: f64) -> Vec<&Entry> {
+        self.entries.iter().filter(|entry| entry.distance_km <= radius_km).collect()
+    }
+
+    /// # Returns the cleanest installation not further than radius_km from center
+    pub fn cleanest(&self, radius_km: f64) -> Option<&Entry> {
+        self.within(radius_km).first().cloned()
+    }
+
+    /// # Returns the dirtiest installation not further than radius_km from center
+    pub fn dirtiest(&self, radius_km: f64) -> Option<&Entry> {
+        self.within(radius_km)
+            .into_iter()
+            .fold(None, |dirtiest: Option<&Entry>, entry| match dirtiest {
+                Some(dirtiest) if dirtiest.value >= entry.value => Some(dirtiest),
+                _ => Some(entry),
+            })
+    }
+
+    /// # Returns median value of all ranked installations, None if none has value
+    pub fn median(&self) -> Option<f64> {
+        median(&self.entries.iter().map(|entry| entry.value).collect::<Vec<f64>>())
+    }
+
+    /// # Returns entry of installation, None if it is not ranked
+    pub fn entry(&self, id: i32) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.installation.id == id)
+    }
+
+    /// Compares installation with median of the other ranked installations
+    ///
+    /// # Returns Comparison, None if installation is not ranked or has no neighbours
+    ///
+    pub fn compare(&self, id: i32) -> Option<Comparison> {
+        let entry = self.entry(id)?;
+        let others: Vec<f64> = self
+            .entries
+            .iter()
+            .filter(|other| other.installation.id != id)
+            .map(|other| other.value)
+            .collect();
+        let neighbours_median = median(&others)?;
+        Some(Comparison {
+            id,
+            value: entry.value,
+            neighbours_median,
+            difference: entry.value - neighbours_median,
+            ratio: if neighbours_median != 0.0 {
+                Some(entry.value / neighbours_median)
+            } else {
+                None
+            },
+            rank: entry.rank,
+            percentile: entry.percentile,
+            neighbours: others.len(),
+        })
+    }
+
+    /// Renders ranking as plain text table with aligned columns, for terminal output
+    pub fn to_table(&self) -> String {
+        let mut table = String::new();
+        let _ = writeln!(
+            table,
+            "{:>4}  {:>8}  {:>8}  {:>8}  {:<11}  {:>10}  address",
+            "rank",
+            "id",
+            "km",
+            self.metric.name().chars().take(8).collect::<String>(),
+            "level",
+            "percentile"
+        );
+        for entry in self.entries.iter() {
+            let _ = writeln!(
+                table,
+                "{:>4}  {:>8}  {:>8.2}  {:>8.1}  {:<11}  {:>10.0}  {}",
+                entry.rank,
+                entry.installation.id,
+                entry.distance_km,
+                entry.value,
+                entry.level.as_deref().unwrap_or("-"),
+                entry.percentile,
+                entry.address()
+            );
+        }
+        if !self.missing.is_empty() {
+            let ids: Vec<String> = self.missing.iter().map(|id| id.to_string()).collect();
+            let _ = writeln!(table, "no value: {}", ids.join(", "));
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod test_ranking {
+    use super::Ranking;
+    use crate::analysis::Metric;
+    use crate::client::BatchOptions;
+    use crate::mock::MockAirly;
+    use crate::stub::{INSTALLATION_JSON, MEASUREMENTS_JSON};
+    use crate::types::{AveragedValues, GeoCircle, GeoPoint, Installation, MeasurementKind, Measurements};
+
+    fn installation(id: i32, lat: f32) -> Installation {
+        let mut installation: Installation = serde_json::from_str(INSTALLATION_JSON).unwrap();
+        installation.id = id;
+        installation.location = GeoPoint::new(lat, 19.94).unwrap();
+        installation
+    }
+
+    fn current(pm25: Option<f64>) -> AveragedValues {
+        let measurements: Measurements = serde_json::from_str(MEASUREMENTS_JSON).unwrap();
+        let mut current = measurements.current.unwrap();
+        current.values.retain(|value| value.name.as_deref() != Some("PM25"));
+        if let Some(pm25) = pm25 {
+            let mut value = current.values[0].clone();
+            value.name = Some("PM25".to_string());
+            value.value = Some(pm25);
+            current.values.push(value);
+        }
+        current
+    }
+
+    fn krakow() -> Vec<(Installation, AveragedValues)> {
+        vec![
+            (installation(1, 50.06), current(Some(40.0))),
+            (installation(2, 50.07), current(Some(10.0))),
+            (installation(3, 50.08), current(Some(25.0))),
+            (installation(4, 50.09), current(Some(25.0))),
+            (installation(5, 50.20), current(Some(5.0))),
+            (installation(6, 50.06), current(None)),
+        ]
+    }
+
+    #[test]
+    fn test_rank_percentiles_and_comparison() {
+        let center = GeoPoint::new(50.06, 19.94).unwrap();
+        let ranking = Ranking::new(Metric::Measurement(MeasurementKind::Pm25), center, krakow());
+        let ids: Vec<i32> = ranking.entries.iter().map(|e| e.installation.id).collect();
+        assert_eq!(ids, vec![5, 2, 3, 4, 1]);
+        assert_eq!(ranking.missing, vec![6]);
+        assert_eq!(ranking.entry(3).unwrap().rank, 3);
+        assert_eq!(ranking.entry(4).unwrap().rank, 3);
+        assert_eq!(ranking.entry(5).unwrap().percentile, 0.0);
+        assert_eq!(ranking.entry(4).unwrap().percentile, 50.0);
+        assert_eq!(ranking.entry(1).unwrap().percentile, 100.0);
+        assert_eq!(ranking.median(), Some(25.0));
+
+        assert_eq!(ranking.cleanest(5.0).unwrap().installation.id, 2);
+        assert_eq!(ranking.dirtiest(5.0).unwrap().installation.id, 1);
+        assert_eq!(ranking.cleanest(50.0).unwrap().installation.id, 5);
+        assert!(ranking.cleanest(-1.0).is_none());
+
+        let comparison = ranking.compare(1).unwrap();
+        assert_eq!(comparison.neighbours, 4);
+        assert_eq!(comparison.neighbours_median, 17.5);
+        assert_eq!(comparison.difference, 22.5);
+        assert!(ranking.compare(6).is_none());
+
+        let table = ranking.to_table();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert!(lines[0].starts_with("rank"));
+        assert!(lines[0].contains("PM25"));
+        assert_eq!(lines[6], "no value: 6");
+    }
+
+    #[test]
+    fn test_fetched_with_nearest() {
+        let mock = MockAirly::start().unwrap();
+        for (installation, current) in krakow() {
+            let mut measurements: Measurements = serde_json::from_str(MEASUREMENTS_JSON).unwrap();
+            measurements.current = Some(current);
+            mock.set_measurements(installation.id, measurements);
+            mock.add_installation(installation);
+        }
+        let client = mock.client("0123456789abcdef0123456789abcdef").unwrap();
+        let circle = GeoCircle::new(GeoPoint::new(50.06, 19.94).unwrap(), 5).unwrap();
+        let metric = Metric::Measurement(MeasurementKind::Pm25);
+        let ranking = Ranking::fetch(&client, circle, 10, metric, &BatchOptions::default()).unwrap();
+        let ids: Vec<i32> = ranking.entries.iter().map(|e| e.installation.id).collect();
+        assert_eq!(ids, vec![2, 3, 4, 1]);
+        assert_eq!(ranking.missing, vec![6]);
+
+        let caqi = Ranking::fetch(&client, circle, 10, Metric::Index("AIRLY_CAQI".to_string()), &BatchOptions::default())
+            .unwrap();
+        assert_eq!(caqi.entries.len(), 5);
+        assert!(caqi.entries.iter().all(|entry| entry.level.is_some()));
+    }
+}
@@ -0,0 +1,474 @@
+use crate::store::{Source, Store};
+use crate::types::{AveragedValues, MeasurementKind};
+use crate::wind::{self, Compass};
+use std::fmt::Write;
+
+const DEFAULT_SECTORS: usize = 16;
+/// Upper bounds of speed bins in m/s, Beaufort 1 to 4, last bin holds anything faster
+const DEFAULT_SPEED_EDGES_MS: [f64; 4] = [1.5, 3.3, 5.5, 7.9];
+const DEFAULT_CALM_MS: f64 = 0.5;
+const DEFAULT_MIN_SAMPLES: usize = 3;
+const SVG_MARGIN: f64 = 24.0;
+
+/// Settings of pollution rose
+#[derive(Debug, Clone)]
+pub struct RoseOptions {
+    pollutant: MeasurementKind,
+    sectors: usize,
+    speed_edges_ms: Vec<f64>,
+    calm_ms: f64,
+    min_samples: usize,
+}
+
+impl Default for RoseOptions {
+    fn default() -> Self {
+        Self {
+            pollutant: MeasurementKind::Pm25,
+            sectors: DEFAULT_SECTORS,
+            speed_edges_ms: DEFAULT_SPEED_EDGES_MS.to_vec(),
+            calm_ms: DEFAULT_CALM_MS,
+            min_samples: DEFAULT_MIN_SAMPLES,
+        }
+    }
+}
+
+impl RoseOptions {
+    /// Sets measurement binned by wind, PM25 by default
+    pub fn with_pollutant(mut self, pollutant: MeasurementKind) -> Self {
+        self.pollutant = pollutant;
+        self
+    }
+
+    /// Sets number of direction sectors, 16 by default
+    pub fn with_sectors(mut self, sectors: usize) -> Self {
+        self.sectors = sectors.max(1);
+        self
+    }
+
+    /// Sets upper bounds of speed bins in m/s, sorted ascending, anything faster lands in extra last bin
+    pub fn with_speed_edges_ms(mut self, speed_edges_ms: Vec<f64>) -> Self {
+        self.speed_edges_ms = speed_edges_ms;
+        self
+    }
+
+    /// Sets speed in m/s below which wind has no meaningful direction, 0.5 by default
+    pub fn with_calm_ms(mut self, calm_ms: f64) -> Self {
+        self.calm_ms = calm_ms;
+        self
+    }
+
+    /// Sets number of hours sector needs to be considered source direction, 3 by default
+    pub fn with_min_samples(mut self, min_samples: usize) -> Self {
+        self.min_samples = min_samples.max(1);
+        self
+    }
+}
+
+/// Hour with both wind and pollutant measured
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub bearing_deg: f64,
+    pub speed_ms: f64,
+    pub value: f64,
+}
+
+/// Collects hours with both wind and given pollutant measured
+///
+/// # Returns samples in order of periods, hours missing either are skipped
+///
+pub fn samples(periods: &[AveragedValues], pollutant: &MeasurementKind) -> Vec<Sample> {
+    periods
+        .iter()
+        .filter_map(|period| {
+            let wind = period.wind()?;
+            Some(Sample {
+                bearing_deg: wind.bearing_deg(),
+                speed_ms: wind.speed_ms(),
+                value: period.value(pollutant)?,
+            })
+        })
+        .collect()
+}
+
+/// Hours and pollutant sum of single rose bin
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Bin {
+    pub count: usize,
+    pub sum: f64,
+}
+
+impl Bin {
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+    }
+
+    /// # Returns mean pollutant value, None for empty bin
+    pub fn mean(&self) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        Some(self.sum / self.count as f64)
+    }
+}
+
+/// Direction pollution most likely comes from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SourceDirection {
+    /// Mean bearing of the sector and its neighbours weighted by pollutant, wind blows from it
+    pub bearing_deg: f64,
+    pub compass: Compass,
+    /// Mean pollutant value of hours with wind from the sector
+    pub mean: f64,
+    /// Sector mean relative to mean of all hours with wind, above 1 when sector is dirtier than average
+    pub ratio: f64,
+    /// Hours with wind from the sector
+    pub samples: usize,
+}
+
+/// Pollutant values binned by wind direction sector and speed
+#[derive(Debug, Clone)]
+pub struct PollutionRose {
+    pollutant: MeasurementKind,
+    speed_edges_ms: Vec<f64>,
+    min_samples: usize,
+    /// Bins indexed by sector, then by speed bin
+    bins: Vec<Vec<Bin>>,
+    calm: Bin,
+    samples: Vec<Sample>,
+}
+
+impl PollutionRose {
+    /// Bins hours of periods that have both wind and pollutant measured
+    ///
+    /// # Arguments:
+    /// * periods - e.g. Measurements.history fetched with wind
+    /// * options - pollutant, sectors and speed bins
+    ///
+    pub fn build(periods: &[AveragedValues], options: &RoseOptions) -> Self {
+        PollutionRose::from_samples(samples(periods, &options.pollutant), options)
+    }
+
+    /// Bins hours of archived series of source, see Store::series
+    ///
+    /// # Returns rose of whole archive, Error if archive could not be read
+    ///
+    pub fn from_store<S: Store + ?Sized>(
+        store: &S,
+        source: &Source,
+        options: &RoseOptions,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(PollutionRose::build(&store.series(source)?, options))
+    }
+
+    /// Bins samples collected elsewhere
+    pub fn from_samples(samples: Vec<Sample>, options: &RoseOptions) -> Self {
+        let speed_bins = options.speed_edges_ms.len() + 1;
+        let mut bins = vec![vec![Bin::default(); speed_bins]; options.sectors];
+        let mut calm = Bin::default();
+        let mut kept = Vec::with_capacity(samples.len());
+        for sample in samples {
+            if sample.speed_ms < options.calm_ms {
+                calm.add(sample.value);
+                continue;
+            }
+            let sector = sector_of(sample.bearing_deg, options.sectors);
+            let speed = options
+                .speed_edges_ms
+                .iter()
+                .take_while(|edge| sample.speed_ms >= **edge)
+                .count();
+            bins[sector][speed].add(sample.value);
+            kept.push(sample);
+        }
+        Self {
+            pollutant: options.pollutant.clone(),
+            speed_edges_ms: options.speed_edges_ms.clone(),
+            min_samples: options.min_samples,
+            bins,
+            calm,
+            samples: kept,
+        }
+    }
+
+    pub fn sectors(&self) -> usize {
+        self.bins.len()
+    }
+
+    /// # Returns bins of sector, slowest speed first
+    pub fn bins(&self, sector: usize) -> &[Bin] {
+        &self.bins[sector]
+    }
+
+    /// # Returns hours calmer than calm threshold, not assigned to any sector
+    pub fn calm(&self) -> Bin {
+        self.calm
+    }
+
+    /// # Returns bearing of sector center in degrees
+    pub fn sector_bearing(&self, sector: usize) -> f64 {
+        sector as f64 * 360.0 / self.sectors() as f64
+    }
+
+    /// # Returns all hours with wind from sector, whatever the speed
+    pub fn sector(&self, sector: usize) -> Bin {
+        self.bins[sector].iter().fold(Bin::default(), |total, bin| Bin {
+            count: total.count + bin.count,
+            sum: total.sum + bin.sum,
+        })
+    }
+
+    /// # Returns all hours with wind, calm ones excluded
+    pub fn total(&self) -> Bin {
+        (0..self.sectors()).map(|s| self.sector(s)).fold(Bin::default(), |total, bin| Bin {
+            count: total.count + bin.count,
+            sum: total.sum + bin.sum,
+        })
+    }
+
+    /// Estimates direction pollution comes from, sector with the highest mean pollutant value among
+    /// sectors with enough hours
+    ///
+    /// # Returns source direction, None if no sector has enough hours
+    ///
+    pub fn dominant_source(&self) -> Option<SourceDirection> {
+        let overall = self.total().mean()?;
+        let (sector, mean) = (0..self.sectors())
+            .filter(|s| self.sector(*s).count >= self.min_samples)
+            .filter_map(|s| Some((s, self.sector(s).mean()?)))
+            .fold(None, |best: Option<(usize, f64)>, (s, mean)| match best {
+                Some((_, best_mean)) if best_mean >= mean => best,
+                _ => Some((s, mean)),
+            })?;
+        let bearing_deg = self.weighted_bearing(sector).unwrap_or_else(|| self.sector_bearing(sector));
+        Some(SourceDirection {
+            bearing_deg,
+            compass: Compass::from_bearing(bearing_deg),
+            mean,
+            ratio: if overall > 0.0 { mean / overall } else { 1.0 },
+            samples: self.sector(sector).count,
+        })
+    }
+
+    /// Circular mean of bearings of hours in sector and its neighbours, weighted by pollutant value
+    fn weighted_bearing(&self, sector: usize) -> Option<f64> {
+        let n = self.sectors();
+        let near = [(sector + n - 1) % n, sector, (sector + 1) % n];
+        let (sin, cos) = self
+            .samples
+            .iter()
+            .filter(|sample| near.contains(&sector_of(sample.bearing_deg, n)))
+            .fold((0.0, 0.0), |(sin, cos), sample| {
+                let rad = sample.bearing_deg.to_radians();
+                (sin + sample.value * rad.sin(), cos + sample.value * rad.cos())
+            });
+        if sin == 0.0 && cos == 0.0 {
+            return None;
+        }
+        Some(wind::normalize_bearing(sin.atan2(cos).to_degrees()))
+    }
+
+    /// Renders rose as SVG, wedge length is share of hours with wind from sector stacked by speed,
+    /// wedge color is mean pollutant value from green for the lowest to red for the highest
+    ///
+    /// # Arguments:
+    /// * size - width and height of image in pixels
+    ///
+    /// # Returns SVG document
+    ///
+    pub fn to_svg(&self, size: u32) -> String {
+        let size = f64::from(size);
+        let center = size / 2.0;
+        let radius = (center - SVG_MARGIN).max(1.0);
+        let longest = (0..self.sectors()).map(|s| self.sector(s).count).max().unwrap_or(0).max(1) as f64;
+        let highest = self
+            .bins
+            .iter()
+            .flatten()
+            .filter_map(Bin::mean)
+            .fold(0.0, f64::max);
+        let half_width = 180.0 / self.sectors() as f64;
+        let mut svg = String::new();
+        let _ = write!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{0}" viewBox="0 0 {0} {0}">"#,
+            size
+        );
+        let _ = write!(
+            svg,
+            r##"<title>{} pollution rose</title><circle cx="{c}" cy="{c}" r="{r}" fill="none" stroke="#ccc"/>"##,
+            escape(self.pollutant.name()),
+            c = center,
+            r = radius
+        );
+        for sector in 0..self.sectors() {
+            let bearing = self.sector_bearing(sector);
+            let mut cumulative = self.sector(sector).count;
+            // outer bins first so inner, slower ones are painted over them
+            for (speed, bin) in self.bins[sector].iter().enumerate().rev() {
+                if bin.count > 0 {
+                    let outer = radius * cumulative as f64 / longest;
+                    let (x1, y1) = polar(center, outer, bearing - half_width);
+                    let (x2, y2) = polar(center, outer, bearing + half_width);
+                    let mean = bin.mean().unwrap_or(0.0);
+                    let _ = write!(
+                        svg,
+                        r#"<path d="M{c:.1} {c:.1} L{:.1} {:.1} A{r:.1} {r:.1} 0 0 1 {:.1} {:.1} Z" fill="{}" stroke="white"><title>{} {}: {} h, mean {:.1}</title></path>"#,
+                        x1,
+                        y1,
+                        x2,
+                        y2,
+                        color(if highest > 0.0 { mean / highest } else { 0.0 }),
+                        escape(Compass::from_bearing(bearing).name()),
+                        escape(&self.speed_label(speed)),
+                        bin.count,
+                        mean,
+                        c = center,
+                        r = outer
+                    );
+                }
+                cumulative -= bin.count;
+            }
+        }
+        for compass in [Compass::N, Compass::E, Compass::S, Compass::W].iter() {
+            let (x, y) = polar(center, radius + SVG_MARGIN / 2.0, compass.bearing());
+            let _ = write!(
+                svg,
+                r#"<text x="{:.1}" y="{:.1}" text-anchor="middle" dominant-baseline="middle" font-size="12">{}</text>"#,
+                x,
+                y,
+                escape(compass.name())
+            );
+        }
+        svg.push_str("</svg>");
+        svg
+    }
+
+    fn speed_label(&self, speed: usize) -> String {
+        match (speed.checked_sub(1).map(|s| self.speed_edges_ms[s]), self.speed_edges_ms.get(speed)) {
+            (None, Some(till)) => format!("<{} m/s", till),
+            (Some(from), Some(till)) => format!("{}-{} m/s", from, till),
+            (Some(from), None) => format!(">={} m/s", from),
+            (None, None) => "any speed".to_string(),
+        }
+    }
+}
+
+fn sector_of(bearing_deg: f64, sectors: usize) -> usize {
+    let width = 360.0 / sectors as f64;
+    (wind::normalize_bearing(bearing_deg) / width).round() as usize % sectors
+}
+
+/// Escapes text written into svg elements
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Point at bearing, clockwise from north, and distance from center of image
+fn polar(center: f64, radius: f64, bearing_deg: f64) -> (f64, f64) {
+    let rad = bearing_deg.to_radians();
+    (center + radius * rad.sin(), center - radius * rad.cos())
+}
+
+/// Color from green through yellow to red for share 0 to 1
+fn color(share: f64) -> String {
+    let share = share.clamp(0.0, 1.0);
+    let (red, green) = if share < 0.5 {
+        (share * 2.0 * 255.0, 200.0)
+    } else {
+        (255.0, (1.0 - share) * 2.0 * 200.0)
+    };
+    format!("#{:02x}{:02x}00", red.round() as u8, green.round() as u8)
+}
+
+#[cfg(test)]
+mod test_rose {
+    use super::{PollutionRose, RoseOptions};
+    use crate::store::{MemoryStore, Record, Source, Store};
+    use crate::types::{AveragedValues, Measurements, MeasurementKind, Value};
+    use crate::wind::Compass;
+
+    fn value(name: &str, value: f64) -> Value {
+        Value {
+            name: Some(name.to_string()),
+            value: Some(value),
+        }
+    }
+
+    fn hour(h: usize, bearing: f64, speed_kmh: f64, pm25: f64) -> AveragedValues {
+        AveragedValues {
+            from_date_time: Some(format!("2019-12-{:02}T{:02}:00:00.000Z", 1 + h / 24, h % 24)),
+            till_date_time: None,
+            values: vec![
+                value("PM25", pm25),
+                value("WIND_SPEED", speed_kmh),
+                value("WIND_BEARING", bearing),
+            ],
+            indexes: Vec::new(),
+            standards: Vec::new(),
+        }
+    }
+
+    /// Clean westerly winds most of the time, smog whenever wind blows from east-north-east
+    fn series() -> Vec<AveragedValues> {
+        (0..96)
+            .map(|h| match h % 4 {
+                0 => hour(h, 60.0 + (h % 3) as f64 * 5.0, 10.0, 85.0),
+                1 => hour(h, 270.0, 20.0, 12.0),
+                2 => hour(h, 250.0 + (h % 5) as f64, 30.0, 10.0),
+                _ => hour(h, 180.0, 1.0, 40.0),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_bins_and_dominant_source() {
+        let rose = PollutionRose::build(&series(), &RoseOptions::default());
+        assert_eq!(rose.sectors(), 16);
+        assert_eq!(rose.calm().count, 24);
+        assert_eq!(rose.total().count, 72);
+        assert_eq!(rose.sector(12).count, 24);
+        assert_eq!(rose.bins(12)[3].count, 24);
+        let source = rose.dominant_source().unwrap();
+        assert_eq!(source.compass, Compass::Ene);
+        assert!(source.bearing_deg > 60.0 && source.bearing_deg < 70.0);
+        assert!((source.mean - 85.0).abs() < 1e-9);
+        assert!(source.ratio > 2.0);
+
+        let sparse = RoseOptions::default().with_min_samples(25);
+        assert!(PollutionRose::build(&series(), &sparse).dominant_source().is_none());
+        let pm10 = RoseOptions::default().with_pollutant(MeasurementKind::Pm10);
+        assert_eq!(PollutionRose::build(&series(), &pm10).total().count, 0);
+    }
+
+    #[test]
+    fn test_rose_of_archive_and_svg() {
+        let mut store = MemoryStore::new();
+        store
+            .save(&Record {
+                source: Source::Installation(34),
+                fetched_at: 0,
+                measurements: Measurements {
+                    current: None,
+                    history: series(),
+                    forecast: Vec::new(),
+                },
+            })
+            .unwrap();
+        let options = RoseOptions::default().with_sectors(8);
+        let rose = PollutionRose::from_store(&store, &Source::Installation(34), &options).unwrap();
+        assert_eq!(rose.dominant_source().unwrap().compass, Compass::Ene);
+        let svg = rose.to_svg(300);
+        assert!(svg.starts_with("<svg") && svg.ends_with("</svg>"));
+        assert_eq!(svg.matches("<path").count(), 4);
+        assert!(svg.contains("fill=\"#ff0000\""));
+        assert!(svg.contains(">N</text>") && svg.contains(">W</text>"));
+        assert!(roxmltree::Document::parse(&svg).is_ok());
+
+        // hours of 1 km/h wind land in the slowest bin, labelled below its edge
+        let light = RoseOptions::default().with_calm_ms(0.1);
+        let svg = PollutionRose::build(&series(), &light).to_svg(300);
+        assert!(svg.contains("S &lt;1.5 m/s: 24 h"));
+        assert!(roxmltree::Document::parse(&svg).is_ok());
+    }
+}
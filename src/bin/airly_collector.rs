@@ -0,0 +1,37 @@
+//! Long-running collector archiving measurements of configured installations and points
+//!
+//! Usage: `airly_collector config.json`, stops gracefully on Ctrl-C or SIGTERM
+
+use airly_rs::client::{AirlyClient, ApiKey};
+use airly_rs::clock::SystemClock;
+use airly_rs::collector::{Collector, Config};
+use airly_rs::store::FileStore;
+use dotenv::dotenv;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+const USAGE: &str = "Usage: airly_collector <config.json>";
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+    env_logger::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let path = env::args().nth(1).ok_or(USAGE)?;
+    let config = Config::from_file(&path)?;
+    let api_key = match &config.api_key {
+        Some(api_key) => api_key.clone(),
+        None => ApiKey::discover()?,
+    };
+    let mut client = AirlyClient::from_api_key(api_key);
+    if let Some(base_url) = &config.base_url {
+        client = client.with_base_url(base_url);
+    }
+    let store = FileStore::new(&config.store_dir)?;
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let flag = shutdown.clone();
+    ctrlc::set_handler(move || flag.store(true, Ordering::SeqCst))?;
+
+    Collector::new(client, &config, store, SystemClock).run(&shutdown);
+    Ok(())
+}
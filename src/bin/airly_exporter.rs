@@ -0,0 +1,35 @@
+//! Prometheus exporter of current air quality of given installations
+//!
+//! Usage: `airly_exporter <listen address> <installation id>...`, e.g. `airly_exporter 0.0.0.0:9188 18 34`
+//! Optional AIRLY_EXPORTER_TTL_SECS sets how long measurements are cached between scrapes
+
+use airly_rs::client::{AirlyClient, ApiKey};
+use airly_rs::clock::SystemClock;
+use airly_rs::exporter::Exporter;
+use dotenv::dotenv;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+const USAGE: &str = "Usage: airly_exporter <listen address> <installation id>...";
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+    env_logger::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let mut args = env::args().skip(1);
+    let addr = args.next().ok_or(USAGE)?;
+    let installations = args.map(|id| id.parse()).collect::<Result<Vec<u32>, _>>()?;
+    if installations.is_empty() {
+        return Err(USAGE.into());
+    }
+    let client = AirlyClient::from_api_key(ApiKey::discover()?);
+    let mut exporter = Exporter::new(client, installations, SystemClock);
+    if let Ok(ttl) = env::var("AIRLY_EXPORTER_TTL_SECS") {
+        exporter = exporter.with_ttl_secs(ttl.parse()?);
+    }
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let flag = shutdown.clone();
+    ctrlc::set_handler(move || flag.store(true, Ordering::SeqCst))?;
+    exporter.serve(&addr, &shutdown)
+}
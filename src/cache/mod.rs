@@ -0,0 +1,508 @@
+use crate::client::{Endpoint, EndpointClass};
+use crate::clock::{Clock, SystemClock};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+const SECONDS_PER_HOUR: i64 = 3600;
+const DEFAULT_CAPACITY: usize = 256;
+
+/// Response body stored in cache with its validity
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CachedResponse {
+    /// Url response was fetched from, without api key
+    pub url: String,
+    /// Response body
+    pub body: String,
+    /// ETag header of the response if server sent one
+    pub etag: Option<String>,
+    /// Unix timestamp in seconds of the moment response was stored
+    pub stored_at: i64,
+    /// Unix timestamp in seconds after which response should be fetched again
+    pub expires_at: i64,
+}
+
+/// Storage of cached responses
+pub trait Cache: Send + Sync + fmt::Debug {
+    /// # Returns response stored under url, expired responses included
+    fn get(&self, url: &str) -> Option<CachedResponse>;
+
+    /// Stores response, replacing previous one for the same url
+    fn put(&self, response: CachedResponse);
+}
+
+/// In-memory cache evicting least recently used responses above capacity
+#[derive(Debug)]
+pub struct MemoryCache {
+    capacity: usize,
+    state: Mutex<LruState>,
+}
+
+#[derive(Debug, Default)]
+struct LruState {
+    tick: u64,
+    entries: HashMap<String, (u64, CachedResponse)>,
+}
+
+impl MemoryCache {
+    /// Constructs MemoryCache
+    ///
+    /// # Arguments:
+    /// * capacity - max number of responses kept
+    ///
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            state: Mutex::new(LruState::default()),
+        }
+    }
+}
+
+impl Default for MemoryCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl Cache for MemoryCache {
+    fn get(&self, url: &str) -> Option<CachedResponse> {
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        state.entries.get_mut(url).map(|(used, response)| {
+            *used = tick;
+            response.clone()
+        })
+    }
+
+    fn put(&self, response: CachedResponse) {
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        state.entries.insert(response.url.clone(), (tick, response));
+        while state.entries.len() > self.capacity {
+            let oldest = state
+                .entries
+                .iter()
+                .min_by_key(|(_, (used, _))| *used)
+                .map(|(url, _)| url.clone());
+            if let Some(url) = oldest {
+                state.entries.remove(&url);
+            }
+        }
+    }
+}
+
+/// Cache keeping every response as json file in a directory, survives restarts
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    dir: PathBuf,
+}
+
+impl DiskCache {
+    /// Constructs DiskCache, creates directory if missing
+    ///
+    /// # Arguments:
+    /// * dir - directory to keep cached responses in
+    ///
+    /// # Returns DiskCache if directory is accessible Error otherwise
+    ///
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, url: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}.json", fnv1a(url)))
+    }
+}
+
+impl Cache for DiskCache {
+    fn get(&self, url: &str) -> Option<CachedResponse> {
+        let text = fs::read_to_string(self.path(url)).ok()?;
+        serde_json::from_str::<CachedResponse>(&text)
+            .ok()
+            .filter(|response| response.url == url)
+    }
+
+    fn put(&self, response: CachedResponse) {
+        let path = self.path(&response.url);
+        let written = serde_json::to_string(&response)
+            .map_err(|e| e.to_string())
+            .and_then(|text| fs::write(&path, text).map_err(|e| e.to_string()));
+        if let Err(err) = written {
+            warn!("Caching response in {} failed: {}", path.display(), err);
+        }
+    }
+}
+
+/// Stable hash of cache key, file names must not change between builds
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Time to live of each endpoint class
+#[derive(Debug, Clone, Copy)]
+pub struct CachePolicy {
+    /// Indexes and measurement types, 3 days by default
+    pub meta_ttl_secs: i64,
+    /// Installations, 6 hours by default
+    pub installation_ttl_secs: i64,
+    /// Measurements are valid until the next full hour when Airly publishes new averages
+    pub measurements_until_full_hour: bool,
+    /// Measurements time to live used when not aligned to full hour, 1 hour by default
+    pub measurements_ttl_secs: i64,
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        Self {
+            meta_ttl_secs: 3 * 24 * SECONDS_PER_HOUR,
+            installation_ttl_secs: 6 * SECONDS_PER_HOUR,
+            measurements_until_full_hour: true,
+            measurements_ttl_secs: SECONDS_PER_HOUR,
+        }
+    }
+}
+
+impl CachePolicy {
+    /// Calculates moment response of endpoint fetched now expires
+    ///
+    /// # Returns unix timestamp in seconds
+    ///
+    pub fn expires_at(&self, endpoint: &Endpoint, now: i64) -> i64 {
+        match endpoint.class() {
+            EndpointClass::Meta => now + self.meta_ttl_secs,
+            EndpointClass::Installation => now + self.installation_ttl_secs,
+            EndpointClass::Measurements if self.measurements_until_full_hour => {
+                (now / SECONDS_PER_HOUR + 1) * SECONDS_PER_HOUR
+            }
+            EndpointClass::Measurements => now + self.measurements_ttl_secs,
+        }
+    }
+}
+
+/// Snapshot of cache counters
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheStats {
+    /// Requests served from cache without touching network
+    pub hits: u64,
+    /// Requests fetched from network
+    pub misses: u64,
+    /// Expired responses confirmed unchanged by server with 304 Not Modified
+    pub revalidated: u64,
+    /// Expired responses served because api was unreachable or client was offline
+    pub stale: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    revalidated: AtomicU64,
+    stale: AtomicU64,
+}
+
+/// Cache of api responses with endpoint aware expiry and hit/miss statistics, shared by client clones
+pub struct ResponseCache {
+    cache: Box<dyn Cache>,
+    policy: CachePolicy,
+    clock: Arc<dyn Clock>,
+    counters: Counters,
+}
+
+impl fmt::Debug for ResponseCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ResponseCache")
+            .field("cache", &self.cache)
+            .field("policy", &self.policy)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl ResponseCache {
+    /// Constructs ResponseCache with default policy and system clock
+    ///
+    /// # Arguments:
+    /// * cache - storage, e.g. MemoryCache or DiskCache
+    ///
+    pub fn new<C: Cache + 'static>(cache: C) -> Self {
+        Self {
+            cache: Box::new(cache),
+            policy: CachePolicy::default(),
+            clock: Arc::new(SystemClock),
+            counters: Counters::default(),
+        }
+    }
+
+    /// Sets time to live of endpoint classes
+    pub fn with_policy(mut self, policy: CachePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets time source for expiry, e.g. FakeClock in tests
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// # Returns current hit, miss, revalidation and stale counters
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+            revalidated: self.counters.revalidated.load(Ordering::Relaxed),
+            stale: self.counters.stale.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Looks response up, counts a hit if it has not expired yet
+    ///
+    /// # Returns Ok with fresh response, Err with expired response usable for revalidation or None if missing
+    ///
+    pub(crate) fn lookup(&self, url: &str) -> Result<CachedResponse, Option<CachedResponse>> {
+        match self.cache.get(url) {
+            Some(response) if response.expires_at > self.clock.now() => {
+                self.counters.hits.fetch_add(1, Ordering::Relaxed);
+                Ok(response)
+            }
+            expired => Err(expired),
+        }
+    }
+
+    /// Stores freshly fetched response
+    pub(crate) fn store(&self, endpoint: &Endpoint, url: &str, body: &str, etag: Option<String>) {
+        self.counters.misses.fetch_add(1, Ordering::Relaxed);
+        let now = self.clock.now();
+        self.cache.put(CachedResponse {
+            url: url.to_string(),
+            body: body.to_string(),
+            etag,
+            stored_at: now,
+            expires_at: self.policy.expires_at(endpoint, now),
+        });
+    }
+
+    /// Extends validity of expired response confirmed unchanged by server
+    pub(crate) fn revalidate(&self, endpoint: &Endpoint, mut response: CachedResponse) -> CachedResponse {
+        self.counters.revalidated.fetch_add(1, Ordering::Relaxed);
+        let now = self.clock.now();
+        response.stored_at = now;
+        response.expires_at = self.policy.expires_at(endpoint, now);
+        self.cache.put(response.clone());
+        response
+    }
+
+    /// Counts expired response served in place of a fresh one
+    ///
+    /// # Returns age of the response in seconds
+    ///
+    pub(crate) fn serve_stale(&self, response: &CachedResponse) -> i64 {
+        self.counters.stale.fetch_add(1, Ordering::Relaxed);
+        self.clock.now() - response.stored_at
+    }
+}
+
+#[cfg(test)]
+mod test_cache {
+    use super::{Cache, CachePolicy, CacheStats, CachedResponse, DiskCache, MemoryCache, ResponseCache};
+    use crate::client::{AirlyClient, Endpoint, Freshness};
+    use crate::clock::FakeClock;
+    use crate::stub::{StubServer, INSTALLATION_JSON, MEASUREMENTS_JSON};
+    use crate::types::{IndexType, Installation, Measurements};
+    use std::env;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const API_KEY: &str = "00000000000000000000000000000000";
+    const HOUR: i64 = 1_575_885_600;
+
+    fn response(url: &str) -> CachedResponse {
+        CachedResponse {
+            url: url.to_string(),
+            body: "{}".to_string(),
+            etag: Some("\"v1\"".to_string()),
+            stored_at: 0,
+            expires_at: 10,
+        }
+    }
+
+    #[test]
+    fn test_memory_cache_evicts_least_recently_used() {
+        let cache = MemoryCache::new(2);
+        cache.put(response("a"));
+        cache.put(response("b"));
+        assert!(cache.get("a").is_some());
+        cache.put(response("c"));
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+    }
+
+    #[test]
+    fn test_disk_cache_round_trip() {
+        let dir = env::temp_dir().join(format!("airly_rs_cache_{}", std::process::id()));
+        DiskCache::new(&dir).unwrap().put(response("http://a/v2/meta/indexes"));
+        let cache = DiskCache::new(&dir).unwrap();
+        assert_eq!(cache.get("http://a/v2/meta/indexes"), Some(response("http://a/v2/meta/indexes")));
+        assert!(cache.get("http://a/v2/meta/measurements").is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_policy_expiry() {
+        let policy = CachePolicy::default();
+        let now = 1_575_885_600 + 1234;
+        assert_eq!(policy.expires_at(&Endpoint::Indices, now), now + 3 * 86_400);
+        assert_eq!(policy.expires_at(&Endpoint::Installation(18), now), now + 6 * 3600);
+        let measurements = Endpoint::InstallationMeasurements {
+            id: 18,
+            index_type: "AIRLY_CAQI".to_string(),
+            include_wind: false,
+        };
+        assert_eq!(policy.expires_at(&measurements, now), 1_575_885_600 + 3600);
+    }
+
+    fn caqi() -> IndexType {
+        IndexType {
+            name: Some("AIRLY_CAQI".to_string()),
+            level: None,
+        }
+    }
+
+    #[test]
+    fn test_client_served_from_cache_until_full_hour() {
+        let server = StubServer::start(|_| (200, MEASUREMENTS_JSON.to_string()));
+        let clock = FakeClock::new(HOUR + 1800);
+        let cache = ResponseCache::new(MemoryCache::default()).with_clock(clock.clone());
+        let client = AirlyClient::new(API_KEY.to_string())
+            .unwrap()
+            .with_base_url(&server.base_url())
+            .with_cache(cache);
+        client.clone().get_installation_measurements_without_wind(18, caqi()).unwrap();
+        clock.advance(1799);
+        client.clone().get_installation_measurements_without_wind(18, caqi()).unwrap();
+        assert_eq!(server.requests().len(), 1);
+        client.clone().get_installation_measurements_with_wind(18, caqi()).unwrap();
+        clock.advance(1);
+        client.clone().get_installation_measurements_without_wind(18, caqi()).unwrap();
+        assert_eq!(server.requests().len(), 3);
+        let stats = client.cache().unwrap().stats();
+        assert_eq!(
+            stats,
+            CacheStats {
+                hits: 1,
+                misses: 3,
+                revalidated: 0,
+                stale: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_expired_response_revalidated_with_etag() {
+        let server = StubServer::start_with(|request| {
+            let etag = vec![("ETag".to_string(), "\"v1\"".to_string())];
+            match request.header("if-none-match") {
+                Some("\"v1\"") => (304, etag, String::new()),
+                _ => (200, etag, INSTALLATION_JSON.to_string()),
+            }
+        });
+        let clock = FakeClock::new(HOUR);
+        let cache = ResponseCache::new(MemoryCache::default()).with_clock(clock.clone());
+        let client = AirlyClient::new(API_KEY.to_string())
+            .unwrap()
+            .with_base_url(&server.base_url())
+            .with_cache(cache);
+        client.clone().get_installation(18).unwrap();
+        clock.advance(6 * 3600);
+        let installation = client.clone().get_installation(18).unwrap();
+        assert_eq!(installation.id, 18);
+        assert_eq!(server.received()[1].header("if-none-match"), Some("\"v1\""));
+        client.clone().get_installation(18).unwrap();
+        assert_eq!(server.requests().len(), 2);
+        assert_eq!(client.cache().unwrap().stats().revalidated, 1);
+    }
+
+    fn measurements_endpoint() -> Endpoint {
+        Endpoint::InstallationMeasurements {
+            id: 18,
+            index_type: "AIRLY_CAQI".to_string(),
+            include_wind: false,
+        }
+    }
+
+    #[test]
+    fn test_stale_response_served_when_api_down() {
+        let mut server = StubServer::start(|_| (200, MEASUREMENTS_JSON.to_string()));
+        let clock = FakeClock::new(HOUR + 600);
+        let cache = ResponseCache::new(MemoryCache::default()).with_clock(clock.clone());
+        let client = AirlyClient::new(API_KEY.to_string())
+            .unwrap()
+            .with_base_url(&server.base_url())
+            .with_cache(cache);
+        let fetched = client.request_with_freshness::<Measurements>(&measurements_endpoint()).unwrap();
+        assert_eq!(fetched.freshness, Freshness::Fresh);
+        server.stop();
+        clock.advance(2 * 3600);
+        let fetched = client.request_with_freshness::<Measurements>(&measurements_endpoint()).unwrap();
+        assert_eq!(fetched.freshness, Freshness::Stale { age_secs: 7200 });
+        assert!(fetched.value.current.is_some());
+        assert!(client.request::<Installation>(&Endpoint::Installation(18)).is_err());
+        assert_eq!(client.cache().unwrap().stats().stale, 1);
+    }
+
+    #[test]
+    fn test_stale_response_served_when_quota_exceeded() {
+        let calls = AtomicUsize::new(0);
+        let server = StubServer::start(move |_| match calls.fetch_add(1, Ordering::SeqCst) {
+            0 => (200, INSTALLATION_JSON.to_string()),
+            _ => (429, r#"{"message": "Rate limit exceeded"}"#.to_string()),
+        });
+        let clock = FakeClock::new(HOUR);
+        let cache = ResponseCache::new(MemoryCache::default()).with_clock(clock.clone());
+        let client = AirlyClient::new(API_KEY.to_string())
+            .unwrap()
+            .with_base_url(&server.base_url())
+            .with_cache(cache);
+        client.clone().get_installation(18).unwrap();
+        clock.advance(7 * 3600);
+        let fetched = client.request_with_freshness::<Installation>(&Endpoint::Installation(18)).unwrap();
+        assert!(fetched.is_stale());
+        assert_eq!(fetched.value.id, 18);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[test]
+    fn test_offline_mode_never_touches_network() {
+        let server = StubServer::start(|_| (200, MEASUREMENTS_JSON.to_string()));
+        let clock = FakeClock::new(HOUR);
+        let cache = ResponseCache::new(MemoryCache::default()).with_clock(clock.clone());
+        let client = AirlyClient::new(API_KEY.to_string())
+            .unwrap()
+            .with_base_url(&server.base_url())
+            .with_cache(cache);
+        client.request::<Measurements>(&measurements_endpoint()).unwrap();
+        let offline = client.with_offline(true);
+        assert!(offline.is_offline());
+        let fetched = offline.request_with_freshness::<Measurements>(&measurements_endpoint()).unwrap();
+        assert_eq!(fetched.freshness, Freshness::Fresh);
+        clock.advance(3 * 86_400);
+        let fetched = offline.request_with_freshness::<Measurements>(&measurements_endpoint()).unwrap();
+        assert_eq!(fetched.freshness, Freshness::Stale { age_secs: 3 * 86_400 });
+        assert!(offline.request::<Installation>(&Endpoint::Installation(18)).is_err());
+        let uncached = AirlyClient::new(API_KEY.to_string())
+            .unwrap()
+            .with_base_url(&server.base_url())
+            .with_offline(true);
+        assert!(uncached.request::<Measurements>(&measurements_endpoint()).is_err());
+        assert_eq!(server.requests().len(), 1);
+    }
+}
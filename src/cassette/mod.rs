@@ -0,0 +1,231 @@
+//! Record and replay of api responses, lets tests run without network and api key
+
+use crate::client::RawResponse;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Environment variable switching Cassette::from_env to record mode when set to non-empty value
+pub const RECORD_ENV: &str = "AIRLY_RECORD";
+
+/// Whether cassette stores or serves responses
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Requests go to the api, every response is stored in cassette file
+    Record,
+    /// Responses are served from cassette file, network is never touched
+    Replay,
+}
+
+/// Recorded request with its response
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Interaction {
+    /// Path with query relative to api root, api key is sent in header so it is never recorded
+    pub path: String,
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// Response body, kept as json value so cassette files stay readable
+    pub body: serde_json::Value,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    interactions: Vec<Interaction>,
+    served: HashMap<String, usize>,
+}
+
+/// Set of interactions stored in json file
+#[derive(Debug)]
+pub struct Cassette {
+    file: PathBuf,
+    mode: Mode,
+    state: Mutex<State>,
+}
+
+impl Cassette {
+    /// Constructs empty Cassette in record mode, file is overwritten with every recorded interaction
+    ///
+    /// # Arguments:
+    /// * file - path of cassette json file
+    ///
+    pub fn record<P: Into<PathBuf>>(file: P) -> Self {
+        Self {
+            file: file.into(),
+            mode: Mode::Record,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Loads Cassette in replay mode
+    ///
+    /// # Arguments:
+    /// * file - path of cassette json file
+    ///
+    /// # Returns Cassette if file holds recorded interactions Error otherwise
+    ///
+    pub fn replay<P: Into<PathBuf>>(file: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = file.into();
+        let interactions: Vec<Interaction> = serde_json::from_str(&fs::read_to_string(&file)?)?;
+        Ok(Self {
+            file,
+            mode: Mode::Replay,
+            state: Mutex::new(State {
+                interactions,
+                served: HashMap::new(),
+            }),
+        })
+    }
+
+    /// Records if AIRLY_RECORD environment variable is set, replays otherwise
+    ///
+    /// # Arguments:
+    /// * file - path of cassette json file
+    ///
+    /// # Returns Cassette in selected mode, Error if replayed file cannot be loaded
+    ///
+    pub fn from_env<P: Into<PathBuf>>(file: P) -> Result<Self, Box<dyn std::error::Error>> {
+        match env::var(RECORD_ENV) {
+            Ok(value) if !value.is_empty() => Ok(Cassette::record(file)),
+            _ => Cassette::replay(file),
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// # Returns interactions recorded or loaded so far
+    pub fn interactions(&self) -> Vec<Interaction> {
+        self.state.lock().unwrap().interactions.clone()
+    }
+
+    /// Serves response recorded for path, repeated requests get following recordings of the same path,
+    /// the last one is repeated once they run out
+    pub(crate) fn play(&self, path: &str) -> Result<RawResponse, Box<dyn std::error::Error>> {
+        let mut state = self.state.lock().unwrap();
+        let recorded: Vec<&Interaction> = state
+            .interactions
+            .iter()
+            .filter(|interaction| interaction.path == path)
+            .collect();
+        let served = state.served.get(path).copied().unwrap_or(0);
+        let interaction = match recorded.get(served).or_else(|| recorded.last()) {
+            Some(interaction) => (*interaction).clone(),
+            None => {
+                return Err(Box::new(Error::new(
+                    ErrorKind::NotFound,
+                    format!("No interaction recorded for {} in {}", path, self.file.display()),
+                )))
+            }
+        };
+        state.served.insert(path.to_string(), served + 1);
+        let body = match interaction.body {
+            serde_json::Value::String(text) => text,
+            json => json.to_string(),
+        };
+        Ok(RawResponse {
+            status: interaction.status,
+            etag: interaction.etag,
+            body,
+        })
+    }
+
+    /// Appends response to cassette and saves the whole cassette file
+    pub(crate) fn store(&self, path: &str, response: &RawResponse) -> Result<(), Box<dyn std::error::Error>> {
+        let mut state = self.state.lock().unwrap();
+        state.interactions.push(Interaction {
+            path: path.to_string(),
+            status: response.status,
+            etag: response.etag.clone(),
+            body: serde_json::from_str(&response.body)
+                .unwrap_or_else(|_| serde_json::Value::String(response.body.clone())),
+        });
+        if let Some(dir) = self.file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.file, serde_json::to_string_pretty(&state.interactions)?)?;
+        Ok(())
+    }
+}
+
+/// # Returns path of synthetic cassette shipped with the crate, e.g. fixture("installation")
+pub fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures")
+        .join("cassettes")
+        .join(format!("{}.json", name))
+}
+
+#[cfg(test)]
+mod test_cassette {
+    use super::{fixture, Cassette, Mode};
+    use crate::client::{AirlyClient, Endpoint};
+    use crate::stub::{StubServer, INSTALLATION_JSON};
+    use crate::types::Installation;
+    use std::env;
+
+    const API_KEY: &str = "00000000000000000000000000000000";
+
+    #[test]
+    fn test_record_then_replay_without_network() {
+        let file = env::temp_dir().join(format!("airly_rs_cassette_{}.json", std::process::id()));
+        let mut server = StubServer::start(|url| match url {
+            "/v2/installations/18" => (200, INSTALLATION_JSON.to_string()),
+            _ => (404, r#"{"errorCode": "NOT_FOUND"}"#.to_string()),
+        });
+        let recording = AirlyClient::new(API_KEY.to_string())
+            .unwrap()
+            .with_base_url(&server.base_url())
+            .with_cassette(Cassette::record(&file));
+        recording.clone().get_installation(18).unwrap();
+        assert!(recording.request::<Installation>(&Endpoint::Installation(19)).is_err());
+        server.stop();
+
+        let cassette = Cassette::replay(&file).unwrap();
+        assert_eq!(cassette.mode(), Mode::Replay);
+        assert_eq!(cassette.interactions().len(), 2);
+        assert!(!cassette.interactions()[0].body.is_string());
+        let replaying = AirlyClient::new(API_KEY.to_string())
+            .unwrap()
+            .with_base_url(&server.base_url())
+            .with_cassette(cassette);
+        assert_eq!(replaying.clone().get_installation(18).unwrap().id, 18);
+        assert!(replaying.request::<Installation>(&Endpoint::Installation(20)).is_err());
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_fixtures_cover_every_endpoint() {
+        for name in &[
+            "installation",
+            "nearest",
+            "indices",
+            "meta_measurements",
+            "installation_measurements",
+            "measurements_nearest",
+            "measurements_point",
+        ] {
+            let cassette = Cassette::replay(fixture(name)).unwrap();
+            assert!(cassette.interactions().iter().all(|i| i.status == 200), "{}", name);
+        }
+        let indices = AirlyClient::new(API_KEY.to_string())
+            .unwrap()
+            .with_cassette(Cassette::replay(fixture("indices")).unwrap())
+            .get_indices()
+            .unwrap();
+        assert_eq!(indices.len(), 3);
+        for index in indices {
+            let level = index.level.unwrap();
+            assert_eq!(level.level.as_deref(), Some("VERY_LOW"));
+            assert!(level.max_value.is_some());
+        }
+    }
+}
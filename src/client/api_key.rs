@@ -0,0 +1,200 @@
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::process::Command;
+
+const API_KEY_LEN: usize = 32;
+const ERR_API_KEY: &str = "Wrong api key length";
+const ERR_API_KEY_CHARS: &str = "Api key may contain ascii letters and digits only";
+const ENV_KEY: &str = "AIRLY_API_KEY";
+const ENV_KEY_FILE: &str = "AIRLY_API_KEY_FILE";
+const ENV_KEY_COMMAND: &str = "AIRLY_API_KEY_COMMAND";
+const DOTENV_FILE: &str = ".env";
+
+/// Airly api key, never printed by Debug or Display, so structs holding it can be logged safely
+#[derive(Clone, PartialEq)]
+pub struct ApiKey(String);
+
+impl ApiKey {
+    /// Constructs ApiKey
+    ///
+    /// # Arguments:
+    /// * api_key - personal api key that can be obtained from https://developer.airly.eu/login
+    ///
+    /// # Returns ApiKey if key is 32 ascii letters and digits Error otherwise
+    ///
+    pub fn new(api_key: String) -> Result<Self, Box<dyn std::error::Error>> {
+        if api_key.len() != API_KEY_LEN {
+            return Err(Box::new(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{}, expected: {}, got: {}", ERR_API_KEY, API_KEY_LEN, api_key.len()),
+            )));
+        }
+        if !api_key.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            return Err(Box::new(Error::new(ErrorKind::InvalidData, ERR_API_KEY_CHARS)));
+        }
+        Ok(ApiKey(api_key))
+    }
+
+    /// Reads key from environment variable
+    ///
+    /// # Arguments:
+    /// * name - name of the variable, e.g. AIRLY_API_KEY
+    ///
+    /// # Returns ApiKey if variable is set and valid Error otherwise
+    ///
+    pub fn from_env(name: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let value = env::var(name).map_err(|err| format!("{}: {}", name, err))?;
+        ApiKey::new(value.trim().to_string())
+    }
+
+    /// Reads key from dotenv file, variables already set in environment take precedence
+    ///
+    /// # Arguments:
+    /// * path - path to dotenv file
+    /// * name - name of the variable, e.g. AIRLY_API_KEY
+    ///
+    /// # Returns ApiKey if variable is set and valid Error otherwise
+    ///
+    pub fn from_dotenv<P: AsRef<Path>>(path: P, name: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        dotenv::from_path(path.as_ref()).map_err(|e| e.to_string())?;
+        ApiKey::from_env(name)
+    }
+
+    /// Reads key from file holding nothing but the key, e.g. docker or kubernetes secret
+    ///
+    /// # Arguments:
+    /// * path - path to the file, surrounding whitespace is ignored
+    ///
+    /// # Returns ApiKey if file is readable and holds valid key Error otherwise
+    ///
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = fs::read_to_string(path.as_ref())?;
+        ApiKey::new(text.trim().to_string())
+    }
+
+    /// Reads key from standard output of command, e.g. password manager cli
+    ///
+    /// # Arguments:
+    /// * program - program to run
+    /// * args - its arguments
+    ///
+    /// # Returns ApiKey if command succeeds and prints valid key Error otherwise
+    ///
+    pub fn from_command(program: &str, args: &[&str]) -> Result<Self, Box<dyn std::error::Error>> {
+        let output = Command::new(program).args(args).output()?;
+        if !output.status.success() {
+            return Err(format!("Api key command {} failed with {}", program, output.status).into());
+        }
+        ApiKey::new(String::from_utf8(output.stdout)?.trim().to_string())
+    }
+
+    /// Looks for key in AIRLY_API_KEY, then in file named by AIRLY_API_KEY_FILE, then in output of
+    /// shell command given in AIRLY_API_KEY_COMMAND, then in AIRLY_API_KEY of .env file
+    ///
+    /// # Returns first key found, Error if none is found or found one is invalid
+    ///
+    pub fn discover() -> Result<Self, Box<dyn std::error::Error>> {
+        if env::var_os(ENV_KEY).is_some() {
+            return ApiKey::from_env(ENV_KEY);
+        }
+        if let Ok(path) = env::var(ENV_KEY_FILE) {
+            return ApiKey::from_file(path);
+        }
+        if let Ok(command) = env::var(ENV_KEY_COMMAND) {
+            return ApiKey::from_command("sh", &["-c", &command]);
+        }
+        if Path::new(DOTENV_FILE).exists() {
+            return ApiKey::from_dotenv(DOTENV_FILE, ENV_KEY);
+        }
+        Err(format!("Api key not found, set {}, {} or {}", ENV_KEY, ENV_KEY_FILE, ENV_KEY_COMMAND).into())
+    }
+
+    /// # Returns the key itself, to be sent to api only
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    /// # Returns last 4 characters of the key, enough to tell keys apart in logs
+    pub fn masked(&self) -> String {
+        format!("...{}", &self.0[API_KEY_LEN - 4..])
+    }
+}
+
+impl fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ApiKey({})", self.masked())
+    }
+}
+
+impl fmt::Display for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.masked())
+    }
+}
+
+/// Serializes masked key only, so configs holding it can be dumped safely, they do not read back
+impl Serialize for ApiKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.masked())
+    }
+}
+
+impl<'de> Deserialize<'de> for ApiKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        ApiKey::new(value).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test_api_key {
+    use super::ApiKey;
+    use crate::client::AirlyClient;
+    use crate::transport::HttpRequest;
+    use std::env;
+    use std::fs;
+
+    const API_KEY: &str = "0123456789abcdefABCDEF0123456789";
+
+    #[test]
+    fn test_validation_and_redaction() {
+        assert!(ApiKey::new("too short".to_string()).is_err());
+        let err = ApiKey::new(format!("{} ", &API_KEY[1..])).unwrap_err();
+        assert!(err.to_string().contains("letters and digits"));
+        let key = ApiKey::new(API_KEY.to_string()).unwrap();
+        assert_eq!(key.expose(), API_KEY);
+        assert_eq!(format!("{:?} {}", key, key), "ApiKey(...6789) ...6789");
+        let client = AirlyClient::from_api_key(key.clone());
+        assert!(!format!("{:?}", client).contains(API_KEY));
+        let request = HttpRequest::get("http://127.0.0.1/v2").with_sensitive_header("apikey", key.expose());
+        assert_eq!(request.header("apikey"), Some(API_KEY));
+        assert!(!format!("{:?}", request).contains(API_KEY));
+    }
+
+    #[test]
+    fn test_sources() {
+        let dir = env::temp_dir().join(format!("airly_rs_api_key_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("airly.key");
+        fs::write(&file, format!("{}\n", API_KEY)).unwrap();
+        assert_eq!(ApiKey::from_file(&file).unwrap().expose(), API_KEY);
+        let dotenv = dir.join(".env");
+        fs::write(&dotenv, format!("AIRLY_TEST_DOTENV_KEY={}\n", API_KEY)).unwrap();
+        assert_eq!(ApiKey::from_dotenv(&dotenv, "AIRLY_TEST_DOTENV_KEY").unwrap().expose(), API_KEY);
+        env::set_var("AIRLY_TEST_ENV_KEY", API_KEY);
+        assert_eq!(ApiKey::from_env("AIRLY_TEST_ENV_KEY").unwrap().expose(), API_KEY);
+        assert!(ApiKey::from_env("AIRLY_TEST_MISSING_KEY").is_err());
+        assert_eq!(ApiKey::from_command("echo", &[API_KEY]).unwrap().expose(), API_KEY);
+        assert!(ApiKey::from_command("false", &[]).is_err());
+        let key: ApiKey = serde_json::from_str(&format!("\"{}\"", API_KEY)).unwrap();
+        assert_eq!(key.expose(), API_KEY);
+        assert!(serde_json::from_str::<ApiKey>("\"short\"").is_err());
+        assert_eq!(serde_json::to_string(&key).unwrap(), "\"...6789\"");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,256 @@
+use super::{index_type_name, AirlyClient, Endpoint};
+use crate::transport::Transport;
+use crate::types;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Result of single batch item, errors are kept per item so one failure does not sink the batch
+pub type ItemResult<R> = Result<R, Box<dyn std::error::Error>>;
+
+/// Results of batch paired with ids or points they were requested for
+pub type Batch<K, R> = Vec<(K, ItemResult<R>)>;
+
+/// Settings of batch requests, api quota is set on client with AirlyClient::with_rate_limit
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    concurrency: usize,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+}
+
+impl BatchOptions {
+    /// Sets max number of requests in flight, 4 by default
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+}
+
+impl<T: Transport> AirlyClient<T> {
+    /// Fetches many endpoints concurrently, identical endpoints are requested once, also when
+    /// other calls of this client or its clones request them meanwhile
+    ///
+    /// Requests sent to api wait for slot in quota of client rate limit, shared with other batches and calls,
+    /// responses served from cache take none
+    ///
+    /// # Arguments:
+    /// * endpoints - endpoints to fetch
+    /// * options - concurrency and rate limits
+    ///
+    /// # Returns result of every endpoint in the order endpoints were given
+    ///
+    pub fn request_batch<R>(&self, endpoints: &[Endpoint], options: &BatchOptions) -> Vec<ItemResult<R>>
+    where
+        R: DeserializeOwned + Clone + Send,
+    {
+        let mut unique: Vec<&Endpoint> = Vec::new();
+        let mut seen: HashMap<&Endpoint, usize> = HashMap::new();
+        let positions: Vec<usize> = endpoints
+            .iter()
+            .map(|endpoint| {
+                *seen.entry(endpoint).or_insert_with(|| {
+                    unique.push(endpoint);
+                    unique.len() - 1
+                })
+            })
+            .collect();
+        let next = AtomicUsize::new(0);
+        let results: Mutex<Vec<Option<Result<R, String>>>> = Mutex::new(vec![None; unique.len()]);
+        thread::scope(|scope| {
+            for _ in 0..options.concurrency.min(unique.len()) {
+                scope.spawn(|| loop {
+                    let at = next.fetch_add(1, Ordering::SeqCst);
+                    if at >= unique.len() {
+                        break;
+                    }
+                    let result = self.request::<R>(unique[at]).map_err(|err| err.to_string());
+                    results.lock().unwrap()[at] = Some(result);
+                });
+            }
+        });
+        let results = results.into_inner().unwrap();
+        positions
+            .into_iter()
+            .map(|at| match &results[at] {
+                Some(Ok(value)) => Ok(value.clone()),
+                Some(Err(err)) => Err(err.clone().into()),
+                None => Err("Batch item was not fetched".into()),
+            })
+            .collect()
+    }
+
+    /// Fetches measurements of many installations concurrently
+    ///
+    /// # Arguments:
+    /// * ids - ids of installations, duplicates are fetched once
+    /// * index_type - type of index of the installation measurements
+    /// * include_wind - whether wind values are included
+    /// * options - concurrency and rate limits
+    ///
+    /// # Returns measurements or error of every installation in the order ids were given,
+    /// Error if index_type has no name
+    ///
+    pub fn get_installations_measurements(
+        &self,
+        ids: &[u32],
+        index_type: types::IndexType,
+        include_wind: bool,
+        options: &BatchOptions,
+    ) -> Result<Batch<u32, types::Measurements>, Box<dyn std::error::Error>> {
+        let index_type = index_type_name(index_type)?;
+        let endpoints: Vec<Endpoint> = ids
+            .iter()
+            .map(|id| Endpoint::InstallationMeasurements {
+                id: *id,
+                index_type: index_type.clone(),
+                include_wind,
+            })
+            .collect();
+        let results = self.request_batch(&endpoints, options);
+        Ok(ids.iter().cloned().zip(results).collect())
+    }
+
+    /// Fetches interpolated measurements of many points concurrently
+    ///
+    /// # Arguments:
+    /// * points - locations on map, duplicates are fetched once
+    /// * index_type - type of index of the measurements
+    /// * options - concurrency and rate limits
+    ///
+    /// # Returns measurements or error of every point in the order points were given,
+    /// Error if index_type has no name
+    ///
+    pub fn get_points_measurements(
+        &self,
+        points: &[types::GeoPoint],
+        index_type: types::IndexType,
+        options: &BatchOptions,
+    ) -> Result<Batch<types::GeoPoint, types::Measurements>, Box<dyn std::error::Error>> {
+        let index_type = index_type_name(index_type)?;
+        let endpoints: Vec<Endpoint> = points
+            .iter()
+            .map(|point| Endpoint::MeasurementsPoint {
+                point: *point,
+                index_type: index_type.clone(),
+            })
+            .collect();
+        let results = self.request_batch(&endpoints, options);
+        Ok(points.iter().cloned().zip(results).collect())
+    }
+}
+
+#[cfg(test)]
+mod test_batch {
+    use super::BatchOptions;
+    use crate::cache::{MemoryCache, ResponseCache};
+    use crate::client::{AirlyClient, RateLimit};
+    use crate::clock::{Clock, FakeClock};
+    use crate::collector::Quota;
+    use crate::stub::{StubServer, MEASUREMENTS_JSON};
+    use crate::types::{GeoPoint, IndexType};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    const API_KEY: &str = "00000000000000000000000000000000";
+
+    fn caqi() -> IndexType {
+        IndexType {
+            name: Some("AIRLY_CAQI".to_string()),
+            level: None,
+        }
+    }
+
+    #[test]
+    fn test_bounded_concurrency_dedupe_and_per_item_errors() {
+        let (in_flight, max_in_flight) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let (current, max) = (in_flight.clone(), max_in_flight.clone());
+        let server = StubServer::start(move |url| {
+            let now = current.fetch_add(1, Ordering::SeqCst) + 1;
+            max.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(50));
+            current.fetch_sub(1, Ordering::SeqCst);
+            if url.ends_with("installationId=13") {
+                (404, r#"{"errorCode": "INSTALLATION_NOT_FOUND"}"#.to_string())
+            } else {
+                (200, MEASUREMENTS_JSON.to_string())
+            }
+        });
+        let client = AirlyClient::new(API_KEY.to_string())
+            .unwrap()
+            .with_base_url(&server.base_url());
+        let ids = [18, 13, 20, 18, 21, 22, 23];
+        let options = BatchOptions::default().with_concurrency(3);
+        let results = client
+            .get_installations_measurements(&ids, caqi(), false, &options)
+            .unwrap();
+        assert_eq!(results.iter().map(|(id, _)| *id).collect::<Vec<u32>>(), ids.to_vec());
+        assert!(results[1].1.is_err());
+        assert_eq!(results.iter().filter(|(_, result)| result.is_ok()).count(), 6);
+        assert_eq!(server.requests().len(), 6);
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_rate_limit_waits_or_fails_items() {
+        let server = StubServer::start(|_| (200, MEASUREMENTS_JSON.to_string()));
+        let points: Vec<GeoPoint> = (0..5)
+            .map(|i| GeoPoint::new(50.0 + i as f32 / 100.0, 19.9).unwrap())
+            .collect();
+        let quota = Quota {
+            per_minute: 2,
+            per_day: 100,
+        };
+        let clock = FakeClock::new(1_575_885_600);
+        let waiting = AirlyClient::new(API_KEY.to_string())
+            .unwrap()
+            .with_base_url(&server.base_url())
+            .with_rate_limit(RateLimit::new(quota).with_max_wait_secs(120).with_clock(clock.clone()));
+        let results = waiting.get_points_measurements(&points, caqi(), &BatchOptions::default()).unwrap();
+        assert!(results.iter().all(|(_, result)| result.is_ok()));
+        assert_eq!(clock.now(), 1_575_885_600 + 120);
+        assert_eq!(server.requests().len(), 5);
+    }
+
+    #[test]
+    fn test_rate_limit_shared_and_cache_hits_free() {
+        let server = StubServer::start(|_| (200, MEASUREMENTS_JSON.to_string()));
+        let points: Vec<GeoPoint> = (0..3)
+            .map(|i| GeoPoint::new(50.0 + i as f32 / 100.0, 19.9).unwrap())
+            .collect();
+        let quota = Quota {
+            per_minute: 2,
+            per_day: 100,
+        };
+        let clock = FakeClock::new(1_575_885_600);
+        let client = AirlyClient::new(API_KEY.to_string())
+            .unwrap()
+            .with_base_url(&server.base_url())
+            .with_cache(ResponseCache::new(MemoryCache::default()).with_clock(clock.clone()))
+            .with_rate_limit(RateLimit::new(quota).with_max_wait_secs(0).with_clock(clock.clone()));
+        let options = BatchOptions::default().with_concurrency(1);
+        let results = client.get_points_measurements(&points, caqi(), &options).unwrap();
+        assert_eq!(results.iter().filter(|(_, result)| result.is_err()).count(), 1);
+        assert!(results[2].1.as_ref().unwrap_err().to_string().contains("Rate limit"));
+        // quota is shared with single requests of clones and with following batches
+        assert!(client.clone().get_measurements_point(caqi(), points[2]).is_err());
+        let cached = client.get_points_measurements(&points[..2], caqi(), &options).unwrap();
+        assert!(cached.iter().all(|(_, result)| result.is_ok()));
+        assert_eq!(server.requests().len(), 2);
+        clock.advance(60);
+        assert!(client.clone().get_measurements_point(caqi(), points[2]).is_ok());
+        assert_eq!(server.requests().len(), 3);
+    }
+}
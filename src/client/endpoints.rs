@@ -1,3 +1,5 @@
+use crate::types;
+
 pub const BASE_URL: &str = "https://airapi.airly.eu/v2";
 pub const INSTALLATIONS_URL: &str = "installations";
 pub const INSTALLATION_URL: &str = "installation";
@@ -6,3 +8,147 @@ pub const NEAREST_URL: &str = "nearest";
 pub const POINT_URL: &str = "point";
 pub const META_INDEXES_URL: &str = "meta/indexes";
 pub const META_MEASUREMENTS_URL: &str = "meta/measurements";
+
+/// Api endpoint with its parameters, used to compose request url and to decide how long response stays valid
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    /// Installation properties for given id
+    Installation(u32),
+    /// Installations in the circle, at most max_results of them
+    Nearest {
+        circle: types::GeoCircle,
+        max_results: u32,
+    },
+    /// Indexes types
+    Indices,
+    /// Measurement types
+    MetaMeasurements,
+    /// Measurements of installation
+    InstallationMeasurements {
+        id: u32,
+        index_type: String,
+        include_wind: bool,
+    },
+    /// Measurements of installation nearest the circle center
+    MeasurementsNearest {
+        circle: types::GeoCircle,
+        index_type: String,
+    },
+    /// Interpolated measurements for point on map
+    MeasurementsPoint {
+        point: types::GeoPoint,
+        index_type: String,
+    },
+}
+
+/// Group of endpoints with similar update frequency
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EndpointClass {
+    /// Indexes and measurement types, change very rarely
+    Meta,
+    /// Installation properties, change rarely
+    Installation,
+    /// Measurements updated by Airly every hour
+    Measurements,
+}
+
+impl Endpoint {
+    /// # Returns path with query relative to api root
+    pub fn path(&self) -> String {
+        match self {
+            Endpoint::Installation(id) => format!("{}/{}", INSTALLATIONS_URL, id),
+            Endpoint::Nearest {
+                circle,
+                max_results,
+            } => format!(
+                "{}/{}?lat={}&lng={}&maxDistanceKM={}&maxResults={}",
+                INSTALLATIONS_URL,
+                NEAREST_URL,
+                circle.get_point().get_lat(),
+                circle.get_point().get_lng(),
+                circle.get_radius_km(),
+                max_results
+            ),
+            Endpoint::Indices => META_INDEXES_URL.to_string(),
+            Endpoint::MetaMeasurements => META_MEASUREMENTS_URL.to_string(),
+            Endpoint::InstallationMeasurements {
+                id,
+                index_type,
+                include_wind,
+            } => format!(
+                "{}/{}?{}indexType={}&installationId={}",
+                MEASUREMENTS_URL,
+                INSTALLATION_URL,
+                if *include_wind { "includeWind=true&" } else { "" },
+                index_type,
+                id
+            ),
+            Endpoint::MeasurementsNearest { circle, index_type } => format!(
+                "{}/{}?indexType={}&lat={}&lng={}&maxDistanceKM={}",
+                MEASUREMENTS_URL,
+                NEAREST_URL,
+                index_type,
+                circle.get_point().get_lat(),
+                circle.get_point().get_lng(),
+                circle.get_radius_km(),
+            ),
+            Endpoint::MeasurementsPoint { point, index_type } => format!(
+                "{}/{}?indexType={}&lat={}&lng={}",
+                MEASUREMENTS_URL,
+                POINT_URL,
+                index_type,
+                point.get_lat(),
+                point.get_lng(),
+            ),
+        }
+    }
+
+    /// # Returns short name of this endpoint, used in spans and metric labels
+    pub fn name(&self) -> &'static str {
+        match self {
+            Endpoint::Installation(_) => "installation",
+            Endpoint::Nearest { .. } => "installations_nearest",
+            Endpoint::Indices => "meta_indexes",
+            Endpoint::MetaMeasurements => "meta_measurements",
+            Endpoint::InstallationMeasurements { .. } => "measurements_installation",
+            Endpoint::MeasurementsNearest { .. } => "measurements_nearest",
+            Endpoint::MeasurementsPoint { .. } => "measurements_point",
+        }
+    }
+
+    /// # Returns id of installation endpoint is about, if any
+    pub fn installation_id(&self) -> Option<u32> {
+        match self {
+            Endpoint::Installation(id) | Endpoint::InstallationMeasurements { id, .. } => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// # Returns name of requested index, if any
+    pub fn index_type(&self) -> Option<&str> {
+        match self {
+            Endpoint::InstallationMeasurements { index_type, .. }
+            | Endpoint::MeasurementsNearest { index_type, .. }
+            | Endpoint::MeasurementsPoint { index_type, .. } => Some(index_type),
+            _ => None,
+        }
+    }
+
+    /// # Returns location endpoint is about, center of circle for nearest endpoints
+    pub fn point(&self) -> Option<types::GeoPoint> {
+        match self {
+            Endpoint::Nearest { circle, .. } | Endpoint::MeasurementsNearest { circle, .. } => Some(circle.get_point()),
+            Endpoint::MeasurementsPoint { point, .. } => Some(*point),
+            _ => None,
+        }
+    }
+
+    /// # Returns class of this endpoint
+    pub fn class(&self) -> EndpointClass {
+        match self {
+            Endpoint::Indices | Endpoint::MetaMeasurements => EndpointClass::Meta,
+            Endpoint::Installation(_) | Endpoint::Nearest { .. } => EndpointClass::Installation,
+            _ => EndpointClass::Measurements,
+        }
+    }
+}
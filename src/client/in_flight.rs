@@ -0,0 +1,136 @@
+use super::Freshness;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Response body with its freshness, errors are shared with waiting callers as text
+type Shared = Result<(String, Freshness), String>;
+
+#[derive(Default)]
+struct Pending {
+    result: Mutex<Option<Shared>>,
+    done: Condvar,
+}
+
+impl Pending {
+    fn wait(&self) -> Shared {
+        let mut result = self.result.lock().unwrap();
+        while result.is_none() {
+            result = self.done.wait(result).unwrap();
+        }
+        result.clone().unwrap()
+    }
+}
+
+/// Requests being sent by AirlyClient and its clones, keyed by url, identical requests made meanwhile
+/// wait for result of the first one instead of going to api again
+#[derive(Default)]
+pub(crate) struct InFlight {
+    pending: Mutex<HashMap<String, Arc<Pending>>>,
+}
+
+impl fmt::Debug for InFlight {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("InFlight")
+            .field("pending", &self.pending.lock().unwrap().len())
+            .finish()
+    }
+}
+
+/// Publishes result of leading request to waiting callers, also when fetching panicked
+struct Lead<'a> {
+    in_flight: &'a InFlight,
+    key: &'a str,
+    pending: Arc<Pending>,
+    result: Option<Shared>,
+}
+
+impl Drop for Lead<'_> {
+    fn drop(&mut self) {
+        self.in_flight.pending.lock().unwrap().remove(self.key);
+        let result = self
+            .result
+            .take()
+            .unwrap_or_else(|| Err(format!("Request of {} was abandoned", self.key)));
+        *self.pending.result.lock().unwrap() = Some(result);
+        self.pending.done.notify_all();
+    }
+}
+
+impl InFlight {
+    /// Fetches response unless identical request is in flight, then waits for its result
+    ///
+    /// # Arguments:
+    /// * key - url of request, with language when it is not the default one
+    /// * fetch - sends request, called only when no identical request is in flight
+    ///
+    /// # Returns response body with its freshness, Error of own or awaited request
+    ///
+    pub(crate) fn fetch<F>(&self, key: &str, fetch: F) -> Result<(String, Freshness), Box<dyn std::error::Error>>
+    where
+        F: FnOnce() -> Result<(String, Freshness), Box<dyn std::error::Error>>,
+    {
+        let pending = {
+            let mut pending = self.pending.lock().unwrap();
+            if let Some(awaited) = pending.get(key) {
+                let awaited = awaited.clone();
+                drop(pending);
+                return awaited.wait().map_err(Into::into);
+            }
+            let lead = Arc::new(Pending::default());
+            pending.insert(key.to_string(), lead.clone());
+            lead
+        };
+        let mut lead = Lead {
+            in_flight: self,
+            key,
+            pending,
+            result: None,
+        };
+        let result = fetch();
+        lead.result = Some(match &result {
+            Ok(response) => Ok(response.clone()),
+            Err(err) => Err(err.to_string()),
+        });
+        result
+    }
+}
+
+#[cfg(test)]
+mod test_in_flight {
+    use crate::client::AirlyClient;
+    use crate::stub::{StubServer, INSTALLATION_JSON};
+    use std::thread;
+    use std::time::Duration;
+
+    const API_KEY: &str = "00000000000000000000000000000000";
+
+    #[test]
+    fn test_identical_requests_sent_once() {
+        let server = StubServer::start(|url| {
+            thread::sleep(Duration::from_millis(200));
+            match url.ends_with("installations/18") {
+                true => (200, INSTALLATION_JSON.to_string()),
+                false => (404, "{}".to_string()),
+            }
+        });
+        let client = AirlyClient::new(API_KEY.to_string())
+            .unwrap()
+            .with_base_url(&server.base_url());
+        let callers: Vec<_> = [18, 18, 18, 19, 19]
+            .iter()
+            .map(|id| {
+                let client = client.clone();
+                let id = *id;
+                thread::spawn(move || client.get_installation(id).map(|installation| installation.id).map_err(|err| err.to_string()))
+            })
+            .collect();
+        let results: Vec<_> = callers.into_iter().map(|caller| caller.join().unwrap()).collect();
+        assert!(results[..3].iter().all(|result| result == &Ok(18)));
+        assert!(results[3..].iter().all(|result| result.is_err()));
+        assert_eq!(server.requests().len(), 2);
+        // once answered, following request goes to api again
+        assert!(client.get_installation(18).is_ok());
+        assert_eq!(server.requests().len(), 3);
+    }
+}
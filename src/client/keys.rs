@@ -0,0 +1,282 @@
+use super::ApiKey;
+use crate::clock::{Clock, SystemClock};
+use crate::transport::HttpResponse;
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::sync::{Arc, Mutex};
+
+const DEFAULT_UNAUTHORIZED_BENCH_SECS: i64 = 3600;
+const DEFAULT_RATE_LIMITED_BENCH_SECS: i64 = 60;
+const DAY_EXHAUSTED_BENCH_SECS: i64 = 3600;
+const STATUS_UNAUTHORIZED: u16 = 401;
+const STATUS_TOO_MANY_REQUESTS: u16 = 429;
+
+/// Usage and state of single key, key itself is masked
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyUsage {
+    /// Last 4 characters of the key
+    pub key: String,
+    /// Requests sent with the key
+    pub requests: u64,
+    /// Responses with 401 status
+    pub unauthorized: u64,
+    /// Responses with 429 status
+    pub rate_limited: u64,
+    /// Remaining daily requests as reported by last response
+    pub remaining_day: Option<u32>,
+    /// Remaining requests in current minute as reported by last response
+    pub remaining_minute: Option<u32>,
+    /// Unix timestamp in seconds until which key is not used
+    pub benched_until: Option<i64>,
+}
+
+#[derive(Clone)]
+struct KeyState {
+    key: ApiKey,
+    usage: KeyUsage,
+}
+
+/// Api keys requests are distributed across, by remaining quota reported in X-RateLimit headers.
+/// Keys answered with 401 or 429 are benched for a while. Shared by clones of the client.
+pub struct KeyPool {
+    keys: Mutex<Vec<KeyState>>,
+    clock: Arc<dyn Clock>,
+    unauthorized_bench_secs: i64,
+}
+
+impl fmt::Debug for KeyPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("KeyPool").field("keys", &self.usage()).finish()
+    }
+}
+
+impl KeyPool {
+    /// Constructs KeyPool
+    ///
+    /// # Arguments:
+    /// * api_keys - personal api keys, at least one
+    ///
+    /// # Returns KeyPool if at least one key is given Error otherwise
+    ///
+    pub fn new(api_keys: Vec<ApiKey>) -> Result<Self, Box<dyn std::error::Error>> {
+        if api_keys.is_empty() {
+            return Err(Box::new(Error::new(ErrorKind::InvalidInput, "Key pool needs at least one api key")));
+        }
+        Ok(KeyPool::from_keys(api_keys))
+    }
+
+    /// Pool of the only key client was constructed with
+    pub(crate) fn single(api_key: ApiKey) -> Self {
+        KeyPool::from_keys(vec![api_key])
+    }
+
+    fn from_keys(api_keys: Vec<ApiKey>) -> Self {
+        let keys = api_keys
+            .into_iter()
+            .map(|key| KeyState {
+                usage: KeyUsage {
+                    key: key.masked(),
+                    requests: 0,
+                    unauthorized: 0,
+                    rate_limited: 0,
+                    remaining_day: None,
+                    remaining_minute: None,
+                    benched_until: None,
+                },
+                key,
+            })
+            .collect();
+        Self {
+            keys: Mutex::new(keys),
+            clock: Arc::new(SystemClock),
+            unauthorized_bench_secs: DEFAULT_UNAUTHORIZED_BENCH_SECS,
+        }
+    }
+
+    /// Sets time source of benching, e.g. FakeClock in tests
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Sets how long key answered with 401 is not used, 1 hour by default
+    pub fn with_unauthorized_bench_secs(mut self, secs: i64) -> Self {
+        self.unauthorized_bench_secs = secs;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// # Returns usage of every key in the order keys were given
+    pub fn usage(&self) -> Vec<KeyUsage> {
+        self.keys
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|state| state.usage.clone())
+            .collect()
+    }
+
+    /// # Returns true if at least one key is not benched
+    pub fn has_available(&self) -> bool {
+        let now = self.clock.now();
+        self.keys.lock().unwrap().iter().any(|state| !benched(state, now))
+    }
+
+    /// Picks key with the most remaining quota among keys not benched, least used one on a tie
+    ///
+    /// # Returns index and value of the key, Error if all keys are benched
+    ///
+    pub(crate) fn pick(&self) -> Result<(usize, ApiKey), Box<dyn std::error::Error>> {
+        let now = self.clock.now();
+        let mut keys = self.keys.lock().unwrap();
+        let picked = keys
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, state)| !benched(state, now))
+            .max_by_key(|(_, state)| {
+                let usage = &state.usage;
+                let minute_left = usage.remaining_minute != Some(0);
+                (
+                    minute_left,
+                    usage.remaining_day.unwrap_or(u32::MAX),
+                    std::cmp::Reverse(usage.requests),
+                )
+            })
+            .map(|(index, _)| index);
+        match picked {
+            Some(index) => {
+                keys[index].usage.requests += 1;
+                Ok((index, keys[index].key.clone()))
+            }
+            None => {
+                let until = keys.iter().filter_map(|state| state.usage.benched_until).min();
+                Err(format!("All api keys are benched, first one returns at {:?}", until).into())
+            }
+        }
+    }
+
+    /// Updates key state from response, benches key answered with 401 or 429
+    ///
+    /// # Returns true if key got benched
+    ///
+    pub(crate) fn report(&self, index: usize, response: &HttpResponse) -> bool {
+        let now = self.clock.now();
+        let mut keys = self.keys.lock().unwrap();
+        let usage = &mut keys[index].usage;
+        let header = |name: &str| response.header(name).and_then(|value| value.trim().parse::<u32>().ok());
+        if let Some(remaining) = header("X-RateLimit-Remaining-day") {
+            usage.remaining_day = Some(remaining);
+        }
+        if let Some(remaining) = header("X-RateLimit-Remaining-minute") {
+            usage.remaining_minute = Some(remaining);
+        }
+        let bench_secs = match response.status {
+            STATUS_UNAUTHORIZED => {
+                usage.unauthorized += 1;
+                self.unauthorized_bench_secs
+            }
+            STATUS_TOO_MANY_REQUESTS => {
+                usage.rate_limited += 1;
+                match header("Retry-After") {
+                    Some(secs) => i64::from(secs),
+                    None if usage.remaining_day == Some(0) => DAY_EXHAUSTED_BENCH_SECS,
+                    None => DEFAULT_RATE_LIMITED_BENCH_SECS,
+                }
+            }
+            _ => return false,
+        };
+        usage.benched_until = Some(now + bench_secs);
+        warn!("Api key {} benched for {} seconds after status {}", usage.key, bench_secs, response.status);
+        true
+    }
+}
+
+fn benched(state: &KeyState, now: i64) -> bool {
+    matches!(state.usage.benched_until, Some(until) if until > now)
+}
+
+#[cfg(test)]
+mod test_keys {
+    use super::KeyPool;
+    use crate::client::{AirlyClient, ApiKey};
+    use crate::clock::FakeClock;
+    use crate::collector::Quota;
+    use crate::mock::MockAirly;
+    use crate::stub::INSTALLATION_JSON;
+    use crate::transport::{DefaultTransport, HttpResponse};
+
+    const KEY_A: &str = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    const KEY_B: &str = "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+    const KEY_REVOKED: &str = "cccccccccccccccccccccccccccccccc";
+
+    fn pool(clock: &FakeClock) -> KeyPool {
+        KeyPool::new(vec![key(KEY_A), key(KEY_B)])
+            .unwrap()
+            .with_clock(clock.clone())
+    }
+
+    fn key(value: &str) -> ApiKey {
+        ApiKey::new(value.to_string()).unwrap()
+    }
+
+    fn remaining(day: u32) -> HttpResponse {
+        HttpResponse::new(200, "{}")
+            .with_header("X-RateLimit-Remaining-day", &day.to_string())
+            .with_header("X-RateLimit-Remaining-minute", "49")
+    }
+
+    #[test]
+    fn test_picks_key_with_most_remaining_quota() {
+        let clock = FakeClock::new(0);
+        let pool = pool(&clock);
+        assert_eq!(pool.pick().unwrap().0, 0);
+        assert_eq!(pool.pick().unwrap().0, 1);
+        pool.report(0, &remaining(10));
+        pool.report(1, &remaining(90));
+        assert_eq!(pool.pick().unwrap().0, 1);
+        assert!(pool.report(1, &HttpResponse::new(429, "").with_header("Retry-After", "30")));
+        assert_eq!(pool.pick().unwrap().0, 0);
+        assert!(pool.report(0, &HttpResponse::new(401, "")));
+        assert!(pool.pick().is_err());
+        clock.advance(30);
+        assert_eq!(pool.pick().unwrap().0, 1);
+        let usage = pool.usage();
+        assert_eq!(usage[0].key, "...aaaa");
+        assert_eq!((usage[0].requests, usage[0].unauthorized), (2, 1));
+        assert_eq!((usage[1].requests, usage[1].rate_limited), (3, 1));
+        assert_eq!(usage[1].remaining_day, Some(90));
+    }
+
+    #[test]
+    fn test_client_rotates_keys_against_mock() {
+        let mock = MockAirly::start().unwrap();
+        mock.add_installation(serde_json::from_str(INSTALLATION_JSON).unwrap());
+        mock.require_api_keys(&[KEY_A, KEY_B]);
+        mock.set_quota(Quota {
+            per_minute: 50,
+            per_day: 2,
+        });
+        let keys = KeyPool::new(vec![key(KEY_REVOKED), key(KEY_A), key(KEY_B)]).unwrap();
+        let client = AirlyClient::with_transport(KEY_A.to_string(), DefaultTransport::default())
+            .unwrap()
+            .with_base_url(&mock.base_url())
+            .with_key_pool(keys);
+        for _ in 0..4 {
+            assert_eq!(client.clone().get_installation(18).unwrap().id, 18);
+        }
+        assert!(client.clone().get_installation(18).is_err());
+        let usage = client.key_pool().usage();
+        assert_eq!(usage[0].unauthorized, 1);
+        assert!(usage[0].benched_until.is_some());
+        assert_eq!((usage[1].remaining_day, usage[2].remaining_day), (Some(0), Some(0)));
+        assert_eq!(usage[1].rate_limited + usage[2].rate_limited, 2);
+    }
+}
@@ -1,46 +1,289 @@
-mod endpoints;
+use crate::cache::{CachedResponse, ResponseCache};
+use self::in_flight::InFlight;
+use crate::cassette::{Cassette, Mode};
+use crate::transport::{DefaultTransport, HttpRequest, Transport};
 use crate::types;
-use reqwest;
-use reqwest::{
-    header::{HeaderName, HeaderValue, ACCEPT, ACCEPT_LANGUAGE},
-    Response,
-};
+use serde::de::DeserializeOwned;
+use std::collections::HashSet;
 use std::io::{Error, ErrorKind};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::Span;
+
+mod api_key;
+mod batch;
+mod endpoints;
+mod in_flight;
+mod keys;
+mod rate_limit;
+mod telemetry;
+
+pub use self::api_key::ApiKey;
+pub use self::batch::{Batch, BatchOptions, ItemResult};
+pub use self::endpoints::{Endpoint, EndpointClass};
+pub use self::keys::{KeyPool, KeyUsage};
+pub use self::rate_limit::RateLimit;
+
+const STATUS_NOT_MODIFIED: u16 = 304;
+const STATUS_TOO_MANY_REQUESTS: u16 = 429;
+const DEFAULT_LANGUAGE: &str = "en";
 
-const API_KEY_LEN: usize = 32;
-const ERR_API_KEY: &str = "Wrong api key length";
+/// Airly api client, generic over http stack, see transport module
+#[derive(Debug)]
+pub struct AirlyClient<T: Transport = DefaultTransport> {
+    keys: Arc<KeyPool>,
+    base_url: String,
+    transport: Arc<T>,
+    cache: Option<Arc<ResponseCache>>,
+    cassette: Option<Arc<Cassette>>,
+    rate_limit: Option<Arc<RateLimit>>,
+    offline: bool,
+    decoding: types::Decoding,
+    language: String,
+    /// Endpoints unknown fields were already logged for
+    unknown_reported: Arc<Mutex<HashSet<&'static str>>>,
+    in_flight: Arc<InFlight>,
+}
+
+/// Status, ETag and body of api response
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RawResponse {
+    pub status: u16,
+    pub etag: Option<String>,
+    pub body: String,
+}
 
-enum IncludeWind {
-    YES,
-    NO
+/// How current response returned by AirlyClient is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Freshness {
+    /// Fetched from api or served from cache before it expired
+    Fresh,
+    /// Expired cached response served because api was unreachable or client is offline
+    Stale { age_secs: i64 },
 }
 
+/// Deserialized response together with its freshness
 #[derive(Debug, Clone)]
-pub struct AirlyClient {
-    api_key: HeaderValue,
-    client: reqwest::Client,
+pub struct Fetched<T> {
+    pub value: T,
+    pub freshness: Freshness,
+    /// Fields of response not known to this crate, keyed by json pointer, e.g. /address/postalCode
+    pub unknown: types::Extra,
+}
+
+impl<T> Fetched<T> {
+    /// # Returns true if value comes from expired cached response
+    pub fn is_stale(&self) -> bool {
+        matches!(self.freshness, Freshness::Stale { .. })
+    }
+}
+
+impl<T: Transport> Clone for AirlyClient<T> {
+    fn clone(&self) -> Self {
+        Self {
+            keys: self.keys.clone(),
+            base_url: self.base_url.clone(),
+            transport: self.transport.clone(),
+            cache: self.cache.clone(),
+            cassette: self.cassette.clone(),
+            rate_limit: self.rate_limit.clone(),
+            offline: self.offline,
+            decoding: self.decoding,
+            language: self.language.clone(),
+            unknown_reported: self.unknown_reported.clone(),
+            in_flight: self.in_flight.clone(),
+        }
+    }
 }
 
 impl AirlyClient {
-    /// Constructs AirlyClient
+    /// Constructs AirlyClient using default transport
     ///
     /// # Arguments:
     /// * api_key - personal api key that can be obtained from https://developer.airly.eu/login
     ///
-    /// # Returns instance of AirlyClient struct if api_key of correct length Error otherwise
+    /// # Returns instance of AirlyClient struct if api_key is 32 ascii letters and digits Error otherwise
     ///
     pub fn new(api_key: String) -> Result<Self, Box<dyn std::error::Error>> {
-        if api_key.len() == API_KEY_LEN {
-            let client = reqwest::Client::new();
-            let _api_key = HeaderValue::from_str(&api_key);
-            if let Ok(api_key) = _api_key {
-                return Ok(Self { api_key, client });
-            }
+        AirlyClient::with_transport(api_key, DefaultTransport::default())
+    }
+
+    /// Constructs AirlyClient using default transport from already validated key
+    ///
+    /// # Arguments:
+    /// * api_key - key loaded e.g. with ApiKey::discover
+    ///
+    /// # Returns instance of AirlyClient struct
+    ///
+    pub fn from_api_key(api_key: ApiKey) -> Self {
+        AirlyClient::build(KeyPool::single(api_key), DefaultTransport::default())
+    }
+}
+
+impl<T: Transport> AirlyClient<T> {
+    /// Constructs AirlyClient sending requests with given transport
+    ///
+    /// # Arguments:
+    /// * api_key - personal api key that can be obtained from https://developer.airly.eu/login
+    /// * transport - http stack, e.g. UreqTransport or MemoryTransport in tests
+    ///
+    /// # Returns instance of AirlyClient struct if api_key is 32 ascii letters and digits Error otherwise
+    ///
+    pub fn with_transport(api_key: String, transport: T) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(AirlyClient::build(KeyPool::single(ApiKey::new(api_key)?), transport))
+    }
+
+    fn build(keys: KeyPool, transport: T) -> Self {
+        Self {
+            keys: Arc::new(keys),
+            base_url: endpoints::BASE_URL.to_string(),
+            transport: Arc::new(transport),
+            cache: None,
+            cassette: None,
+            rate_limit: None,
+            offline: false,
+            decoding: types::Decoding::Default,
+            language: DEFAULT_LANGUAGE.to_string(),
+            unknown_reported: Arc::new(Mutex::new(HashSet::new())),
+            in_flight: Arc::new(InFlight::default()),
         }
-        Err(Box::new(Error::new(
-            ErrorKind::Other,
-            format!("{}, expected: {}, got: {}", ERR_API_KEY, API_KEY_LEN, &api_key.len()),
-        )))
+    }
+
+    /// Distributes requests across several api keys instead of the one client was constructed with,
+    /// pool is shared by clones of this client
+    ///
+    /// # Arguments:
+    /// * keys - pool of api keys picking key by remaining quota
+    ///
+    /// # Returns AirlyClient rotating keys, retrying with next key when one is answered with 401 or 429
+    ///
+    pub fn with_key_pool(mut self, keys: KeyPool) -> Self {
+        self.keys = Arc::new(keys);
+        self
+    }
+
+    /// Getter for key pool
+    ///
+    /// # Returns key pool with per key usage
+    ///
+    pub fn key_pool(&self) -> &KeyPool {
+        &self.keys
+    }
+
+    /// Getter for transport
+    ///
+    /// # Returns transport shared by clones of this client
+    ///
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Points client to other Airly compatible api root, e.g. proxy or local stub server
+    ///
+    /// # Arguments:
+    /// * base_url - api root without trailing slash, e.g. http://127.0.0.1:8080/v2
+    ///
+    /// # Returns AirlyClient sending requests to given api root
+    ///
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Caches responses of all endpoints, cache is shared by clones of this client
+    ///
+    /// # Arguments:
+    /// * cache - response cache with its storage and expiry policy
+    ///
+    /// # Returns AirlyClient serving responses from cache until they expire
+    ///
+    pub fn with_cache(mut self, cache: ResponseCache) -> Self {
+        self.cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Spreads requests over api quota, quota is shared by clones of this client and by batches
+    ///
+    /// # Arguments:
+    /// * rate_limit - quota with longest wait for free slot in it
+    ///
+    /// # Returns AirlyClient waiting for free slot before every request sent to api, requests served from
+    /// cache or cassette take no slot
+    ///
+    pub fn with_rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.rate_limit = Some(Arc::new(rate_limit));
+        self
+    }
+
+    /// Records responses to or replays them from cassette, cassette is shared by clones of this client
+    ///
+    /// # Arguments:
+    /// * cassette - cassette in record or replay mode
+    ///
+    /// # Returns AirlyClient that in replay mode never touches network
+    ///
+    pub fn with_cassette(mut self, cassette: Cassette) -> Self {
+        self.cassette = Some(Arc::new(cassette));
+        self
+    }
+
+    /// Switches offline mode, offline client never touches network and serves cached responses only,
+    /// expired ones included
+    ///
+    /// # Arguments:
+    /// * offline - true to stop sending requests
+    ///
+    /// # Returns AirlyClient in given mode
+    ///
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// # Returns true if client serves cached responses only
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Getter for response cache
+    ///
+    /// # Returns cache if client was constructed with one, e.g. to read its statistics
+    ///
+    pub fn cache(&self) -> Option<&ResponseCache> {
+        self.cache.as_deref()
+    }
+
+    /// Sets how strictly responses are deserialized
+    ///
+    /// # Arguments:
+    /// * decoding - Lenient to tolerate nulls and skip bad list elements, Strict to reject unknown fields
+    ///
+    /// # Returns AirlyClient deserializing responses in given mode
+    ///
+    pub fn with_decoding(mut self, decoding: types::Decoding) -> Self {
+        self.decoding = decoding;
+        self
+    }
+
+    /// Sets language of translated texts, e.g. Index.description and Index.advice, sent in Accept-Language
+    /// header, cached and recorded responses in other than default language are kept apart
+    ///
+    /// # Arguments:
+    /// * language - language code, Airly translates to en (default) and pl
+    ///
+    /// # Returns AirlyClient requesting texts in given language
+    ///
+    pub fn with_language(mut self, language: &str) -> Self {
+        self.language = language.to_string();
+        self
+    }
+
+    /// Getter for language
+    ///
+    /// # Returns language code sent in Accept-Language header
+    ///
+    pub fn language(&self) -> &str {
+        &self.language
     }
 
     /// Get installation properties for given id
@@ -54,14 +297,7 @@ impl AirlyClient {
         self,
         id: u32,
     ) -> Result<types::Installation, Box<dyn std::error::Error>> {
-        let mut uri_composed = String::new();
-        uri_composed.push_str(
-            &format!("{}/{}/{}", endpoints::BASE_URL, endpoints::INSTALLATIONS_URL, id)
-        );
-        let mut res = self.get(&uri_composed)?;
-        let text = res.text()?;
-        let installation: types::Installation = serde_json::from_str(&text)?;
-        Ok(installation)
+        self.request(&Endpoint::Installation(id))
     }
 
     /// Get nearest installations
@@ -77,21 +313,11 @@ impl AirlyClient {
         circle: types::GeoCircle,
         max_results: u32,
     ) -> Result<Vec<types::Installation>, Box<dyn std::error::Error>> {
-        let mut uri_composed = String::new();
-        let point = circle.get_point();
-        uri_composed.push_str(&format!(
-            "{}/{}/{}?lat={}&lng={}&maxDistanceKM={}&maxResults={}",
-            endpoints::BASE_URL,
-            endpoints::INSTALLATIONS_URL,
-            endpoints::NEAREST_URL,
-            point.get_lat(),
-            point.get_lng(),
-            circle.get_radius_km(),
-            max_results
-        ));
-        let mut res = self.get(&uri_composed)?;
-        let installations = res.json::<Vec<types::Installation>>()?;
-        Ok(installations)
+        let installations: types::Elements<types::Installation> = self.request(&Endpoint::Nearest {
+            circle,
+            max_results,
+        })?;
+        Ok(installations.0)
     }
 
     /// Get indexes types
@@ -99,11 +325,8 @@ impl AirlyClient {
     /// # Returns Success of indexes types or Error otherwise
     ///
     pub fn get_indices(self) -> Result<Vec<types::IndexType>, Box<dyn std::error::Error>> {
-        let mut uri_composed = String::new();
-        uri_composed.push_str(&format!("{}/{}", endpoints::BASE_URL, endpoints::META_INDEXES_URL));
-        let mut res = self.get(&uri_composed)?;
-        let indexes_types = res.json::<Vec<types::IndexType>>()?;
-        Ok(indexes_types)
+        let indices: types::Elements<types::IndexType> = self.request(&Endpoint::Indices)?;
+        Ok(indices.0)
     }
 
     /// Get meta measurement types
@@ -113,13 +336,8 @@ impl AirlyClient {
     pub fn get_meta_measurements(
         self,
     ) -> Result<Vec<types::MeasurementType>, Box<dyn std::error::Error>> {
-        let mut uri_composed = String::new();
-        uri_composed.push_str(
-            &format!("{}/{}", endpoints::BASE_URL, endpoints::META_MEASUREMENTS_URL)
-        );
-        let mut res = self.get(&uri_composed)?;
-        let measurements_types = res.json::<Vec<types::MeasurementType>>()?;
-        Ok(measurements_types)
+        let measurement_types: types::Elements<types::MeasurementType> = self.request(&Endpoint::MetaMeasurements)?;
+        Ok(measurement_types.0)
     }
 
     /// Get measurements of specific installation including wind
@@ -135,15 +353,12 @@ impl AirlyClient {
         id: u32,
         index_type: types::IndexType,
     ) -> Result<types::Measurements, Box<dyn std::error::Error>> {
-        if let Some(type_name) = index_type.name {
-            let uri_composed = get_measurements_query_string(id, type_name, IncludeWind::YES);
-            self.get_installation_measurements(uri_composed)
-        } else {
-            return Err(Box::new(Error::new(
-                ErrorKind::InvalidInput,
-                "IndexType.name is None",
-            )));
-        }
+        let index_type = index_type_name(index_type)?;
+        self.request(&Endpoint::InstallationMeasurements {
+            id,
+            index_type,
+            include_wind: true,
+        })
     }
 
     /// Get measurements of specific installation but don't include wind
@@ -159,15 +374,12 @@ impl AirlyClient {
         id: u32,
         index_type: types::IndexType,
     ) -> Result<types::Measurements, Box<dyn std::error::Error>> {
-        if let Some(type_name) = index_type.name {
-            let uri_composed = get_measurements_query_string(id, type_name, IncludeWind::NO);
-            self.get_installation_measurements(uri_composed)
-        } else {
-            return Err(Box::new(Error::new(
-                ErrorKind::InvalidInput,
-                "IndexType.name is None",
-            )));
-        }
+        let index_type = index_type_name(index_type)?;
+        self.request(&Endpoint::InstallationMeasurements {
+            id,
+            index_type,
+            include_wind: false,
+        })
     }
 
     /// Get measurements of installation nearest the specified point in circle boundaries
@@ -183,29 +395,8 @@ impl AirlyClient {
         index_type: types::IndexType,
         circle: types::GeoCircle,
     ) -> Result<types::Measurements, Box<dyn std::error::Error>> {
-        if let Some(name) = index_type.name {
-            let mut uri_composed = String::new();
-            let point = circle.get_point();
-            uri_composed.push_str(&format!(
-                "{}/{}/{}?indexType={}&lat={}&lng={}&maxDistanceKM={}",
-                endpoints::BASE_URL,
-                endpoints::MEASUREMENTS_URL,
-                endpoints::NEAREST_URL,
-                name,
-                point.get_lat(),
-                point.get_lng(),
-                circle.get_radius_km(),
-            ));
-            let mut res = self.get(&uri_composed)?;
-            let text = res.text()?;
-            let measurements: types::Measurements = serde_json::from_str(&text)?;
-            return Ok(measurements);
-        } else {
-            return Err(Box::new(Error::new(
-                ErrorKind::InvalidInput,
-                "IndexType.name is None",
-            )));
-        }
+        let index_type = index_type_name(index_type)?;
+        self.request(&Endpoint::MeasurementsNearest { circle, index_type })
     }
 
     /// Get measurements of interpolated values for given point on map
@@ -221,85 +412,250 @@ impl AirlyClient {
         index_type: types::IndexType,
         point: types::GeoPoint,
     ) -> Result<types::Measurements, Box<dyn std::error::Error>> {
-        if let Some(name) = index_type.name {
-            let mut uri_composed = String::new();
-            uri_composed.push_str(&format!(
-                "{}/{}/{}?indexType={}&lat={}&lng={}",
-                endpoints::BASE_URL,
-                endpoints::MEASUREMENTS_URL,
-                endpoints::POINT_URL,
-                name,
-                point.get_lat(),
-                point.get_lng(),
-            ));
-            let mut res = self.get(&uri_composed)?;
-            let text = res.text()?;
-            let measurements: types::Measurements = serde_json::from_str(&text)?;
-            return Ok(measurements);
-        } else {
-            return Err(Box::new(Error::new(
-                ErrorKind::InvalidInput,
-                "IndexType.name is None",
-            )));
+        let index_type = index_type_name(index_type)?;
+        self.request(&Endpoint::MeasurementsPoint { point, index_type })
+    }
+
+    /// Fetches any endpoint and deserializes its response
+    ///
+    /// # Arguments:
+    /// * endpoint - endpoint with its parameters
+    ///
+    /// # Returns Success of deserialized response or Error otherwise
+    ///
+    pub fn request<R: DeserializeOwned>(
+        &self,
+        endpoint: &Endpoint,
+    ) -> Result<R, Box<dyn std::error::Error>> {
+        Ok(self.request_with_freshness(endpoint)?.value)
+    }
+
+    /// Fetches any endpoint, falls back to the most recent cached response when api is unreachable,
+    /// responds with server error or quota is exceeded, identical requests made by clones of this client
+    /// while one is in flight wait for its response instead of being sent again
+    ///
+    /// # Arguments:
+    /// * endpoint - endpoint with its parameters
+    ///
+    /// # Returns Success of deserialized response marked stale with its age if it comes from expired cache,
+    /// together with fields of response not known to this crate, Error if neither api nor cache could provide it
+    ///
+    pub fn request_with_freshness<R: DeserializeOwned>(
+        &self,
+        endpoint: &Endpoint,
+    ) -> Result<Fetched<R>, Box<dyn std::error::Error>> {
+        let span = telemetry::span(endpoint);
+        let _entered = span.enter();
+        let started = Instant::now();
+        let key = self.language_key(format!("{}/{}", self.base_url, endpoint.path()));
+        let fetched = self.in_flight.fetch(&key, || self.fetch_endpoint(&key, endpoint, &span));
+        let result = fetched.and_then(|(text, freshness)| {
+            let decoded: types::Decoded<R> = types::decode::from_str(&text, self.decoding)?;
+            self.report_unknown(endpoint, &decoded.unknown);
+            Ok(Fetched {
+                value: decoded.value,
+                freshness,
+                unknown: decoded.unknown,
+            })
+        });
+        let error = result.as_ref().err().map(|err| err.as_ref());
+        telemetry::finish(&span, endpoint, started.elapsed(), error);
+        result
+    }
+
+    /// Warns about unknown fields once per endpoint for all clones of this client
+    fn report_unknown(&self, endpoint: &Endpoint, unknown: &types::Extra) {
+        if unknown.is_empty() || !self.unknown_reported.lock().unwrap().insert(endpoint.name()) {
+            return;
         }
+        let names: Vec<&str> = unknown.keys().map(String::as_str).collect();
+        warn!("Airly api added fields to {} response: {}", endpoint.name(), names.join(", "));
     }
 
-    fn get_installation_measurements(
-        self, uri_composed: String
-    ) -> Result<types::Measurements, Box<dyn std::error::Error>> {
-        let mut res = self.get(&uri_composed)?;
-        let text = res.text()?;
-        let measurements: types::Measurements = serde_json::from_str(&text)?;
-        return Ok(measurements);
-    }
-
-    fn get(self, uri_req: &String) -> Result<Response, Box<dyn std::error::Error>> {
-        let res = self
-            .client
-            .get(uri_req)
-            .header(ACCEPT, HeaderValue::from_static("application/json"))
-            .header(ACCEPT_LANGUAGE, HeaderValue::from_static("en"))
-            .header(HeaderName::from_static("apikey"), self.api_key)
-            .send()?;
-        Ok(res)
+    /// # Returns key of cached or recorded response, language is appended when it is not the default one
+    fn language_key(&self, url: String) -> String {
+        if self.language == DEFAULT_LANGUAGE {
+            return url;
+        }
+        format!("{}#lang={}", url, self.language)
+    }
+
+    fn fetch_endpoint(
+        &self,
+        uri_composed: &str,
+        endpoint: &Endpoint,
+        span: &Span,
+    ) -> Result<(String, Freshness), Box<dyn std::error::Error>> {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None if self.offline => return Err(offline_miss(uri_composed)),
+            None => return Ok((successful(uri_composed, self.get(endpoint, None, span)?)?, Freshness::Fresh)),
+        };
+        let expired = match cache.lookup(uri_composed) {
+            Ok(fresh) => {
+                telemetry::cache(span, endpoint, "hit");
+                return Ok((fresh.body, Freshness::Fresh));
+            }
+            Err(expired) => expired,
+        };
+        if self.offline {
+            return match expired {
+                Some(response) => {
+                    telemetry::cache(span, endpoint, "stale");
+                    Ok(stale(cache, response))
+                }
+                None => {
+                    telemetry::cache(span, endpoint, "offline_miss");
+                    Err(offline_miss(uri_composed))
+                }
+            };
+        }
+        let etag = expired.as_ref().and_then(|response| response.etag.clone());
+        let outcome = self.get(endpoint, etag, span);
+        let failure = match &outcome {
+            Ok(res) if res.status >= 500 || res.status == STATUS_TOO_MANY_REQUESTS => {
+                Some(res.status.to_string())
+            }
+            Ok(_) => None,
+            Err(err) => Some(err.to_string()),
+        };
+        if let (Some(reason), Some(response)) = (failure, &expired) {
+            warn!("Airly api unavailable ({}), serving stale response of {}", reason, uri_composed);
+            telemetry::cache(span, endpoint, "stale");
+            return Ok(stale(cache, response.clone()));
+        }
+        let res = outcome?;
+        if res.status == STATUS_NOT_MODIFIED {
+            if let Some(response) = expired {
+                telemetry::cache(span, endpoint, "revalidated");
+                return Ok((cache.revalidate(endpoint, response).body, Freshness::Fresh));
+            }
+        }
+        telemetry::cache(span, endpoint, "miss");
+        if (200..300).contains(&res.status) {
+            cache.store(endpoint, uri_composed, &res.body, res.etag.clone());
+        }
+        Ok((successful(uri_composed, res)?, Freshness::Fresh))
     }
+
+    fn get(
+        &self,
+        endpoint: &Endpoint,
+        etag: Option<String>,
+        span: &Span,
+    ) -> Result<RawResponse, Box<dyn std::error::Error>> {
+        let path = endpoint.path();
+        let recorded_as = self.language_key(path.clone());
+        if let Some(cassette) = &self.cassette {
+            if cassette.mode() == Mode::Replay {
+                let response = cassette.play(&recorded_as)?;
+                telemetry::response(span, endpoint, None, response.status, response.body.len(), 0);
+                return Ok(response);
+            }
+        }
+        let url = format!("{}/{}", self.base_url, path);
+        let mut attempts = 0;
+        let (res, api_key) = loop {
+            let (index, api_key) = self.keys.pick()?;
+            if let Some(rate_limit) = &self.rate_limit {
+                rate_limit.acquire()?;
+            }
+            let mut request = HttpRequest::get(&url)
+                .with_header("Accept", "application/json")
+                .with_header("Accept-Language", &self.language)
+                .with_sensitive_header("apikey", api_key.expose());
+            if let Some(etag) = &etag {
+                request = request.with_header("If-None-Match", etag);
+            }
+            let res = self.transport.send(request)?;
+            attempts += 1;
+            let benched = self.keys.report(index, &res);
+            if !benched || attempts >= self.keys.len() || !self.keys.has_available() {
+                break (res, api_key);
+            }
+        };
+        telemetry::response(span, endpoint, Some(&api_key), res.status, res.body.len(), attempts - 1);
+        let response = RawResponse {
+            status: res.status,
+            etag: res.header("ETag").map(String::from),
+            body: res.body,
+        };
+        if let Some(cassette) = &self.cassette {
+            cassette.store(&recorded_as, &response)?;
+        }
+        Ok(response)
+    }
+}
+
+/// # Returns body of 2xx response, Error with status and body otherwise
+fn successful(uri: &str, response: RawResponse) -> Result<String, Box<dyn std::error::Error>> {
+    if (200..300).contains(&response.status) {
+        return Ok(response.body);
+    }
+    Err(format!("Airly api responded {} to {}: {}", response.status, uri, response.body.trim()).into())
 }
 
-fn get_measurements_query_string(id: u32, type_name: String, wind: IncludeWind) -> String {
-    let mut uri_composed = String::new();
-    let wind_string = match wind {
-        IncludeWind::YES => format!("includeWind=true&"),
-        IncludeWind::NO => format!(""),
-    };
-    uri_composed.push_str(&format!(
-        "{}/{}/{}?{}indexType={}&installationId={}",
-        endpoints::BASE_URL,
-        endpoints::MEASUREMENTS_URL,
-        endpoints::INSTALLATION_URL,
-        wind_string,
-        type_name,
-        id
-    ));
-    uri_composed
+fn stale(cache: &ResponseCache, response: CachedResponse) -> (String, Freshness) {
+    let age_secs = cache.serve_stale(&response);
+    (response.body, Freshness::Stale { age_secs })
+}
+
+fn offline_miss(uri: &str) -> Box<dyn std::error::Error> {
+    Box::new(Error::new(
+        ErrorKind::NotFound,
+        format!("Client is offline and no cached response of {} is available", uri),
+    ))
+}
+
+fn index_type_name(index_type: types::IndexType) -> Result<String, Box<dyn std::error::Error>> {
+    match index_type.name {
+        Some(name) => Ok(name),
+        None => Err(Box::new(Error::new(
+            ErrorKind::InvalidInput,
+            "IndexType.name is None",
+        ))),
+    }
 }
 
 #[cfg(test)]
 mod test_client {
     use std::env;
     use dotenv::dotenv;
+    use crate::cassette::{self, Cassette, Mode};
+    use crate::stub::INSTALLATION_JSON;
+    use crate::transport::{HttpResponse, MemoryTransport};
+    use super::{Endpoint, Fetched};
     const INSTALLATION_ID: u32 = 18;
     const INFO_DETAILS: &str =
         "Error while fetching data, run with: -- --nocapture, to see details.";
     const INFO_CONNECTION: &str = "Cannot establish https connection.";
     const API_KEY_INFO: &str = "AIRLY_API_KEY has wrong length";
+    const REPLAY_API_KEY: &str = "00000000000000000000000000000000";
+
+    /// Key is only needed to record cassettes, replay works without it
+    fn api_key() -> String {
+        match env::var(cassette::RECORD_ENV) {
+            Ok(value) if !value.is_empty() => env::var("AIRLY_API_KEY").expect("AIRLY_API_KEY must be set"),
+            _ => env::var("AIRLY_API_KEY").unwrap_or_else(|_| REPLAY_API_KEY.to_string()),
+        }
+    }
+
+    /// Client replaying shipped synthetic cassette, replaced by recording with AIRLY_RECORD=1 and valid AIRLY_API_KEY
+    fn client(api_key: String, name: &str) -> Result<super::AirlyClient, Box<dyn std::error::Error>> {
+        let cassette = Cassette::from_env(cassette::fixture(name))?;
+        if cassette.mode() == Mode::Record {
+            println!("Recording {}", cassette.file().display());
+        }
+        Ok(super::AirlyClient::new(api_key)?.with_cassette(cassette))
+    }
     #[test]
     fn test_get_installation() {
         dotenv().ok();
-        let api_key = env::var("AIRLY_API_KEY").expect("AIRLY_API_KEY must be set");
+        let api_key = api_key();
         if api_key.len() == 0 {
             panic!(API_KEY_INFO);
         } else {
-            if let Ok(client) = super::AirlyClient::new(api_key) {
+            if let Ok(client) = client(api_key, "installation") {
                 if let Ok(installation) = client.get_installation(INSTALLATION_ID) {
                     println!("Fetched installation for id: \n{:?}\n", installation);
                     assert_eq!(installation.id, INSTALLATION_ID as i32);
@@ -314,7 +670,7 @@ mod test_client {
     #[test]
     fn test_get_nearest() {
         dotenv().ok();
-        let api_key = env::var("AIRLY_API_KEY").expect("AIRLY_API_KEY must be set");
+        let api_key = api_key();
         if api_key.len() == 0 {
             panic!(API_KEY_INFO);
         } else {
@@ -322,7 +678,7 @@ mod test_client {
                 super::types::GeoPoint::new(54.347279, 18.653846).unwrap(), // Gdansk, Poland
                 5,
             ).unwrap();
-            if let Ok(client) = super::AirlyClient::new(api_key) {
+            if let Ok(client) = client(api_key, "nearest") {
                 if let Ok(installations) = client.get_nearest(circle, 123) {
                     println!("Fetched installations for nearest: \n{:?}\n", installations);
                     assert_eq!(installations.len() > 0, true);
@@ -337,11 +693,11 @@ mod test_client {
     #[test]
     fn test_get_indices() {
         dotenv().ok();
-        let api_key = env::var("AIRLY_API_KEY").expect("AIRLY_API_KEY must be set");
+        let api_key = api_key();
         if api_key.len() == 0 {
             panic!(API_KEY_INFO);
         } else {
-            if let Ok(client) = super::AirlyClient::new(api_key) {
+            if let Ok(client) = client(api_key, "indices") {
                 if let Ok(index_types) = client.get_indices() {
                     println!("Fetched indexes: \n{:?}\n", index_types);
                     assert_eq!(index_types.len() > 0, true);
@@ -356,11 +712,11 @@ mod test_client {
     #[test]
     fn test_get_installation_measurements() {
         dotenv().ok();
-        let api_key = env::var("AIRLY_API_KEY").expect("AIRLY_API_KEY must be set");
+        let api_key = api_key();
         if api_key.len() == 0 {
             panic!(API_KEY_INFO);
         } else {
-            if let Ok(client) = super::AirlyClient::new(api_key) {
+            if let Ok(client) = client(api_key, "installation_measurements") {
                 let id = 34;
                 let name = Some(format!("AIRLY_CAQI"));
                 let level = None;
@@ -391,7 +747,7 @@ mod test_client {
     #[test]
     fn test_get_measurements_nearest() {
         dotenv().ok();
-        let api_key = env::var("AIRLY_API_KEY").expect("AIRLY_API_KEY must be set");
+        let api_key = api_key();
         if api_key.len() == 0 {
             panic!(API_KEY_INFO);
         } else {
@@ -399,7 +755,7 @@ mod test_client {
                 super::types::GeoPoint::new(54.347279, 18.653846).unwrap(), // Gdansk, Poland
                 5,
             ).unwrap();
-            if let Ok(client) = super::AirlyClient::new(api_key) {
+            if let Ok(client) = client(api_key, "measurements_nearest") {
                 let name = Some(format!("AIRLY_CAQI"));
                 let level = None;
                 let index_type = super::types::IndexType { name, level };
@@ -419,12 +775,12 @@ mod test_client {
     #[test]
     fn test_get_measurements_point() {
         dotenv().ok();
-        let api_key = env::var("AIRLY_API_KEY").expect("AIRLY_API_KEY must be set");
+        let api_key = api_key();
         if api_key.len() == 0 {
             panic!(API_KEY_INFO);
         } else {
             let point = super::types::GeoPoint::new(54.347279, 18.653846).unwrap(); // Gdansk, Poland
-            if let Ok(client) = super::AirlyClient::new(api_key) {
+            if let Ok(client) = client(api_key, "measurements_point") {
                 let name = Some(format!("AIRLY_CAQI"));
                 let level = None;
                 let index_type = super::types::IndexType { name, level };
@@ -444,11 +800,11 @@ mod test_client {
     #[test]
     fn test_get_meta_measurements() {
         dotenv().ok();
-        let api_key = env::var("AIRLY_API_KEY").expect("AIRLY_API_KEY must be set");
+        let api_key = api_key();
         if api_key.len() == 0 {
             panic!(API_KEY_INFO);
         } else {
-            if let Ok(client) = super::AirlyClient::new(api_key) {
+            if let Ok(client) = client(api_key, "meta_measurements") {
                 if let Ok(measurement_types) = client.get_meta_measurements() {
                     println!("Fetched measurements types: \n{:?}\n", measurement_types);
                     assert_eq!(measurement_types.len() > 0, true);
@@ -460,4 +816,17 @@ mod test_client {
             }
         }
     }
+    #[test]
+    fn test_unknown_fields_returned() {
+        let body = INSTALLATION_JSON.replacen('{', "{\"locationId\": 18,", 1);
+        let transport = MemoryTransport::new().with_response("installations/18", HttpResponse::new(200, &body));
+        let client = super::AirlyClient::with_transport(REPLAY_API_KEY.to_string(), transport).unwrap();
+        for _ in 0..2 {
+            let fetched: Fetched<super::types::Installation> =
+                client.request_with_freshness(&Endpoint::Installation(INSTALLATION_ID)).unwrap();
+            assert_eq!(fetched.value.id, INSTALLATION_ID as i32);
+            assert_eq!(fetched.unknown["/locationId"], 18);
+        }
+        assert_eq!(client.unknown_reported.lock().unwrap().len(), 1);
+    }
 }
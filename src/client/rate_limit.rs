@@ -0,0 +1,80 @@
+use crate::clock::{Clock, SystemClock};
+use crate::collector::{Quota, RateLimiter};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const DEFAULT_MAX_WAIT_SECS: i64 = 60;
+
+/// Api quota shared by AirlyClient and all its clones, only requests sent to api take a slot in it,
+/// responses served from cache or cassette do not
+pub struct RateLimit {
+    limiter: Mutex<RateLimiter>,
+    max_wait_secs: i64,
+    clock: Arc<dyn Clock>,
+}
+
+impl fmt::Debug for RateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RateLimit")
+            .field("limiter", &self.limiter)
+            .field("max_wait_secs", &self.max_wait_secs)
+            .finish()
+    }
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        RateLimit::new(Quota::default())
+    }
+}
+
+impl RateLimit {
+    /// Constructs RateLimit
+    ///
+    /// # Arguments:
+    /// * quota - api limits requests are spread over, Airly defaults with Quota::default
+    ///
+    pub fn new(quota: Quota) -> Self {
+        Self {
+            limiter: Mutex::new(RateLimiter::new(quota)),
+            max_wait_secs: DEFAULT_MAX_WAIT_SECS,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Sets longest wait for free slot in quota, requests that would wait longer fail, 60 seconds by default
+    pub fn with_max_wait_secs(mut self, max_wait_secs: i64) -> Self {
+        self.max_wait_secs = max_wait_secs;
+        self
+    }
+
+    /// Sets time source of rate limiting, e.g. FakeClock in tests
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Waits for free slot in quota and registers request in it
+    ///
+    /// # Returns Error if slot is further away than max wait
+    ///
+    pub(crate) fn acquire(&self) -> Result<(), Box<dyn std::error::Error>> {
+        loop {
+            let wait = {
+                let mut limiter = self.limiter.lock().unwrap();
+                let now = self.clock.now();
+                let slot = limiter.next_slot(now);
+                if slot <= now {
+                    limiter.record(now);
+                    return Ok(());
+                }
+                slot - now
+            };
+            if wait > self.max_wait_secs {
+                return Err(format!("Rate limit reached, next request possible in {} seconds", wait).into());
+            }
+            self.clock.sleep(Duration::from_secs(wait as u64));
+        }
+    }
+}
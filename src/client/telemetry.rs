@@ -0,0 +1,249 @@
+use super::{ApiKey, Endpoint};
+use std::time::Duration;
+use tracing::field::Empty;
+use tracing::Span;
+
+/// Span of single AirlyClient request, response fields are filled in as request goes
+pub(crate) fn span(endpoint: &Endpoint) -> Span {
+    let span = tracing::info_span!(
+        "airly_request",
+        endpoint = endpoint.name(),
+        installation_id = Empty,
+        index_type = Empty,
+        lat = Empty,
+        lng = Empty,
+        api_key = Empty,
+        status = Empty,
+        bytes = Empty,
+        retries = Empty,
+        cache = Empty,
+        latency_ms = Empty,
+    );
+    if let Some(id) = endpoint.installation_id() {
+        span.record("installation_id", id);
+    }
+    if let Some(index_type) = endpoint.index_type() {
+        span.record("index_type", index_type);
+    }
+    if let Some(point) = endpoint.point() {
+        span.record("lat", f64::from(point.get_lat()));
+        span.record("lng", f64::from(point.get_lng()));
+    }
+    span
+}
+
+/// Records response of api or cassette, key is recorded masked
+pub(crate) fn response(
+    span: &Span,
+    endpoint: &Endpoint,
+    api_key: Option<&ApiKey>,
+    status: u16,
+    bytes: usize,
+    retries: usize,
+) {
+    if let Some(api_key) = api_key {
+        span.record("api_key", api_key.masked().as_str());
+    }
+    span.record("status", status);
+    span.record("bytes", bytes as u64);
+    span.record("retries", retries as u64);
+    #[cfg(feature = "metrics")]
+    {
+        metrics::counter!("airly_responses_total", "endpoint" => endpoint.name(), "status" => status.to_string())
+            .increment(1);
+        metrics::histogram!("airly_response_bytes", "endpoint" => endpoint.name()).record(bytes as f64);
+        if retries > 0 {
+            metrics::counter!("airly_key_retries_total", "endpoint" => endpoint.name()).increment(retries as u64);
+        }
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = endpoint;
+}
+
+/// Records how cache served request: hit, miss, revalidated, stale or offline_miss
+pub(crate) fn cache(span: &Span, endpoint: &Endpoint, outcome: &'static str) {
+    span.record("cache", outcome);
+    #[cfg(feature = "metrics")]
+    metrics::counter!("airly_cache_total", "endpoint" => endpoint.name(), "outcome" => outcome).increment(1);
+    #[cfg(not(feature = "metrics"))]
+    let _ = endpoint;
+}
+
+/// Records latency and result of whole request, cache lookups, key retries and decoding included, non-2xx
+/// responses are errors
+pub(crate) fn finish(span: &Span, endpoint: &Endpoint, latency: Duration, error: Option<&dyn std::error::Error>) {
+    span.record("latency_ms", latency.as_millis() as u64);
+    match error {
+        Some(err) => tracing::warn!(parent: span, error = %err, "Airly request failed"),
+        None => tracing::debug!(parent: span, "Airly request done"),
+    }
+    #[cfg(feature = "metrics")]
+    {
+        let result = if error.is_some() { "error" } else { "ok" };
+        metrics::counter!("airly_requests_total", "endpoint" => endpoint.name(), "result" => result).increment(1);
+        metrics::histogram!("airly_request_duration_seconds", "endpoint" => endpoint.name())
+            .record(latency.as_secs_f64());
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = endpoint;
+}
+
+#[cfg(test)]
+mod test_telemetry {
+    use crate::client::AirlyClient;
+    use crate::stub::{StubServer, INSTALLATION_JSON, MEASUREMENTS_JSON};
+    use crate::types::IndexType;
+    use std::collections::BTreeMap;
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    const API_KEY: &str = "0000000000000000000000000000abcd";
+
+    type Fields = BTreeMap<String, String>;
+
+    /// Subscriber keeping fields of every span
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<Fields>>>);
+
+    struct Visitor<'a>(&'a mut Fields);
+
+    impl<'a> Visit for Visitor<'a> {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    impl Subscriber for Capture {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn new_span(&self, attrs: &Attributes) -> Id {
+            let mut fields = Fields::new();
+            attrs.record(&mut Visitor(&mut fields));
+            let mut spans = self.0.lock().unwrap();
+            spans.push(fields);
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, id: &Id, values: &Record) {
+            let mut spans = self.0.lock().unwrap();
+            values.record(&mut Visitor(&mut spans[id.into_u64() as usize - 1]));
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, _: &Event) {}
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn test_request_span_fields() {
+        let server = StubServer::start(|url| match url.contains("measurements") {
+            true => (200, MEASUREMENTS_JSON.to_string()),
+            false => (200, INSTALLATION_JSON.to_string()),
+        });
+        let client = AirlyClient::new(API_KEY.to_string())
+            .unwrap()
+            .with_base_url(&server.base_url());
+        let capture = Capture::default();
+        tracing::subscriber::with_default(capture.clone(), || {
+            client.clone().get_installation(18).unwrap();
+            let caqi = IndexType {
+                name: Some("AIRLY_CAQI".to_string()),
+                level: None,
+            };
+            client.clone().get_installation_measurements_without_wind(18, caqi).unwrap();
+        });
+        let spans = capture.0.lock().unwrap();
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0]["endpoint"], "\"installation\"");
+        assert_eq!(spans[0]["installation_id"], "18");
+        assert_eq!(spans[0]["status"], "200");
+        assert_eq!(spans[0]["bytes"], INSTALLATION_JSON.len().to_string());
+        assert_eq!(spans[0]["retries"], "0");
+        assert_eq!(spans[0]["api_key"], "\"...abcd\"");
+        assert!(spans[0].contains_key("latency_ms"));
+        assert!(!spans[0].contains_key("index_type"));
+        assert_eq!(spans[1]["index_type"], "\"AIRLY_CAQI\"");
+        assert!(spans.iter().all(|fields| fields.values().all(|value| !value.contains(API_KEY))));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_request_metrics() {
+        use metrics::{Counter, CounterFn, Gauge, Histogram, HistogramFn, Key, KeyName, Recorder, SharedString, Unit};
+
+        #[derive(Default)]
+        struct Recorded(Mutex<BTreeMap<String, f64>>);
+
+        impl Recorded {
+            fn add(&self, key: &Key, value: f64) {
+                let labels: Vec<String> = key.labels().map(|l| format!("{}={}", l.key(), l.value())).collect();
+                let name = format!("{}{{{}}}", key.name(), labels.join(","));
+                *self.0.lock().unwrap().entry(name).or_insert(0.0) += value;
+            }
+        }
+
+        struct Handle(Arc<Recorded>, Key);
+
+        impl CounterFn for Handle {
+            fn increment(&self, value: u64) {
+                self.0.add(&self.1, value as f64);
+            }
+
+            fn absolute(&self, _: u64) {}
+        }
+
+        impl HistogramFn for Handle {
+            fn record(&self, _: f64) {
+                self.0.add(&self.1, 1.0);
+            }
+        }
+
+        struct Sink(Arc<Recorded>);
+
+        impl Recorder for Sink {
+            fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+            fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+            fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+            fn register_counter(&self, key: &Key, _: &metrics::Metadata<'_>) -> Counter {
+                Counter::from_arc(Arc::new(Handle(self.0.clone(), key.clone())))
+            }
+
+            fn register_gauge(&self, _: &Key, _: &metrics::Metadata<'_>) -> Gauge {
+                Gauge::noop()
+            }
+
+            fn register_histogram(&self, key: &Key, _: &metrics::Metadata<'_>) -> Histogram {
+                Histogram::from_arc(Arc::new(Handle(self.0.clone(), key.clone())))
+            }
+        }
+
+        let server = StubServer::start(|url| match url.ends_with("/18") {
+            true => (200, INSTALLATION_JSON.to_string()),
+            false => (404, "{}".to_string()),
+        });
+        let client = AirlyClient::new(API_KEY.to_string())
+            .unwrap()
+            .with_base_url(&server.base_url());
+        let recorded = Arc::new(Recorded::default());
+        metrics::with_local_recorder(&Sink(recorded.clone()), || {
+            client.clone().get_installation(18).unwrap();
+            assert!(client.clone().get_installation(19).is_err());
+        });
+        let recorded = recorded.0.lock().unwrap();
+        assert_eq!(recorded["airly_responses_total{endpoint=installation,status=200}"], 1.0);
+        assert_eq!(recorded["airly_responses_total{endpoint=installation,status=404}"], 1.0);
+        assert_eq!(recorded["airly_requests_total{endpoint=installation,result=ok}"], 1.0);
+        assert_eq!(recorded["airly_requests_total{endpoint=installation,result=error}"], 1.0);
+        assert_eq!(recorded["airly_request_duration_seconds{endpoint=installation}"], 2.0);
+    }
+}
@@ -0,0 +1,62 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Source of wall clock time, abstracted so schedules can be driven by a fake clock in tests
+pub trait Clock: Send + Sync {
+    /// # Returns current unix timestamp in seconds
+    fn now(&self) -> i64;
+
+    /// Blocks caller for given duration
+    fn sleep(&self, duration: Duration);
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0)
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
+}
+
+/// Clock that only moves when asked to, sleeping advances it instantly
+#[derive(Debug, Clone)]
+pub struct FakeClock {
+    now: Arc<Mutex<i64>>,
+}
+
+impl FakeClock {
+    /// Constructs FakeClock
+    ///
+    /// # Arguments:
+    /// * now - initial unix timestamp in seconds
+    ///
+    pub fn new(now: i64) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    /// Moves clock forward by given number of seconds
+    pub fn advance(&self, secs: i64) {
+        *self.now.lock().unwrap() += secs;
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> i64 {
+        *self.now.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration.as_secs() as i64);
+    }
+}
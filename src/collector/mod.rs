@@ -0,0 +1,525 @@
+use crate::client::{AirlyClient, ApiKey};
+use crate::clock::Clock;
+use crate::store::{self, Gap, Record, Source, Store};
+use crate::types;
+use std::collections::VecDeque;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+const SECONDS_PER_MINUTE: i64 = 60;
+const SECONDS_PER_DAY: i64 = 86_400;
+const RETRY_AFTER_SECS: i64 = 300;
+
+fn default_index_type() -> String {
+    "AIRLY_CAQI".to_string()
+}
+
+fn default_interval_secs() -> u64 {
+    3600
+}
+
+fn default_store_dir() -> String {
+    "airly_archive".to_string()
+}
+
+/// Api usage limits, Airly defaults to 50 requests per minute and 100 per day
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Quota {
+    #[serde(rename = "perMinute")]
+    pub per_minute: u32,
+    #[serde(rename = "perDay")]
+    pub per_day: u32,
+}
+
+impl Default for Quota {
+    fn default() -> Self {
+        Self {
+            per_minute: 50,
+            per_day: 100,
+        }
+    }
+}
+
+impl Quota {
+    /// Calculates shortest polling interval letting all targets be fetched without exceeding daily limit
+    ///
+    /// # Arguments:
+    /// * targets - number of installations and points polled
+    ///
+    /// # Returns interval in seconds
+    ///
+    pub fn min_interval_secs(self, targets: usize) -> i64 {
+        if self.per_day == 0 {
+            return SECONDS_PER_DAY;
+        }
+        let per_day = i64::from(self.per_day);
+        (SECONDS_PER_DAY * targets as i64 + per_day - 1) / per_day
+    }
+}
+
+/// Collector configuration, usually read from json file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Config {
+    /// Airly api key, ApiKey::discover is used when missing
+    #[serde(rename = "apiKey", default)]
+    pub api_key: Option<ApiKey>,
+    /// Api root, Airly production api when missing
+    #[serde(rename = "baseUrl", default)]
+    pub base_url: Option<String>,
+    /// Name of index requested with measurements
+    #[serde(rename = "indexType", default = "default_index_type")]
+    pub index_type: String,
+    /// Whether installation measurements should include wind
+    #[serde(rename = "includeWind", default)]
+    pub include_wind: bool,
+    /// Ids of installations to poll
+    #[serde(default)]
+    pub installations: Vec<u32>,
+    /// Points on map to poll interpolated measurements for
+    #[serde(default)]
+    pub points: Vec<types::GeoPoint>,
+    /// Desired polling interval of every target, stretched if quota does not allow it
+    #[serde(rename = "intervalSecs", default = "default_interval_secs")]
+    pub interval_secs: u64,
+    #[serde(default)]
+    pub quota: Quota,
+    /// Directory archive files are kept in
+    #[serde(rename = "storeDir", default = "default_store_dir")]
+    pub store_dir: String,
+}
+
+impl Config {
+    /// Reads configuration from json file
+    ///
+    /// # Arguments:
+    /// * path - path to configuration file
+    ///
+    /// # Returns Config if file is readable and valid Error otherwise
+    ///
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = fs::read_to_string(path)?;
+        let config: Config = serde_json::from_str(&text)?;
+        Ok(config)
+    }
+
+    /// # Returns all polled sources, installations first
+    pub fn sources(&self) -> Vec<Source> {
+        self.installations
+            .iter()
+            .map(|id| Source::Installation(*id))
+            .chain(self.points.iter().map(|point| Source::Point(*point)))
+            .collect()
+    }
+}
+
+/// Keeps track of calls made within the last minute and day
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    quota: Quota,
+    calls: VecDeque<i64>,
+}
+
+impl RateLimiter {
+    pub fn new(quota: Quota) -> Self {
+        Self {
+            quota,
+            calls: VecDeque::new(),
+        }
+    }
+
+    /// Finds earliest moment next call fits in the quota
+    ///
+    /// # Arguments:
+    /// * now - current unix timestamp in seconds
+    ///
+    /// # Returns unix timestamp in seconds, now if call can be made right away
+    ///
+    pub fn next_slot(&self, now: i64) -> i64 {
+        let in_window = |window: i64, limit: u32| {
+            let recent: Vec<i64> = self
+                .calls
+                .iter()
+                .cloned()
+                .filter(|at| now - at < window)
+                .collect();
+            if limit == 0 {
+                return now + window;
+            }
+            let limit = limit as usize;
+            if recent.len() < limit {
+                now
+            } else {
+                recent[recent.len() - limit] + window
+            }
+        };
+        in_window(SECONDS_PER_MINUTE, self.quota.per_minute)
+            .max(in_window(SECONDS_PER_DAY, self.quota.per_day))
+    }
+
+    /// Registers call made at given moment
+    pub fn record(&mut self, at: i64) {
+        self.calls.push_back(at);
+        while let Some(first) = self.calls.front() {
+            if at - first >= SECONDS_PER_DAY {
+                self.calls.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Target {
+    source: Source,
+    next_due: i64,
+    /// The latest archived hour, read from archive once before the first fetch is saved
+    last_hour: Option<i64>,
+    resumed: bool,
+}
+
+/// Outcome of single collector pass
+#[derive(Debug, Default)]
+pub struct TickReport {
+    /// Sources fetched and archived successfully
+    pub fetched: Vec<Source>,
+    /// Sources that failed with error description
+    pub failed: Vec<(Source, String)>,
+    /// Newly discovered holes in archived series
+    pub gaps: Vec<(Source, Gap)>,
+}
+
+/// Periodically polls configured installations and points and archives results
+pub struct Collector<S: Store, C: Clock> {
+    client: AirlyClient,
+    index_type: types::IndexType,
+    include_wind: bool,
+    interval: i64,
+    targets: Vec<Target>,
+    limiter: RateLimiter,
+    store: S,
+    clock: C,
+}
+
+impl<S: Store, C: Clock> Collector<S, C> {
+    /// Constructs Collector, all targets are due immediately
+    ///
+    /// # Arguments:
+    /// * client - client used to fetch measurements
+    /// * config - targets, interval and quota
+    /// * store - archive for fetched measurements
+    /// * clock - time source driving the schedule
+    ///
+    pub fn new(client: AirlyClient, config: &Config, store: S, clock: C) -> Self {
+        let sources = config.sources();
+        let min_interval = config.quota.min_interval_secs(sources.len());
+        let interval = (config.interval_secs as i64).max(min_interval);
+        if interval > config.interval_secs as i64 {
+            warn!(
+                "Interval of {}s does not fit daily quota of {} for {} targets, using {}s",
+                config.interval_secs,
+                config.quota.per_day,
+                sources.len(),
+                interval
+            );
+        }
+        let now = clock.now();
+        let targets = sources
+            .into_iter()
+            .map(|source| Target {
+                source,
+                next_due: now,
+                last_hour: None,
+                resumed: false,
+            })
+            .collect();
+        Self {
+            client,
+            index_type: types::IndexType {
+                name: Some(config.index_type.clone()),
+                level: None,
+            },
+            include_wind: config.include_wind,
+            interval,
+            targets,
+            limiter: RateLimiter::new(config.quota),
+            store,
+            clock,
+        }
+    }
+
+    /// # Returns effective polling interval in seconds
+    pub fn interval_secs(&self) -> i64 {
+        self.interval
+    }
+
+    /// # Returns archive collected measurements are kept in
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Fetches and archives all targets that are due, as long as quota allows
+    ///
+    /// # Returns report of fetched and failed targets and newly found gaps
+    ///
+    pub fn tick(&mut self) -> TickReport {
+        let mut report = TickReport::default();
+        for i in 0..self.targets.len() {
+            let now = self.clock.now();
+            if self.targets[i].next_due > now {
+                continue;
+            }
+            if self.limiter.next_slot(now) > now {
+                break;
+            }
+            self.limiter.record(now);
+            let source = self.targets[i].source.clone();
+            match self.fetch(&source).and_then(|m| self.archive(i, now, m)) {
+                Ok(gaps) => {
+                    self.targets[i].next_due = now + self.interval;
+                    for gap in gaps {
+                        warn!(
+                            "Missing {} hour(s) of {} starting at {}",
+                            gap.hours(),
+                            source.key(),
+                            gap.from
+                        );
+                        report.gaps.push((source.clone(), gap));
+                    }
+                    info!("Archived measurements of {}", source.key());
+                    report.fetched.push(source);
+                }
+                Err(err) => {
+                    self.targets[i].next_due = now + self.interval.min(RETRY_AFTER_SECS);
+                    error!("Fetching {} failed: {}", source.key(), err);
+                    report.failed.push((source, err.to_string()));
+                }
+            }
+        }
+        report
+    }
+
+    /// # Returns unix timestamp in seconds of the moment next target can be fetched
+    pub fn next_wakeup(&self) -> i64 {
+        let now = self.clock.now();
+        let due = self
+            .targets
+            .iter()
+            .map(|target| target.next_due)
+            .min()
+            .unwrap_or(now + self.interval);
+        due.max(self.limiter.next_slot(now))
+    }
+
+    /// Runs collector until shutdown flag is raised, checked at least every second
+    ///
+    /// # Arguments:
+    /// * shutdown - flag raised e.g. by a signal handler to stop gracefully
+    ///
+    pub fn run(&mut self, shutdown: &AtomicBool) {
+        while !shutdown.load(Ordering::SeqCst) {
+            self.tick();
+            let wakeup = self.next_wakeup();
+            while self.clock.now() < wakeup && !shutdown.load(Ordering::SeqCst) {
+                self.clock.sleep(Duration::from_secs(1));
+            }
+        }
+        info!("Collector stopped");
+    }
+
+    fn fetch(&self, source: &Source) -> Result<types::Measurements, Box<dyn std::error::Error>> {
+        let client = self.client.clone();
+        let index_type = self.index_type.clone();
+        match source {
+            Source::Installation(id) if self.include_wind => {
+                client.get_installation_measurements_with_wind(*id, index_type)
+            }
+            Source::Installation(id) => {
+                client.get_installation_measurements_without_wind(*id, index_type)
+            }
+            Source::Point(point) => client.get_measurements_point(index_type, *point),
+        }
+    }
+
+    fn archive(
+        &mut self,
+        i: usize,
+        now: i64,
+        measurements: types::Measurements,
+    ) -> Result<Vec<Gap>, Box<dyn std::error::Error>> {
+        let source = self.targets[i].source.clone();
+        if !self.targets[i].resumed {
+            // archive of previous runs is read once, later gaps are found against the tracked last hour
+            self.targets[i].last_hour = self.store.series(&source)?.last().and_then(|v| v.from_timestamp());
+            self.targets[i].resumed = true;
+        }
+        let last_hour = self.targets[i].last_hour;
+        let mut hours: Vec<i64> = measurements
+            .history
+            .iter()
+            .chain(measurements.current.iter())
+            .filter_map(|v| v.from_timestamp())
+            .filter(|hour| !matches!(last_hour, Some(last) if *hour <= last))
+            .chain(last_hour)
+            .collect();
+        hours.sort_unstable();
+        hours.dedup();
+        self.store.save(&Record {
+            source,
+            fetched_at: now,
+            measurements,
+        })?;
+        self.targets[i].last_hour = hours.last().copied();
+        Ok(store::hour_gaps(&hours))
+    }
+}
+
+#[cfg(test)]
+mod test_collector {
+    use super::{Collector, Config, Quota, RateLimiter};
+    use crate::client::AirlyClient;
+    use crate::clock::{Clock, FakeClock};
+    use crate::store::{MemoryStore, Record, Source, Store};
+    use crate::stub::{StubServer, MEASUREMENTS_JSON};
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const API_KEY: &str = "00000000000000000000000000000000";
+
+    fn config(base_url: String, installations: Vec<u32>, quota: Quota) -> Config {
+        let mut config: Config = serde_json::from_str("{}").unwrap();
+        config.base_url = Some(base_url);
+        config.installations = installations;
+        config.quota = quota;
+        config
+    }
+
+    fn collector(config: &Config, clock: FakeClock) -> Collector<MemoryStore, FakeClock> {
+        let client = AirlyClient::new(API_KEY.to_string())
+            .unwrap()
+            .with_base_url(config.base_url.as_ref().unwrap());
+        Collector::new(client, config, MemoryStore::new(), clock)
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut limiter = RateLimiter::new(Quota {
+            per_minute: 2,
+            per_day: 3,
+        });
+        limiter.record(0);
+        limiter.record(10);
+        assert_eq!(limiter.next_slot(20), 60);
+        limiter.record(60);
+        assert_eq!(limiter.next_slot(120), 86_400);
+        assert_eq!(limiter.next_slot(86_401), 86_401);
+    }
+
+    #[test]
+    fn test_interval_stretched_to_daily_quota() {
+        let quota = Quota {
+            per_minute: 50,
+            per_day: 100,
+        };
+        assert_eq!(quota.min_interval_secs(5), 4320);
+        let conf = config("http://127.0.0.1:1/v2".to_string(), vec![1, 2, 3, 4, 5], quota);
+        assert_eq!(collector(&conf, FakeClock::new(0)).interval_secs(), 4320);
+    }
+
+    #[test]
+    fn test_tick_respects_quota_and_schedule() {
+        let server = StubServer::start(|_| (200, MEASUREMENTS_JSON.to_string()));
+        let quota = Quota {
+            per_minute: 2,
+            per_day: 100,
+        };
+        let conf = config(server.base_url(), vec![1, 2, 3], quota);
+        let clock = FakeClock::new(1_575_885_600);
+        let mut collector = collector(&conf, clock.clone());
+
+        assert_eq!(collector.tick().fetched.len(), 2);
+        assert_eq!(collector.next_wakeup(), clock.now() + 60);
+        clock.advance(60);
+        let report = collector.tick();
+        assert_eq!(report.fetched, vec![Source::Installation(3)]);
+        assert_eq!(collector.tick().fetched.len(), 0);
+        assert_eq!(server.requests().len(), 3);
+        assert!(server.requests()[0].contains("installationId=1"));
+        assert_eq!(collector.store().load(&Source::Installation(1)).unwrap().len(), 1);
+        assert_eq!(collector.store().series(&Source::Installation(1)).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_failures_and_gaps_are_reported() {
+        let server = StubServer::start(|url| {
+            if url.contains("installationId=2") {
+                (500, "{}".to_string())
+            } else {
+                (200, MEASUREMENTS_JSON.replace("2019-12-09T09:00", "2019-12-09T07:00"))
+            }
+        });
+        let conf = config(server.base_url(), vec![1, 2], Quota::default());
+        let mut collector = collector(&conf, FakeClock::new(0));
+        let report = collector.tick();
+        assert_eq!(report.fetched, vec![Source::Installation(1)]);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.gaps.len(), 1);
+        assert_eq!(report.gaps[0].1.hours(), 2);
+    }
+
+    /// MemoryStore counting how often archive is read
+    #[derive(Default)]
+    struct CountingStore {
+        store: MemoryStore,
+        loads: Cell<usize>,
+    }
+
+    impl Store for CountingStore {
+        fn save(&mut self, record: &Record) -> Result<(), Box<dyn std::error::Error>> {
+            self.store.save(record)
+        }
+
+        fn load(&self, source: &Source) -> Result<Vec<Record>, Box<dyn std::error::Error>> {
+            self.loads.set(self.loads.get() + 1);
+            self.store.load(source)
+        }
+    }
+
+    #[test]
+    fn test_gaps_between_ticks_found_without_reading_archive() {
+        let responses = Arc::new(AtomicUsize::new(0));
+        let served = responses.clone();
+        let server = StubServer::start(move |_| match served.fetch_add(1, Ordering::SeqCst) {
+            0 => (200, MEASUREMENTS_JSON.to_string()),
+            _ => (
+                200,
+                MEASUREMENTS_JSON
+                    .replace("2019-12-09T11:00", "2019-12-09T14:00")
+                    .replace("2019-12-09T10:00", "2019-12-09T13:00")
+                    .replace("2019-12-09T09:00", "2019-12-09T12:00"),
+            ),
+        });
+        let conf = config(server.base_url(), vec![1], Quota::default());
+        let clock = FakeClock::new(0);
+        let client = AirlyClient::new(API_KEY.to_string()).unwrap().with_base_url(&server.base_url());
+        let mut collector = Collector::new(client, &conf, CountingStore::default(), clock.clone());
+        assert!(collector.tick().gaps.is_empty());
+        clock.advance(collector.interval_secs());
+        let report = collector.tick();
+        assert_eq!(report.fetched.len(), 1);
+        assert_eq!(report.gaps.len(), 1);
+        assert_eq!(report.gaps[0].1.hours(), 1);
+        assert_eq!(collector.store().loads.get(), 1);
+        assert_eq!(collector.store().store.series(&Source::Installation(1)).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_run_stops_on_shutdown() {
+        let conf = config("http://127.0.0.1:1/v2".to_string(), vec![1], Quota::default());
+        let mut collector = collector(&conf, FakeClock::new(0));
+        collector.run(&AtomicBool::new(true));
+        assert!(collector.store().load(&Source::Installation(1)).unwrap().is_empty());
+    }
+}
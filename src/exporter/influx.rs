@@ -0,0 +1,230 @@
+use crate::transport::{DefaultTransport, HttpRequest, Transport};
+use crate::types;
+
+const NANOS_PER_SECOND: i64 = 1_000_000_000;
+
+/// Serializer of measurements to InfluxDB line protocol, one line per averaged period
+#[derive(Debug, Clone)]
+pub struct LineProtocol {
+    measurement: String,
+    tags: Vec<(String, String)>,
+}
+
+impl LineProtocol {
+    /// Constructs LineProtocol writing lines of given measurement without tags
+    ///
+    /// # Arguments:
+    /// * measurement - name of InfluxDB measurement, e.g. airly
+    ///
+    pub fn new(measurement: &str) -> Self {
+        Self {
+            measurement: measurement.to_string(),
+            tags: Vec::new(),
+        }
+    }
+
+    /// Adds tag to every line
+    pub fn with_tag(mut self, key: &str, value: &str) -> Self {
+        if !value.is_empty() {
+            self.tags.push((key.to_string(), value.to_string()));
+        }
+        self
+    }
+
+    /// Adds installation id and its address as tags to every line
+    pub fn with_installation(self, installation: &types::Installation) -> Self {
+        let address = &installation.address;
+        self.with_tag("installation", &installation.id.to_string())
+            .with_tag("country", &address.country)
+            .with_tag("city", &address.city)
+            .with_tag("street", &address.street)
+    }
+
+    /// Serializes single averaged period
+    ///
+    /// Values become fields named after lowercase measurement name, indexes fields named after
+    /// lowercase index name and standards `<name>_<pollutant>_percent` fields, first index name and level are tags
+    ///
+    /// # Arguments:
+    /// * values - averaged values of one period
+    ///
+    /// # Returns line with nanosecond timestamp of from_date_time, None if there are no fields or no valid timestamp
+    ///
+    pub fn line(&self, values: &types::AveragedValues) -> Option<String> {
+        let timestamp = values.from_timestamp()? * NANOS_PER_SECOND;
+        let mut fields = Vec::new();
+        for value in &values.values {
+            if let (Some(name), Some(v)) = (&value.name, value.value) {
+                fields.push((name.to_lowercase(), v));
+            }
+        }
+        for index in &values.indexes {
+            if let (Some(name), Some(v)) = (&index.name, index.value) {
+                fields.push((name.to_lowercase(), v));
+            }
+        }
+        for standard in &values.standards {
+            if let (Some(name), Some(pollutant), Some(v)) =
+                (&standard.name, &standard.pollutant, standard.percent)
+            {
+                fields.push((format!("{}_{}_percent", name, pollutant).to_lowercase(), v));
+            }
+        }
+        if fields.is_empty() {
+            return None;
+        }
+        let mut line = escape(&self.measurement, ", ");
+        let index = values.indexes.first();
+        let index_tags = [
+            ("index", index.and_then(|i| i.name.clone())),
+            ("level", index.and_then(|i| i.level.clone())),
+        ];
+        let index_tags = index_tags
+            .iter()
+            .filter_map(|(key, value)| value.as_ref().map(|v| (key.to_string(), v.clone())));
+        for (key, value) in self.tags.iter().cloned().chain(index_tags) {
+            line.push_str(&format!(",{}={}", escape(&key, ",= "), escape(&value, ",= ")));
+        }
+        let fields: Vec<String> = fields
+            .iter()
+            .map(|(key, value)| format!("{}={}", escape(key, ",= "), value))
+            .collect();
+        line.push_str(&format!(" {} {}", fields.join(","), timestamp));
+        Some(line)
+    }
+
+    /// Serializes history and current values, forecast is left out as it was not measured
+    ///
+    /// # Returns lines ordered from the oldest
+    ///
+    pub fn lines(&self, measurements: &types::Measurements) -> Vec<String> {
+        measurements
+            .history
+            .iter()
+            .chain(measurements.current.iter())
+            .filter_map(|values| self.line(values))
+            .collect()
+    }
+}
+
+fn escape(text: &str, special: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c == '\\' || special.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Pushes line protocol over http to InfluxDB or compatible receiver, e.g. VictoriaMetrics
+#[derive(Debug, Clone)]
+pub struct InfluxSink<T: Transport = DefaultTransport> {
+    url: String,
+    token: Option<String>,
+    transport: T,
+}
+
+impl InfluxSink {
+    /// Constructs InfluxSink using default transport
+    ///
+    /// # Arguments:
+    /// * url - full write url, e.g. http://localhost:8086/write?db=airly&precision=ns
+    ///
+    pub fn new(url: &str) -> Self {
+        InfluxSink::with_transport(url, DefaultTransport::default())
+    }
+}
+
+impl<T: Transport> InfluxSink<T> {
+    /// Constructs InfluxSink sending lines with given transport
+    pub fn with_transport(url: &str, transport: T) -> Self {
+        Self {
+            url: url.to_string(),
+            token: None,
+            transport,
+        }
+    }
+
+    /// Sets InfluxDB 2 api token sent as `Authorization: Token <token>` header
+    pub fn with_token(mut self, token: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        if !token.bytes().all(|b| b.is_ascii_graphic()) {
+            return Err("Influx token must consist of visible ascii characters".into());
+        }
+        self.token = Some(format!("Token {}", token));
+        Ok(self)
+    }
+
+    /// Writes lines in single request
+    ///
+    /// # Arguments:
+    /// * lines - line protocol lines, e.g. returned by LineProtocol::lines
+    ///
+    /// # Returns Success if receiver accepted lines Error otherwise
+    ///
+    pub fn push(&self, lines: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let mut request = HttpRequest::post(&self.url, lines.join("\n"))
+            .with_header("Content-Type", "text/plain; charset=utf-8");
+        if let Some(token) = &self.token {
+            request = request.with_header("Authorization", token);
+        }
+        let res = self.transport.send(request)?;
+        if !res.is_success() {
+            return Err(format!("Influx write rejected with {}: {}", res.status, res.body).into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_influx {
+    use super::{InfluxSink, LineProtocol};
+    use crate::stub::{StubServer, INSTALLATION_JSON, MEASUREMENTS_JSON};
+    use crate::types::{Installation, Measurements};
+
+    fn lines() -> Vec<String> {
+        let installation: Installation = serde_json::from_str(INSTALLATION_JSON).unwrap();
+        let measurements: Measurements = serde_json::from_str(MEASUREMENTS_JSON).unwrap();
+        LineProtocol::new("air quality")
+            .with_installation(&installation)
+            .with_tag("sensor", "a=b,c")
+            .lines(&measurements)
+    }
+
+    #[test]
+    fn test_lines() {
+        let lines = lines();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            "air\\ quality,installation=18,country=Poland,city=Kraków,street=Mikołajska,sensor=a\\=b\\,c,\
+             index=AIRLY_CAQI,level=LOW pm25=21.3,pm10=31.2,airly_caqi=40.1 1575882000000000000"
+        );
+        assert!(lines[1].contains(" pm1=13.61,pm25=19.58,"));
+        assert!(lines[1].ends_with(",who_pm25_percent=78.32,who_pm10_percent=59.96 1575885600000000000"));
+    }
+
+    #[test]
+    fn test_push_to_receiver() {
+        let server = StubServer::start(|url| {
+            if url.starts_with("/write") {
+                (204, String::new())
+            } else {
+                (404, "not found".to_string())
+            }
+        });
+        let root = server.base_url().replace("/v2", "");
+        let lines = lines();
+        InfluxSink::new(&format!("{}/write?db=airly", root))
+            .with_token("secret")
+            .unwrap()
+            .push(&lines)
+            .unwrap();
+        assert_eq!(server.bodies(), vec![lines.join("\n")]);
+        assert!(InfluxSink::new(&format!("{}/other", root)).push(&lines).is_err());
+    }
+}
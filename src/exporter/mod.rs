@@ -0,0 +1,423 @@
+use crate::client::AirlyClient;
+use crate::clock::Clock;
+use crate::collector::Quota;
+use crate::types;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub mod influx;
+pub mod openmetrics;
+
+const METRICS_PATH: &str = "/metrics";
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+const INSTALLATION_TTL_SECS: i64 = 86_400;
+const DEFAULT_TTL_SECS: i64 = 900;
+
+/// Current measurements of single installation ready to be rendered as metrics
+#[derive(Debug, Clone)]
+pub struct Sample {
+    /// ID of the installation
+    pub id: u32,
+    /// Address of the installation if known
+    pub address: Option<types::Address>,
+    /// Whether last attempt to refresh measurements succeeded
+    pub up: bool,
+    /// Most recently fetched measurements
+    pub measurements: Option<types::Measurements>,
+}
+
+/// Gauge metric family with its samples
+pub(crate) struct Gauge {
+    pub name: &'static str,
+    pub help: &'static str,
+    /// Labels, value and unix timestamp in seconds of the period value was measured in
+    pub samples: Vec<(String, f64, Option<i64>)>,
+}
+
+/// Builds gauges of current measurement values, index values and standard percents
+pub(crate) fn gauges(samples: &[Sample]) -> Vec<Gauge> {
+    let mut up = Vec::new();
+    let mut updated = Vec::new();
+    let mut values = Vec::new();
+    let mut indexes = Vec::new();
+    let mut standards = Vec::new();
+    for sample in samples {
+        let labels = installation_labels(sample);
+        up.push((labels.clone(), if sample.up { 1.0 } else { 0.0 }, None));
+        let current = match sample.measurements.as_ref().and_then(|m| m.current.as_ref()) {
+            Some(current) => current,
+            None => continue,
+        };
+        let timestamp = current.from_timestamp();
+        if let Some(from) = timestamp {
+            updated.push((labels.clone(), from as f64, None));
+        }
+        for value in &current.values {
+            if let (Some(name), Some(v)) = (&value.name, value.value) {
+                values.push((format!("{},name=\"{}\"", labels, escape(name)), v, timestamp));
+            }
+        }
+        for index in &current.indexes {
+            if let (Some(name), Some(v)) = (&index.name, index.value) {
+                let level = index.level.as_deref().unwrap_or("");
+                let labels = format!("{},name=\"{}\",level=\"{}\"", labels, escape(name), escape(level));
+                indexes.push((labels, v, timestamp));
+            }
+        }
+        for standard in &current.standards {
+            if let (Some(name), Some(pollutant), Some(v)) =
+                (&standard.name, &standard.pollutant, standard.percent)
+            {
+                let labels = format!(
+                    "{},name=\"{}\",pollutant=\"{}\"",
+                    labels,
+                    escape(name),
+                    escape(pollutant)
+                );
+                standards.push((labels, v, timestamp));
+            }
+        }
+    }
+    vec![
+        Gauge {
+            name: "airly_up",
+            help: "Whether last refresh of installation measurements succeeded",
+            samples: up,
+        },
+        Gauge {
+            name: "airly_current_from_timestamp_seconds",
+            help: "Start of the period current measurements are averaged over",
+            samples: updated,
+        },
+        Gauge {
+            name: "airly_measurement",
+            help: "Current averaged measurement value",
+            samples: values,
+        },
+        Gauge {
+            name: "airly_index",
+            help: "Current index value",
+            samples: indexes,
+        },
+        Gauge {
+            name: "airly_standard_percent",
+            help: "Current pollutant measurement as percent of the standard limit",
+            samples: standards,
+        },
+    ]
+}
+
+/// Renders samples in Prometheus text exposition format
+///
+/// # Arguments:
+/// * samples - installations with their current measurements
+///
+/// # Returns text with gauges of measurement values, index values and standard percents
+///
+pub fn render(samples: &[Sample]) -> String {
+    let mut out = String::new();
+    for gauge in gauges(samples) {
+        if gauge.samples.is_empty() {
+            continue;
+        }
+        writeln!(out, "# HELP {} {}", gauge.name, gauge.help).unwrap();
+        writeln!(out, "# TYPE {} gauge", gauge.name).unwrap();
+        for (labels, value, _) in gauge.samples {
+            writeln!(out, "{}{{{}}} {}", gauge.name, labels, value).unwrap();
+        }
+    }
+    out
+}
+
+fn installation_labels(sample: &Sample) -> String {
+    let (city, street) = sample
+        .address
+        .as_ref()
+        .map(|a| (a.city.as_str(), a.street.as_str()))
+        .unwrap_or(("", ""));
+    format!(
+        "installation=\"{}\",city=\"{}\",street=\"{}\"",
+        sample.id,
+        escape(city),
+        escape(street)
+    )
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Data fetched for expired entry, None where fetching failed or was not needed
+#[derive(Debug, Default)]
+struct Refresh {
+    installation: Option<types::Installation>,
+    measurements: Option<types::Measurements>,
+}
+
+#[derive(Debug, Default)]
+struct Entry {
+    installation: Option<(i64, types::Installation)>,
+    measurements: Option<types::Measurements>,
+    refreshed_at: Option<i64>,
+    up: bool,
+}
+
+/// Serves current measurements of installations as Prometheus metrics,
+/// measurements are cached so scrapes do not consume api quota
+pub struct Exporter<C: Clock> {
+    client: AirlyClient,
+    installations: Vec<u32>,
+    index_type: types::IndexType,
+    ttl: i64,
+    clock: C,
+    entries: Mutex<HashMap<u32, Entry>>,
+}
+
+impl<C: Clock> Exporter<C> {
+    /// Constructs Exporter, cache time to live defaults to 15 minutes stretched to fit the default daily quota
+    ///
+    /// # Arguments:
+    /// * client - client used to fetch installations and measurements
+    /// * installations - ids of exported installations
+    /// * clock - time source for cache expiry
+    ///
+    pub fn new(client: AirlyClient, installations: Vec<u32>, clock: C) -> Self {
+        let ttl = DEFAULT_TTL_SECS.max(Quota::default().min_interval_secs(installations.len()));
+        Self {
+            client,
+            installations,
+            index_type: types::IndexType {
+                name: Some("AIRLY_CAQI".to_string()),
+                level: None,
+            },
+            ttl,
+            clock,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets how long fetched measurements are served before refreshing
+    pub fn with_ttl_secs(mut self, ttl: i64) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets index requested with measurements, AIRLY_CAQI by default
+    pub fn with_index_type(mut self, index_type: types::IndexType) -> Self {
+        self.index_type = index_type;
+        self
+    }
+
+    /// Refreshes expired entries and renders metrics of all installations, entries are fetched without
+    /// holding the lock, so concurrent scrapes are served cached values meanwhile
+    ///
+    /// # Returns Prometheus text exposition
+    ///
+    pub fn metrics(&self) -> String {
+        let now = self.clock.now();
+        let expired = self.claim_expired(now);
+        let fetched: Vec<(u32, Refresh)> = expired
+            .into_iter()
+            .map(|(id, installation_expired)| (id, self.fetch(id, installation_expired)))
+            .collect();
+        let mut entries = self.entries.lock().unwrap();
+        for (id, refresh) in fetched {
+            let entry = entries.entry(id).or_default();
+            if let Some(installation) = refresh.installation {
+                entry.installation = Some((now, installation));
+            }
+            entry.up = refresh.measurements.is_some();
+            if let Some(measurements) = refresh.measurements {
+                entry.measurements = Some(measurements);
+            }
+        }
+        let mut samples = Vec::new();
+        for id in &self.installations {
+            let entry = entries.entry(*id).or_default();
+            samples.push(Sample {
+                id: *id,
+                address: entry.installation.as_ref().map(|(_, i)| i.address.clone()),
+                up: entry.up,
+                measurements: entry.measurements.clone(),
+            });
+        }
+        render(&samples)
+    }
+
+    /// Handles http request, embeddable in other servers
+    ///
+    /// # Arguments:
+    /// * path - requested path
+    ///
+    /// # Returns status code, content type and body of the response
+    ///
+    pub fn handle(&self, path: &str) -> (u16, &'static str, String) {
+        if path.split('?').next() == Some(METRICS_PATH) {
+            (200, CONTENT_TYPE, self.metrics())
+        } else {
+            (404, "text/plain", "Not found".to_string())
+        }
+    }
+
+    /// Serves metrics over http until shutdown flag is raised
+    ///
+    /// # Arguments:
+    /// * addr - address to listen on, e.g. 0.0.0.0:9188
+    /// * shutdown - flag stopping the server
+    ///
+    /// # Returns Ok after shutdown or Error if address can not be bound
+    ///
+    pub fn serve(&self, addr: &str, shutdown: &AtomicBool) -> Result<(), Box<dyn std::error::Error>> {
+        let server = tiny_http::Server::http(addr).map_err(|e| e.to_string())?;
+        self.serve_on(server, shutdown)
+    }
+
+    fn serve_on(&self, server: tiny_http::Server, shutdown: &AtomicBool) -> Result<(), Box<dyn std::error::Error>> {
+        info!("Serving metrics on http://{}{}", server.server_addr(), METRICS_PATH);
+        while !shutdown.load(Ordering::SeqCst) {
+            if let Some(request) = server.recv_timeout(Duration::from_millis(200))? {
+                let (status, content_type, body) = self.handle(request.url());
+                let header = tiny_http::Header::from_bytes(&b"Content-Type"[..], content_type.as_bytes())
+                    .expect("valid content type header");
+                let response = tiny_http::Response::from_string(body)
+                    .with_status_code(status)
+                    .with_header(header);
+                if let Err(err) = request.respond(response) {
+                    warn!("Responding to scrape failed: {}", err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Marks expired entries refreshed, so concurrent scrapes do not fetch them again
+    ///
+    /// # Returns ids of expired entries and whether their installation expired too
+    ///
+    fn claim_expired(&self, now: i64) -> Vec<(u32, bool)> {
+        let mut entries = self.entries.lock().unwrap();
+        let mut expired = Vec::new();
+        for id in &self.installations {
+            let entry = entries.entry(*id).or_default();
+            if matches!(entry.refreshed_at, Some(at) if now - at < self.ttl) {
+                continue;
+            }
+            entry.refreshed_at = Some(now);
+            let installation_expired = match &entry.installation {
+                Some((fetched_at, _)) => now - fetched_at >= INSTALLATION_TTL_SECS,
+                None => true,
+            };
+            expired.push((*id, installation_expired));
+        }
+        expired
+    }
+
+    fn fetch(&self, id: u32, installation_expired: bool) -> Refresh {
+        let mut refresh = Refresh::default();
+        if installation_expired {
+            match self.client.clone().get_installation(id) {
+                Ok(installation) => refresh.installation = Some(installation),
+                Err(err) => warn!("Fetching installation {} failed: {}", id, err),
+            }
+        }
+        match self
+            .client
+            .clone()
+            .get_installation_measurements_without_wind(id, self.index_type.clone())
+        {
+            Ok(measurements) => refresh.measurements = Some(measurements),
+            Err(err) => warn!("Fetching measurements of installation {} failed: {}", id, err),
+        }
+        refresh
+    }
+}
+
+#[cfg(test)]
+mod test_exporter {
+    use super::Exporter;
+    use crate::client::AirlyClient;
+    use crate::clock::FakeClock;
+    use crate::stub::{StubServer, INSTALLATION_JSON, MEASUREMENTS_JSON};
+    use crate::transport::{DefaultTransport, HttpRequest, Transport};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    const API_KEY: &str = "00000000000000000000000000000000";
+
+    fn stub() -> StubServer {
+        StubServer::start(|url| {
+            if url.starts_with("/v2/installations/18") {
+                (200, INSTALLATION_JSON.to_string())
+            } else if url.contains("installationId=18") {
+                (200, MEASUREMENTS_JSON.to_string())
+            } else {
+                (404, "{}".to_string())
+            }
+        })
+    }
+
+    fn exporter(server: &StubServer, ids: Vec<u32>, clock: FakeClock) -> Exporter<FakeClock> {
+        let client = AirlyClient::new(API_KEY.to_string())
+            .unwrap()
+            .with_base_url(&server.base_url());
+        Exporter::new(client, ids, clock).with_ttl_secs(600)
+    }
+
+    #[test]
+    fn test_metrics_rendered_with_labels() {
+        let server = stub();
+        let exporter = exporter(&server, vec![18, 19], FakeClock::new(0));
+        let (status, _, body) = exporter.handle("/metrics");
+        assert_eq!(status, 200);
+        let labels = "installation=\"18\",city=\"Kraków\",street=\"Mikołajska\"";
+        assert!(body.contains(&format!("airly_measurement{{{},name=\"PM25\"}} 19.58", labels)));
+        assert!(body.contains(&format!(
+            "airly_index{{{},name=\"AIRLY_CAQI\",level=\"LOW\"}} 37.98",
+            labels
+        )));
+        assert!(body.contains(&format!(
+            "airly_standard_percent{{{},name=\"WHO\",pollutant=\"PM10\"}} 59.96",
+            labels
+        )));
+        assert!(body.contains("airly_up{installation=\"19\",city=\"\",street=\"\"} 0"));
+        assert_eq!(exporter.handle("/other").0, 404);
+    }
+
+    #[test]
+    fn test_scrapes_served_from_cache() {
+        let server = stub();
+        let clock = FakeClock::new(0);
+        let exporter = exporter(&server, vec![18], clock.clone());
+        exporter.metrics();
+        exporter.metrics();
+        assert_eq!(server.requests().len(), 2);
+        clock.advance(600);
+        exporter.metrics();
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn test_serve_over_http() {
+        let server = stub();
+        let exporter = Arc::new(exporter(&server, vec![18], FakeClock::new(0)));
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let http = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let addr = http.server_addr();
+        let (serving, stop) = (exporter.clone(), shutdown.clone());
+        let handle = thread::spawn(move || serving.serve_on(http, &stop).unwrap());
+        let body = DefaultTransport::default()
+            .send(HttpRequest::get(&format!("http://{}/metrics", addr)))
+            .unwrap()
+            .body;
+        assert!(body.contains("# TYPE airly_measurement gauge"));
+        shutdown.store(true, Ordering::SeqCst);
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,54 @@
+use super::{gauges, Sample};
+use std::fmt::Write;
+
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Renders samples in OpenMetrics text format, values carry timestamp of the hour they were measured in
+///
+/// # Arguments:
+/// * samples - installations with their current measurements
+///
+/// # Returns OpenMetrics exposition terminated with # EOF
+///
+pub fn render(samples: &[Sample]) -> String {
+    let mut out = String::new();
+    for gauge in gauges(samples) {
+        if gauge.samples.is_empty() {
+            continue;
+        }
+        writeln!(out, "# TYPE {} gauge", gauge.name).unwrap();
+        writeln!(out, "# HELP {} {}", gauge.name, gauge.help).unwrap();
+        for (labels, value, timestamp) in gauge.samples {
+            match timestamp {
+                Some(at) => writeln!(out, "{}{{{}}} {} {}", gauge.name, labels, value, at),
+                None => writeln!(out, "{}{{{}}} {}", gauge.name, labels, value),
+            }
+            .unwrap();
+        }
+    }
+    out.push_str("# EOF\n");
+    out
+}
+
+#[cfg(test)]
+mod test_openmetrics {
+    use super::render;
+    use crate::exporter::Sample;
+    use crate::stub::MEASUREMENTS_JSON;
+
+    #[test]
+    fn test_render_with_timestamps() {
+        let sample = Sample {
+            id: 18,
+            address: None,
+            up: true,
+            measurements: Some(serde_json::from_str(MEASUREMENTS_JSON).unwrap()),
+        };
+        let text = render(&[sample]);
+        assert!(text.starts_with("# TYPE airly_up gauge\n"));
+        assert!(text.contains(
+            "airly_measurement{installation=\"18\",city=\"\",street=\"\",name=\"PM10\"} 29.98 1575885600\n"
+        ));
+        assert!(text.ends_with("# EOF\n"));
+    }
+}
@@ -0,0 +1,408 @@
+//! Offline labelling of points with city and district, and search of installations by address
+use crate::client::AirlyClient;
+use crate::transport::Transport;
+use crate::types::{GeoCircle, GeoPoint, Installation};
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs;
+
+/// Places of Polish cities and districts of Kraków and Warszawa bundled with crate
+const BUNDLED_GAZETTEER: &str = include_str!("gazetteer.json");
+/// Distance within which address of installation labels point, 500 m by default
+const DEFAULT_INSTALLATION_KM: f64 = 0.5;
+
+/// City or district approximated by circle
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Place {
+    pub country: String,
+    pub city: String,
+    /// None for place covering whole city
+    #[serde(default)]
+    pub district: Option<String>,
+    #[serde(flatten)]
+    pub location: GeoPoint,
+    #[serde(rename = "radiusKm")]
+    pub radius_km: f64,
+}
+
+/// List of places points are labelled with when no installation is near
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Gazetteer {
+    pub places: Vec<Place>,
+}
+
+impl Default for Gazetteer {
+    fn default() -> Self {
+        Gazetteer::bundled()
+    }
+}
+
+impl Gazetteer {
+    /// # Returns gazetteer bundled with crate, Polish cities with districts of Kraków and Warszawa
+    pub fn bundled() -> Self {
+        serde_json::from_str(BUNDLED_GAZETTEER).expect("bundled gazetteer is valid")
+    }
+
+    /// Reads gazetteer from JSON file
+    ///
+    /// # Arguments:
+    /// * path - path to file of the same format as bundled gazetteer.json
+    ///
+    /// # Returns Gazetteer if file is readable and valid Error otherwise
+    ///
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = fs::read_to_string(path)?;
+        let gazetteer: Gazetteer = serde_json::from_str(&text)?;
+        Ok(gazetteer)
+    }
+
+    /// # Returns the nearest place covering point, districts before whole cities, None if no place covers it
+    pub fn place(&self, point: GeoPoint) -> Option<(&Place, f64)> {
+        self.places
+            .iter()
+            .map(|place| (place, place.location.distance_km(point)))
+            .filter(|(place, distance)| *distance <= place.radius_km)
+            .min_by(|a, b| {
+                b.0.district
+                    .is_some()
+                    .cmp(&a.0.district.is_some())
+                    .then(a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+            })
+    }
+
+    /// # Returns the nearest district of city covering point
+    fn district(&self, city: &str, point: GeoPoint) -> Option<&str> {
+        self.places
+            .iter()
+            .filter(|place| fold(&place.city) == fold(city))
+            .filter_map(|place| place.district.as_ref().map(|district| (district, place.location.distance_km(point), place)))
+            .filter(|(_, distance, place)| *distance <= place.radius_km)
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+            .map(|(district, _, _)| district.as_str())
+    }
+
+    /// Labels point with place covering it
+    ///
+    /// # Returns Label or None if no place covers point
+    ///
+    pub fn label(&self, point: GeoPoint) -> Option<Label> {
+        self.place(point).map(|(place, distance_km)| Label {
+            country: place.country.clone(),
+            city: place.city.clone(),
+            district: place.district.clone(),
+            street: None,
+            source: LabelSource::Gazetteer,
+            distance_km,
+        })
+    }
+}
+
+/// Where label of point comes from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LabelSource {
+    /// Address of installation with given id
+    Installation(i32),
+    Gazetteer,
+}
+
+/// Location label of point
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub country: String,
+    pub city: String,
+    pub district: Option<String>,
+    /// Street and number of installation labelling point
+    pub street: Option<String>,
+    pub source: LabelSource,
+    /// Distance from point to installation or center of place
+    pub distance_km: f64,
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.city)?;
+        if let Some(district) = &self.district {
+            write!(f, ", {}", district)?;
+        }
+        if let Some(street) = &self.street {
+            write!(f, ", {}", street)?;
+        }
+        Ok(())
+    }
+}
+
+/// Installations kept locally, e.g. fetched once and saved to file
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    installations: Vec<Installation>,
+}
+
+impl Registry {
+    pub fn new(installations: Vec<Installation>) -> Self {
+        let mut registry = Registry::default();
+        registry.extend(installations);
+        registry
+    }
+
+    /// Fetches installations around point with get_nearest
+    ///
+    /// # Arguments:
+    /// * client - api client
+    /// * circle - area to fetch installations of
+    /// * max_results - maximum number of installations
+    ///
+    /// # Returns Registry or Error if request fails
+    ///
+    pub fn fetch<T: Transport>(
+        client: &AirlyClient<T>,
+        circle: GeoCircle,
+        max_results: u32,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Registry::new(client.clone().get_nearest(circle, max_results)?))
+    }
+
+    /// Reads registry saved with save
+    ///
+    /// # Returns Registry if file is readable and valid Error otherwise
+    ///
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = fs::read_to_string(path)?;
+        let installations: Vec<Installation> = serde_json::from_str(&text)?;
+        Ok(Registry::new(installations))
+    }
+
+    /// Saves installations to file as JSON array
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        fs::write(path, serde_json::to_string(&self.installations)?)?;
+        Ok(())
+    }
+
+    /// Adds installations, replacing ones with the same id
+    pub fn extend(&mut self, installations: Vec<Installation>) {
+        for installation in installations {
+            match self.installations.iter_mut().find(|known| known.id == installation.id) {
+                Some(known) => *known = installation,
+                None => self.installations.push(installation),
+            }
+        }
+    }
+
+    pub fn installations(&self) -> &[Installation] {
+        &self.installations
+    }
+
+    pub fn get(&self, id: i32) -> Option<&Installation> {
+        self.installations.iter().find(|installation| installation.id == id)
+    }
+
+    /// # Returns the nearest installation with its distance in km, None for empty registry
+    pub fn nearest(&self, point: GeoPoint) -> Option<(&Installation, f64)> {
+        self.installations
+            .iter()
+            .map(|installation| (installation, installation.location.distance_km(point)))
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+    }
+
+    /// Searches installations by address
+    ///
+    /// # Arguments:
+    /// * query - words all found in city, street or display address, ignoring case and Polish diacritics,
+    ///   e.g. "krakow mikolajska"
+    ///
+    /// # Returns matching installations in registry order
+    ///
+    pub fn search(&self, query: &str) -> Vec<&Installation> {
+        let words: Vec<String> = query.split_whitespace().map(fold).collect();
+        if words.is_empty() {
+            return Vec::new();
+        }
+        self.installations
+            .iter()
+            .filter(|installation| {
+                let address = &installation.address;
+                let text = fold(&format!(
+                    "{} {} {} {} {}",
+                    address.city,
+                    address.street,
+                    address.number,
+                    address.display_address1.as_deref().unwrap_or(""),
+                    address.display_address2.as_deref().unwrap_or("")
+                ));
+                words.iter().all(|word| text.contains(word.as_str()))
+            })
+            .collect()
+    }
+}
+
+/// Lowercases text and strips Polish diacritics
+fn fold(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'ą' => 'a',
+            'ć' => 'c',
+            'ę' => 'e',
+            'ł' => 'l',
+            'ń' => 'n',
+            'ó' => 'o',
+            'ś' => 's',
+            'ź' | 'ż' => 'z',
+            c => c,
+        })
+        .collect()
+}
+
+/// Labels points with address of the nearest known installation or place of gazetteer
+#[derive(Debug, Clone)]
+pub struct Geocoder {
+    registry: Registry,
+    gazetteer: Gazetteer,
+    installation_km: f64,
+}
+
+impl Geocoder {
+    /// Constructs geocoder of registry with bundled gazetteer
+    pub fn new(registry: Registry) -> Self {
+        Self {
+            registry,
+            gazetteer: Gazetteer::bundled(),
+            installation_km: DEFAULT_INSTALLATION_KM,
+        }
+    }
+
+    /// Sets gazetteer used for points without installation near, bundled one by default
+    pub fn with_gazetteer(mut self, gazetteer: Gazetteer) -> Self {
+        self.gazetteer = gazetteer;
+        self
+    }
+
+    /// Sets distance within which address of installation labels point, 0.5 km by default
+    pub fn with_installation_km(mut self, installation_km: f64) -> Self {
+        self.installation_km = installation_km;
+        self
+    }
+
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Labels point
+    ///
+    /// # Arguments:
+    /// * point - any point, e.g. one passed to get_measurements_point
+    ///
+    /// # Returns Label of the nearest installation within installation distance, with district from gazetteer,
+    /// Label of gazetteer place covering point otherwise, None if neither is found
+    ///
+    pub fn label(&self, point: GeoPoint) -> Option<Label> {
+        match self.registry.nearest(point) {
+            Some((installation, distance_km)) if distance_km <= self.installation_km => {
+                let address = &installation.address;
+                let street = format!("{} {}", address.street, address.number).trim().to_string();
+                Some(Label {
+                    country: address.country.clone(),
+                    city: address.city.clone(),
+                    district: self.gazetteer.district(&address.city, point).map(str::to_string),
+                    street: if street.is_empty() { None } else { Some(street) },
+                    source: LabelSource::Installation(installation.id),
+                    distance_km,
+                })
+            }
+            _ => self.gazetteer.label(point),
+        }
+    }
+
+    /// Searches installations of registry by address, see Registry::search
+    pub fn search(&self, query: &str) -> Vec<&Installation> {
+        self.registry.search(query)
+    }
+}
+
+#[cfg(test)]
+mod test_geocode {
+    use super::{Gazetteer, Geocoder, LabelSource, Registry};
+    use crate::mock::MockAirly;
+    use crate::stub::INSTALLATION_JSON;
+    use crate::types::{GeoCircle, GeoPoint, Installation};
+    use std::env;
+
+    fn installation(id: i32, lat: f32, lng: f32, city: &str, street: &str) -> Installation {
+        let mut installation: Installation = serde_json::from_str(INSTALLATION_JSON).unwrap();
+        installation.id = id;
+        installation.location = GeoPoint::new(lat, lng).unwrap();
+        installation.address.city = city.to_string();
+        installation.address.street = street.to_string();
+        installation.address.display_address1 = Some(city.to_string());
+        installation.address.display_address2 = Some(street.to_string());
+        installation
+    }
+
+    fn registry() -> Registry {
+        Registry::new(vec![
+            installation(18, 50.062006, 19.940984, "Kraków", "Mikołajska"),
+            installation(19, 52.2297, 21.0122, "Warszawa", "Marszałkowska"),
+            installation(20, 50.0645, 19.9237, "Kraków", "Zwierzyniecka"),
+        ])
+    }
+
+    #[test]
+    fn test_gazetteer_label() {
+        let gazetteer = Gazetteer::bundled();
+        let rynek = gazetteer.label(GeoPoint::new(50.0617, 19.9373).unwrap()).unwrap();
+        assert_eq!(rynek.to_string(), "Kraków, Stare Miasto");
+        assert_eq!(rynek.source, LabelSource::Gazetteer);
+        let mokotow = gazetteer.label(GeoPoint::new(52.1937, 21.0288).unwrap()).unwrap();
+        assert_eq!(mokotow.district.as_deref(), Some("Mokotów"));
+        let opole = gazetteer.label(GeoPoint::new(50.67, 17.93).unwrap()).unwrap();
+        assert_eq!(opole.to_string(), "Opole");
+        assert!(gazetteer.label(GeoPoint::new(40.0, -30.0).unwrap()).is_none());
+
+        let own: Gazetteer = serde_json::from_str(
+            r#"{"places": [{"country": "Czechia", "city": "Ostrava", "latitude": 49.82, "longitude": 18.26, "radiusKm": 8}]}"#,
+        )
+        .unwrap();
+        assert_eq!(own.label(GeoPoint::new(49.83, 18.27).unwrap()).unwrap().country, "Czechia");
+    }
+
+    #[test]
+    fn test_geocoder_label_and_search() {
+        let geocoder = Geocoder::new(registry());
+        let near = geocoder.label(GeoPoint::new(50.0622, 19.9412).unwrap()).unwrap();
+        assert_eq!(near.source, LabelSource::Installation(18));
+        assert_eq!(near.to_string(), "Kraków, Stare Miasto, Mikołajska 4");
+        assert!(near.distance_km < 0.1);
+        let far = geocoder.label(GeoPoint::new(50.0300, 19.9000).unwrap()).unwrap();
+        assert_eq!(far.source, LabelSource::Gazetteer);
+        assert_eq!(far.district.as_deref(), Some("Dębniki"));
+        let strict = Geocoder::new(registry()).with_installation_km(0.01);
+        assert_eq!(strict.label(GeoPoint::new(50.0622, 19.9412).unwrap()).unwrap().source, LabelSource::Gazetteer);
+
+        let ids = |query: &str| geocoder.search(query).iter().map(|i| i.id).collect::<Vec<i32>>();
+        assert_eq!(ids("krakow"), vec![18, 20]);
+        assert_eq!(ids("KRAKÓW mikolajska"), vec![18]);
+        assert_eq!(ids("marszałk"), vec![19]);
+        assert!(ids("gdansk").is_empty());
+        assert!(ids(" ").is_empty());
+    }
+
+    #[test]
+    fn test_registry_fetch_and_file() {
+        let mock = MockAirly::start().unwrap();
+        mock.add_installation(serde_json::from_str(INSTALLATION_JSON).unwrap());
+        let client = mock.client("0123456789abcdef0123456789abcdef").unwrap();
+        let circle = GeoCircle::new(GeoPoint::new(50.06, 19.94).unwrap(), 5).unwrap();
+        let mut fetched = Registry::fetch(&client, circle, 10).unwrap();
+        assert_eq!(fetched.installations().len(), 1);
+        fetched.extend(registry().installations().to_vec());
+        assert_eq!(fetched.installations().len(), 3);
+        assert_eq!(fetched.get(18).unwrap().address.street, "Mikołajska");
+
+        let path = env::temp_dir().join(format!("airly_rs_registry_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        fetched.save(path).unwrap();
+        let loaded = Registry::from_file(path).unwrap();
+        assert_eq!(loaded.installations().len(), 3);
+        assert_eq!(loaded.search("warszawa").len(), 1);
+        std::fs::remove_file(path).unwrap();
+    }
+}
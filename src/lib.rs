@@ -1,6 +1,26 @@
 #[macro_use]
 extern crate serde_derive;
-extern crate reqwest;
+#[macro_use]
+extern crate log;
 
+pub mod advisor;
+pub mod alert;
+pub mod analysis;
+pub mod cache;
+pub mod cassette;
 pub mod client;
+pub mod clock;
+pub mod collector;
+pub mod exporter;
+pub mod geocode;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod mqtt;
+pub mod route;
+pub mod store;
+pub mod transport;
 pub mod types;
+pub mod wind;
+
+#[cfg(test)]
+mod stub;
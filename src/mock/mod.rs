@@ -0,0 +1,554 @@
+//! Local http server emulating Airly api v2, lets services depending on this crate be tested without
+//! network and api key. Compiled with `mock` feature.
+
+use crate::client::AirlyClient;
+use crate::collector::Quota;
+use crate::types::{GeoPoint, IndexType, Installation, MeasurementType, Measurements};
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const API_ROOT: &str = "/v2/";
+const DEFAULT_INDEXES: [&str; 3] = ["AIRLY_CAQI", "CAQI", "PIJP"];
+const DEFAULT_MEASUREMENT_TYPES: [(&str, &str, &str); 8] = [
+    ("PM1", "PM1", "µg/m³"),
+    ("PM25", "PM2.5", "µg/m³"),
+    ("PM10", "PM10", "µg/m³"),
+    ("TEMPERATURE", "Temperature", "°C"),
+    ("HUMIDITY", "Humidity", "%"),
+    ("PRESSURE", "Pressure", "hPa"),
+    ("WIND_SPEED", "Wind speed", "km/h"),
+    ("WIND_BEARING", "Wind bearing", "°"),
+];
+
+/// Error mock server answers with instead of regular response
+#[derive(Debug, Clone, PartialEq)]
+pub enum Failure {
+    /// 401, as for missing or invalid api key
+    Unauthorized,
+    /// 404, as for unknown installation
+    NotFound,
+    /// 429 with rate limit headers showing no remaining requests
+    RateLimited { retry_after_secs: u64 },
+    /// 500
+    ServerError,
+    /// Regular response sent after delay
+    Slow(Duration),
+    /// 200 with body that is not valid json
+    MalformedJson,
+}
+
+/// Request received by MockAirly
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub method: String,
+    /// Path with query relative to api root, e.g. installations/18
+    pub path: String,
+    /// Header names lowercased with their values
+    pub headers: Vec<(String, String)>,
+}
+
+impl MockRequest {
+    /// # Returns value of header with given lowercase name if it was sent
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    installations: Vec<Installation>,
+    measurements: HashMap<i32, Measurements>,
+    point_measurements: Option<Measurements>,
+    indices: Option<Vec<IndexType>>,
+    measurement_types: Option<Vec<MeasurementType>>,
+    api_keys: Option<Vec<String>>,
+    quota: Option<Quota>,
+    usage: HashMap<String, Vec<Instant>>,
+    failures: VecDeque<Failure>,
+    requests: Vec<MockRequest>,
+}
+
+type Reply = (u16, Vec<(String, String)>, String);
+
+/// Mock Airly api server, stops when dropped
+pub struct MockAirly {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    running: Arc<AtomicBool>,
+}
+
+impl MockAirly {
+    /// Starts server on random local port, with no installations and accepting any api key
+    ///
+    /// # Returns MockAirly if port could be bound Error otherwise
+    ///
+    pub fn start() -> Result<Self, Box<dyn std::error::Error>> {
+        let server = tiny_http::Server::http("127.0.0.1:0").map_err(|e| e.to_string())?;
+        let addr = server.server_addr();
+        let state = Arc::new(Mutex::new(State::default()));
+        let running = Arc::new(AtomicBool::new(true));
+        let (shared, alive) = (state.clone(), running.clone());
+        thread::spawn(move || {
+            while alive.load(Ordering::SeqCst) {
+                if let Ok(Some(request)) = server.recv_timeout(Duration::from_millis(20)) {
+                    let state = shared.clone();
+                    thread::spawn(move || respond(request, &state));
+                }
+            }
+        });
+        Ok(Self {
+            addr,
+            state,
+            running,
+        })
+    }
+
+    /// # Returns api root to be passed to AirlyClient::with_base_url
+    pub fn base_url(&self) -> String {
+        format!("http://{}/v2", self.addr)
+    }
+
+    /// Constructs client pointed at this server
+    ///
+    /// # Arguments:
+    /// * api_key - key sent by client, any 32 characters if server accepts all keys
+    ///
+    /// # Returns AirlyClient if api_key of correct length Error otherwise
+    ///
+    pub fn client(&self, api_key: &str) -> Result<AirlyClient, Box<dyn std::error::Error>> {
+        Ok(AirlyClient::new(api_key.to_string())?.with_base_url(&self.base_url()))
+    }
+
+    /// Adds installation served by installation and nearest endpoints, replaces one with the same id
+    pub fn add_installation(&self, installation: Installation) {
+        let mut state = self.state.lock().unwrap();
+        state.installations.retain(|i| i.id != installation.id);
+        state.installations.push(installation);
+    }
+
+    /// Sets measurements of installation, installations without them get empty measurements
+    pub fn set_measurements(&self, id: i32, measurements: Measurements) {
+        self.state.lock().unwrap().measurements.insert(id, measurements);
+    }
+
+    /// Sets measurements served by point endpoint, measurements of nearest installation are served otherwise
+    pub fn set_point_measurements(&self, measurements: Measurements) {
+        self.state.lock().unwrap().point_measurements = Some(measurements);
+    }
+
+    /// Replaces default AIRLY_CAQI, CAQI and PIJP index types
+    pub fn set_indices(&self, indices: Vec<IndexType>) {
+        self.state.lock().unwrap().indices = Some(indices);
+    }
+
+    /// Replaces default particulate matter and weather measurement types
+    pub fn set_measurement_types(&self, measurement_types: Vec<MeasurementType>) {
+        self.state.lock().unwrap().measurement_types = Some(measurement_types);
+    }
+
+    /// Accepts only given api keys, requests with other keys get 401
+    pub fn require_api_keys(&self, keys: &[&str]) {
+        self.state.lock().unwrap().api_keys = Some(keys.iter().map(|key| key.to_string()).collect());
+    }
+
+    /// Limits requests of every api key, responses carry X-RateLimit headers, exceeded limit gets 429
+    pub fn set_quota(&self, quota: Quota) {
+        self.state.lock().unwrap().quota = Some(quota);
+    }
+
+    /// Answers following requests with failure
+    ///
+    /// # Arguments:
+    /// * failure - error to inject
+    /// * times - number of requests failing, queued after previously injected failures
+    ///
+    pub fn fail_next(&self, failure: Failure, times: usize) {
+        let mut state = self.state.lock().unwrap();
+        for _ in 0..times {
+            state.failures.push_back(failure.clone());
+        }
+    }
+
+    /// # Returns all requests received so far
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// # Returns number of received requests which path starts with given prefix, e.g. measurements/
+    pub fn requests_to(&self, prefix: &str) -> usize {
+        self.requests()
+            .iter()
+            .filter(|request| request.path.starts_with(prefix))
+            .count()
+    }
+
+    /// Panics unless at least one request was received and every request sent given api key
+    pub fn assert_api_key_sent(&self, api_key: &str) {
+        let requests = self.requests();
+        assert!(!requests.is_empty(), "No request received by mock Airly api");
+        for request in requests {
+            assert_eq!(
+                request.header("apikey"),
+                Some(api_key),
+                "Request to {} sent unexpected apikey header",
+                request.path
+            );
+        }
+    }
+
+    /// Panics unless request with given path, query included, was received
+    pub fn assert_requested(&self, path: &str) {
+        let requests = self.requests();
+        assert!(
+            requests.iter().any(|request| request.path == path),
+            "No request to {} received, got: {:?}",
+            path,
+            requests.iter().map(|request| &request.path).collect::<Vec<_>>()
+        );
+    }
+}
+
+impl Drop for MockAirly {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+}
+
+fn respond(request: tiny_http::Request, state: &Mutex<State>) {
+    let received = MockRequest {
+        method: request.method().as_str().to_string(),
+        path: request.url().trim_start_matches(API_ROOT).to_string(),
+        headers: request
+            .headers()
+            .iter()
+            .map(|h| (h.field.as_str().as_str().to_lowercase(), h.value.as_str().to_string()))
+            .collect(),
+    };
+    let (delay, (status, mut headers, body)) = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(received.clone());
+        handle(&mut state, &received)
+    };
+    if let Some(delay) = delay {
+        thread::sleep(delay);
+    }
+    let mut response = tiny_http::Response::from_string(body).with_status_code(status);
+    headers.push(("Content-Type".to_string(), "application/json".to_string()));
+    for (name, value) in headers {
+        if let Ok(header) = tiny_http::Header::from_bytes(name.as_bytes(), value.as_bytes()) {
+            response.add_header(header);
+        }
+    }
+    if let Err(err) = request.respond(response) {
+        warn!("Mock Airly api response failed: {}", err);
+    }
+}
+
+fn handle(state: &mut State, request: &MockRequest) -> (Option<Duration>, Reply) {
+    let api_key = request.header("apikey").unwrap_or("").to_string();
+    if let Some(keys) = &state.api_keys {
+        if !keys.contains(&api_key) {
+            return (None, failure(&Failure::Unauthorized, Vec::new()));
+        }
+    }
+    let mut delay = None;
+    match state.failures.pop_front() {
+        Some(Failure::Slow(duration)) => delay = Some(duration),
+        Some(injected) => return (None, failure(&injected, rate_limit_headers(state.quota, 0, 0))),
+        None => {}
+    }
+    let mut headers = Vec::new();
+    if let Some(quota) = state.quota {
+        let now = Instant::now();
+        let used = state.usage.entry(api_key).or_default();
+        let last_minute = used
+            .iter()
+            .filter(|at| now.duration_since(**at) < Duration::from_secs(60))
+            .count() as u32;
+        if used.len() as u32 >= quota.per_day || last_minute >= quota.per_minute {
+            let retry_after_secs = if last_minute >= quota.per_minute { 60 } else { 86_400 };
+            let remaining_minute = quota.per_minute.saturating_sub(last_minute);
+            let headers = rate_limit_headers(state.quota, remaining_minute, 0);
+            return (None, failure(&Failure::RateLimited { retry_after_secs }, headers));
+        }
+        used.push(now);
+        let remaining_day = quota.per_day - used.len() as u32;
+        headers = rate_limit_headers(state.quota, quota.per_minute - last_minute - 1, remaining_day);
+    }
+    let (status, body) = route(state, &request.path);
+    (delay, (status, headers, body))
+}
+
+fn route(state: &State, path: &str) -> (u16, String) {
+    let (resource, query) = match path.find('?') {
+        Some(at) => (&path[..at], &path[at + 1..]),
+        None => (path, ""),
+    };
+    let params: HashMap<&str, &str> = query
+        .split('&')
+        .filter_map(|pair| {
+            let at = pair.find('=')?;
+            Some((&pair[..at], &pair[at + 1..]))
+        })
+        .collect();
+    let point = || {
+        let lat = params.get("lat")?.parse().ok()?;
+        let lng = params.get("lng")?.parse().ok()?;
+        GeoPoint::new(lat, lng).ok()
+    };
+    let max_distance = params
+        .get("maxDistanceKM")
+        .and_then(|km| km.parse::<f64>().ok())
+        .unwrap_or(3.0);
+    match resource {
+        "meta/indexes" => ok(&state.indices.clone().unwrap_or_else(default_indices)),
+        "meta/measurements" => ok(&state
+            .measurement_types
+            .clone()
+            .unwrap_or_else(default_measurement_types)),
+        "installations/nearest" => match point() {
+            Some(point) => {
+                let max_results = params
+                    .get("maxResults")
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or(1);
+                let nearest: Vec<&Installation> = nearest(state, point, max_distance)
+                    .into_iter()
+                    .take(max_results)
+                    .collect();
+                ok(&nearest)
+            }
+            None => bad_request("lat and lng are required"),
+        },
+        "measurements/installation" => {
+            match params.get("installationId").and_then(|id| id.parse::<i32>().ok()) {
+                Some(id) if state.installations.iter().any(|i| i.id == id) => ok(&measurements(state, id)),
+                Some(id) => installation_not_found(id),
+                None => bad_request("installationId is required"),
+            }
+        }
+        "measurements/nearest" => match point() {
+            Some(point) => match nearest(state, point, max_distance).first() {
+                Some(installation) => ok(&measurements(state, installation.id)),
+                None => (
+                    404,
+                    r#"{"errorCode": "INSTALLATION_NOT_FOUND", "message": "No installation found within given distance"}"#
+                        .to_string(),
+                ),
+            },
+            None => bad_request("lat and lng are required"),
+        },
+        "measurements/point" => match (point(), &state.point_measurements) {
+            (Some(_), Some(measurements)) => ok(measurements),
+            (Some(point), None) => match nearest(state, point, f64::MAX).first() {
+                Some(installation) => ok(&measurements(state, installation.id)),
+                None => ok(&empty_measurements()),
+            },
+            (None, _) => bad_request("lat and lng are required"),
+        },
+        _ => match resource.strip_prefix("installations/").map(str::parse::<i32>) {
+            Some(Ok(id)) => match state.installations.iter().find(|i| i.id == id) {
+                Some(installation) => ok(installation),
+                None => installation_not_found(id),
+            },
+            _ => (
+                404,
+                format!(r#"{{"errorCode": "NOT_FOUND", "message": "No endpoint {}"}}"#, resource),
+            ),
+        },
+    }
+}
+
+fn nearest(state: &State, point: GeoPoint, max_distance_km: f64) -> Vec<&Installation> {
+    let mut found: Vec<(f64, &Installation)> = state
+        .installations
+        .iter()
+        .map(|installation| (point.distance_km(installation.location), installation))
+        .filter(|(distance, _)| *distance <= max_distance_km)
+        .collect();
+    found.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    found.into_iter().map(|(_, installation)| installation).collect()
+}
+
+fn measurements(state: &State, id: i32) -> Measurements {
+    state
+        .measurements
+        .get(&id)
+        .cloned()
+        .unwrap_or_else(empty_measurements)
+}
+
+fn empty_measurements() -> Measurements {
+    Measurements {
+        current: None,
+        history: Vec::new(),
+        forecast: Vec::new(),
+    }
+}
+
+fn default_indices() -> Vec<IndexType> {
+    DEFAULT_INDEXES
+        .iter()
+        .map(|name| IndexType {
+            name: Some(name.to_string()),
+            level: None,
+        })
+        .collect()
+}
+
+fn default_measurement_types() -> Vec<MeasurementType> {
+    DEFAULT_MEASUREMENT_TYPES
+        .iter()
+        .map(|(name, label, unit)| MeasurementType {
+            name: Some(name.to_string()),
+            label: Some(label.to_string()),
+            unit: Some(unit.to_string()),
+        })
+        .collect()
+}
+
+fn ok<T: serde::Serialize + ?Sized>(value: &T) -> (u16, String) {
+    match serde_json::to_string(value) {
+        Ok(body) => (200, body),
+        Err(err) => (500, format!(r#"{{"message": "{}"}}"#, err)),
+    }
+}
+
+fn bad_request(message: &str) -> (u16, String) {
+    (400, format!(r#"{{"errorCode": "BAD_REQUEST", "message": "{}"}}"#, message))
+}
+
+fn installation_not_found(id: i32) -> (u16, String) {
+    (
+        404,
+        format!(
+            r#"{{"errorCode": "INSTALLATION_NOT_FOUND", "message": "Installation with ID={} not found"}}"#,
+            id
+        ),
+    )
+}
+
+fn rate_limit_headers(quota: Option<Quota>, remaining_minute: u32, remaining_day: u32) -> Vec<(String, String)> {
+    let quota = quota.unwrap_or_default();
+    vec![
+        ("X-RateLimit-Limit-minute".to_string(), quota.per_minute.to_string()),
+        ("X-RateLimit-Remaining-minute".to_string(), remaining_minute.to_string()),
+        ("X-RateLimit-Limit-day".to_string(), quota.per_day.to_string()),
+        ("X-RateLimit-Remaining-day".to_string(), remaining_day.to_string()),
+    ]
+}
+
+fn failure(failure: &Failure, mut headers: Vec<(String, String)>) -> Reply {
+    let (status, body) = match failure {
+        Failure::Unauthorized => (401, r#"{"message": "Invalid authentication credentials"}"#.to_string()),
+        Failure::NotFound => (404, r#"{"errorCode": "NOT_FOUND", "message": "Not found"}"#.to_string()),
+        Failure::RateLimited { retry_after_secs } => {
+            headers.push(("Retry-After".to_string(), retry_after_secs.to_string()));
+            (429, r#"{"message": "API rate limit exceeded"}"#.to_string())
+        }
+        Failure::ServerError => (
+            500,
+            r#"{"errorCode": "INTERNAL_SERVER_ERROR", "message": "Unexpected error"}"#.to_string(),
+        ),
+        Failure::MalformedJson => (200, r#"{"current": {"fromDateTime": "#.to_string()),
+        Failure::Slow(_) => (200, String::new()),
+    };
+    if status != 429 {
+        headers.clear();
+    }
+    (status, headers, body)
+}
+
+#[cfg(test)]
+mod test_mock {
+    use super::{Failure, MockAirly};
+    use crate::collector::Quota;
+    use crate::transport::{DefaultTransport, HttpRequest, Transport};
+    use crate::stub::{INSTALLATION_JSON, MEASUREMENTS_JSON};
+    use crate::types::{GeoCircle, GeoPoint, IndexType, Installation, Measurements};
+    use std::time::{Duration, Instant};
+
+    const API_KEY: &str = "0123456789abcdef0123456789abcdef";
+
+    fn krakow() -> MockAirly {
+        let mock = MockAirly::start().unwrap();
+        let installation: Installation = serde_json::from_str(INSTALLATION_JSON).unwrap();
+        let measurements: Measurements = serde_json::from_str(MEASUREMENTS_JSON).unwrap();
+        mock.add_installation(installation);
+        mock.set_measurements(18, measurements);
+        mock
+    }
+
+    fn caqi() -> IndexType {
+        IndexType {
+            name: Some("AIRLY_CAQI".to_string()),
+            level: None,
+        }
+    }
+
+    #[test]
+    fn test_emulates_endpoints() {
+        let mock = krakow();
+        let client = mock.client(API_KEY).unwrap();
+        assert_eq!(client.clone().get_installation(18).unwrap().id, 18);
+        assert!(client.clone().get_installation(19).is_err());
+        let near = GeoCircle::new(GeoPoint::new(50.06, 19.94).unwrap(), 2).unwrap();
+        assert_eq!(client.clone().get_nearest(near, 5).unwrap().len(), 1);
+        let far = GeoCircle::new(GeoPoint::new(54.35, 18.65).unwrap(), 20).unwrap();
+        assert!(client.clone().get_nearest(far, 5).unwrap().is_empty());
+        let measurements = client.clone().get_measurements_nearest(caqi(), near).unwrap();
+        assert!(measurements.current.is_some());
+        let point = client.clone().get_measurements_point(caqi(), GeoPoint::new(50.0, 20.0).unwrap());
+        assert!(point.unwrap().current.is_some());
+        assert_eq!(client.clone().get_indices().unwrap().len(), 3);
+        assert_eq!(client.get_meta_measurements().unwrap()[1].label.as_deref(), Some("PM2.5"));
+        mock.assert_api_key_sent(API_KEY);
+        mock.assert_requested("installations/19");
+        assert_eq!(mock.requests_to("measurements/"), 2);
+    }
+
+    #[test]
+    fn test_injected_failures() {
+        let mock = krakow();
+        let client = mock.client(API_KEY).unwrap();
+        mock.fail_next(Failure::ServerError, 1);
+        mock.fail_next(Failure::MalformedJson, 1);
+        mock.fail_next(Failure::Slow(Duration::from_millis(300)), 1);
+        assert!(client.clone().get_installation_measurements_without_wind(18, caqi()).is_err());
+        assert!(client.clone().get_installation_measurements_without_wind(18, caqi()).is_err());
+        let started = Instant::now();
+        assert!(client.clone().get_installation_measurements_without_wind(18, caqi()).is_ok());
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        mock.require_api_keys(&["ffffffffffffffffffffffffffffffff"]);
+        assert!(client.get_installation(18).is_err());
+    }
+
+    #[test]
+    fn test_quota_and_rate_limit_headers() {
+        let mock = krakow();
+        mock.set_quota(Quota {
+            per_minute: 50,
+            per_day: 2,
+        });
+        let url = format!("{}/installations/18", mock.base_url());
+        let http = DefaultTransport::default();
+        let get = |key: &str| http.send(HttpRequest::get(&url).with_header("apikey", key)).unwrap();
+        let res = get(API_KEY);
+        assert_eq!(res.status, 200);
+        assert_eq!(res.header("X-RateLimit-Remaining-day"), Some("1"));
+        assert_eq!(get(API_KEY).header("X-RateLimit-Remaining-minute"), Some("48"));
+        let res = get(API_KEY);
+        assert_eq!(res.status, 429);
+        assert_eq!(res.header("X-RateLimit-Remaining-day"), Some("0"));
+        assert_eq!(res.header("Retry-After"), Some("86400"));
+        mock.fail_next(Failure::RateLimited { retry_after_secs: 30 }, 1);
+        assert_eq!(get("other").header("Retry-After"), Some("30"));
+    }
+}
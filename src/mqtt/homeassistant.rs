@@ -0,0 +1,92 @@
+use super::Topics;
+use crate::types::{self, MeasurementKind};
+use serde_json::{json, Value};
+
+/// Maps measurement kind to Home Assistant sensor device class
+///
+/// # Returns device class if Home Assistant defines one for this kind None otherwise
+///
+pub fn device_class(kind: &MeasurementKind) -> Option<&'static str> {
+    match kind {
+        MeasurementKind::Pm1 => Some("pm1"),
+        MeasurementKind::Pm25 => Some("pm25"),
+        MeasurementKind::Pm10 => Some("pm10"),
+        MeasurementKind::Temperature => Some("temperature"),
+        MeasurementKind::Humidity => Some("humidity"),
+        MeasurementKind::Pressure => Some("pressure"),
+        MeasurementKind::WindSpeed => Some("wind_speed"),
+        MeasurementKind::No2 => Some("nitrogen_dioxide"),
+        MeasurementKind::O3 => Some("ozone"),
+        MeasurementKind::So2 => Some("sulphur_dioxide"),
+        MeasurementKind::Co => Some("carbon_monoxide"),
+        MeasurementKind::WindBearing | MeasurementKind::Other(_) => None,
+    }
+}
+
+/// Builds Home Assistant device description of installation
+pub fn device(installation: &types::Installation) -> Value {
+    let address = &installation.address;
+    json!({
+        "identifiers": [format!("airly_{}", installation.id)],
+        "name": format!("Airly {} {} {}", address.city, address.street, address.number).trim(),
+        "manufacturer": if installation.airly { "Airly" } else { installation.sponsor.name.as_str() },
+        "model": if installation.airly { "Airly sensor" } else { "Partner sensor" },
+    })
+}
+
+/// Builds discovery configs of sensors for every measurement and index present in current values
+///
+/// # Arguments:
+/// * topics - topic layout
+/// * installation - installation registered as device
+/// * current - current values of the installation
+/// * units - measurement types unit of each sensor is taken from
+///
+/// # Returns pairs of config topic and config payload
+///
+pub fn discovery(
+    topics: &Topics,
+    installation: &types::Installation,
+    current: &types::AveragedValues,
+    units: &[types::MeasurementType],
+) -> Vec<(String, Value)> {
+    let device = device(installation);
+    let mut configs = Vec::new();
+    for name in current.values.iter().filter_map(|v| v.name.as_ref()) {
+        let kind = MeasurementKind::from_name(name);
+        let measurement_type = units.iter().find(|t| t.name.as_deref() == Some(name.as_str()));
+        let label = measurement_type.and_then(|t| t.label.clone()).unwrap_or_else(|| name.clone());
+        let mut config = sensor(topics, installation.id, name, &label, &device);
+        if let Some(unit) = measurement_type.and_then(|t| t.unit.clone()) {
+            config["unit_of_measurement"] = json!(unit);
+        }
+        if let Some(class) = device_class(&kind) {
+            config["device_class"] = json!(class);
+        }
+        configs.push((config_topic(topics, installation.id, name), config));
+    }
+    for name in current.indexes.iter().filter_map(|i| i.name.as_ref()) {
+        let mut config = sensor(topics, installation.id, name, name, &device);
+        config["device_class"] = json!("aqi");
+        configs.push((config_topic(topics, installation.id, name), config));
+    }
+    configs
+}
+
+fn sensor(topics: &Topics, id: i32, name: &str, label: &str, device: &Value) -> Value {
+    json!({
+        "name": label,
+        "unique_id": object_id(id, name),
+        "state_topic": topics.state(id, name),
+        "state_class": "measurement",
+        "device": device,
+    })
+}
+
+fn config_topic(topics: &Topics, id: i32, name: &str) -> String {
+    format!("{}/sensor/{}/config", topics.discovery_prefix, object_id(id, name))
+}
+
+fn object_id(id: i32, name: &str) -> String {
+    format!("airly_{}_{}", id, name.to_lowercase())
+}
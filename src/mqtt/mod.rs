@@ -0,0 +1,488 @@
+use crate::clock::{Clock, SystemClock};
+use crate::types;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub mod homeassistant;
+
+const PROTOCOL_NAME: &str = "MQTT";
+const PROTOCOL_LEVEL: u8 = 4;
+const KEEP_ALIVE_SECS: u16 = 60;
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PINGREQ: u8 = 0xC0;
+const PINGRESP: u8 = 0xD0;
+const DISCONNECT: u8 = 0xE0;
+const RETAIN: u8 = 0x01;
+const CLEAN_SESSION: u8 = 0x02;
+const PASSWORD_FLAG: u8 = 0x40;
+const USERNAME_FLAG: u8 = 0x80;
+const MAX_REMAINING_LENGTH: usize = 268_435_455;
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+type Opener<S> = Box<dyn Fn() -> std::io::Result<S> + Send>;
+
+/// Minimal MQTT 3.1.1 client, publishes with QoS 0 only
+///
+/// Broker drops clients silent for one and a half keep alive, so before publishing Connection pings it
+/// once half of keep alive passed and connects again when it was idle for longer or the ping failed
+pub struct Connection<S: Read + Write> {
+    stream: S,
+    client_id: String,
+    credentials: Option<(String, String)>,
+    open: Option<Opener<S>>,
+    clock: Arc<dyn Clock>,
+    last_sent: i64,
+}
+
+impl Connection<TcpStream> {
+    /// Connects to broker over tcp
+    ///
+    /// # Arguments:
+    /// * addr - broker address, e.g. localhost:1883
+    /// * client_id - id this client is registered with
+    /// * credentials - optional user name and password
+    ///
+    /// # Returns Connection if broker accepted it Error otherwise
+    ///
+    pub fn connect(
+        addr: &str,
+        client_id: &str,
+        credentials: Option<(&str, &str)>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let addr = addr.to_string();
+        let open = move || -> std::io::Result<TcpStream> {
+            let stream = TcpStream::connect(&addr)?;
+            stream.set_read_timeout(Some(TIMEOUT))?;
+            stream.set_write_timeout(Some(TIMEOUT))?;
+            Ok(stream)
+        };
+        let mut connection = Connection::handshake(open()?, client_id, credentials)?;
+        connection.open = Some(Box::new(open));
+        Ok(connection)
+    }
+}
+
+impl<S: Read + Write> Connection<S> {
+    /// Sends CONNECT packet over already open stream and waits for CONNACK,
+    /// such connection can not be reopened once broker dropped it
+    pub fn handshake(
+        stream: S,
+        client_id: &str,
+        credentials: Option<(&str, &str)>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let clock = Arc::new(SystemClock);
+        let mut connection = Self {
+            stream,
+            client_id: client_id.to_string(),
+            credentials: credentials.map(|(user, password)| (user.to_string(), password.to_string())),
+            open: None,
+            last_sent: clock.now(),
+            clock,
+        };
+        connection.start_session()?;
+        Ok(connection)
+    }
+
+    /// Sets time source keep alive is measured with, e.g. FakeClock in tests
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.last_sent = clock.now();
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    fn start_session(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut flags = CLEAN_SESSION;
+        let mut payload = encode_string(&self.client_id)?;
+        if let Some((user, password)) = &self.credentials {
+            flags |= USERNAME_FLAG | PASSWORD_FLAG;
+            payload.extend(encode_string(user)?);
+            payload.extend(encode_string(password)?);
+        }
+        let mut body = encode_string(PROTOCOL_NAME)?;
+        body.push(PROTOCOL_LEVEL);
+        body.push(flags);
+        body.extend(&KEEP_ALIVE_SECS.to_be_bytes());
+        body.extend(payload);
+        self.send(&packet(CONNECT, &body)?)?;
+
+        let mut connack = [0u8; 4];
+        self.stream.read_exact(&mut connack)?;
+        if connack[0] != CONNACK || connack[1] != 2 {
+            return Err(Box::new(Error::new(ErrorKind::InvalidData, "Expected CONNACK from broker")));
+        }
+        if connack[3] != 0 {
+            return Err(Box::new(Error::new(
+                ErrorKind::PermissionDenied,
+                format!("Broker refused connection with code {}", connack[3]),
+            )));
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, packet: &[u8]) -> std::io::Result<()> {
+        self.stream.write_all(packet)?;
+        self.last_sent = self.clock.now();
+        Ok(())
+    }
+
+    /// Sends PINGREQ packet and waits for PINGRESP
+    pub fn ping(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.send(&[PINGREQ, 0])?;
+        let mut pingresp = [0u8; 2];
+        self.stream.read_exact(&mut pingresp)?;
+        if pingresp != [PINGRESP, 0] {
+            return Err(Box::new(Error::new(ErrorKind::InvalidData, "Expected PINGRESP from broker")));
+        }
+        Ok(())
+    }
+
+    /// Opens new stream and session with the broker
+    ///
+    /// # Returns Error if connection was not opened with Connection::connect or broker refused it
+    ///
+    pub fn reconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.stream = match &self.open {
+            Some(open) => open()?,
+            None => {
+                return Err(Box::new(Error::new(
+                    ErrorKind::NotConnected,
+                    "Connection over given stream can not be reopened",
+                )))
+            }
+        };
+        self.start_session()
+    }
+
+    /// Pings broker when connection was idle for half of keep alive,
+    /// connects again when ping failed or broker already dropped idle connection
+    pub fn keep_alive(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let idle = self.clock.now() - self.last_sent;
+        let keep_alive = i64::from(KEEP_ALIVE_SECS);
+        if idle >= keep_alive * 3 / 2 {
+            return self.reconnect();
+        }
+        if idle >= keep_alive / 2 && self.ping().is_err() {
+            return self.reconnect();
+        }
+        Ok(())
+    }
+
+    /// Publishes message with QoS 0, keeps connection alive before and connects again if sending failed
+    ///
+    /// # Arguments:
+    /// * topic - topic name
+    /// * payload - message content
+    /// * retain - whether broker should keep message for future subscribers
+    ///
+    pub fn publish(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        retain: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut body = encode_string(topic)?;
+        body.extend(payload);
+        let header = if retain { PUBLISH | RETAIN } else { PUBLISH };
+        let packet = packet(header, &body)?;
+        self.keep_alive()?;
+        if let Err(err) = self.send(&packet) {
+            if self.open.is_none() {
+                return Err(Box::new(err));
+            }
+            self.reconnect()?;
+            self.send(&packet)?;
+        }
+        Ok(())
+    }
+
+    /// Sends DISCONNECT packet
+    pub fn disconnect(mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.stream.write_all(&[DISCONNECT, 0])?;
+        self.stream.flush()?;
+        Ok(())
+    }
+}
+
+fn encode_string(text: &str) -> Result<Vec<u8>, Error> {
+    if text.len() > usize::from(u16::MAX) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("String of {} bytes is too long for MQTT, limit is {}", text.len(), u16::MAX),
+        ));
+    }
+    let mut encoded = (text.len() as u16).to_be_bytes().to_vec();
+    encoded.extend(text.as_bytes());
+    Ok(encoded)
+}
+
+fn packet(header: u8, body: &[u8]) -> Result<Vec<u8>, Error> {
+    if body.len() > MAX_REMAINING_LENGTH {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Packet of {} bytes is too long for MQTT, limit is {}", body.len(), MAX_REMAINING_LENGTH),
+        ));
+    }
+    let mut packet = vec![header];
+    let mut remaining = body.len();
+    loop {
+        let mut byte = (remaining % 128) as u8;
+        remaining /= 128;
+        if remaining > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if remaining == 0 {
+            break;
+        }
+    }
+    packet.extend(body);
+    Ok(packet)
+}
+
+/// Topic layout, state of measurement is published to `<base>/<installation id>/<measurement>`
+#[derive(Debug, Clone)]
+pub struct Topics {
+    /// Home Assistant discovery prefix
+    pub discovery_prefix: String,
+    /// Prefix of state topics
+    pub base: String,
+}
+
+impl Default for Topics {
+    fn default() -> Self {
+        Self {
+            discovery_prefix: "homeassistant".to_string(),
+            base: "airly".to_string(),
+        }
+    }
+}
+
+impl Topics {
+    /// # Returns topic state of given measurement or index is published to
+    pub fn state(&self, id: i32, name: &str) -> String {
+        format!("{}/{}/{}", self.base, id, name.to_lowercase())
+    }
+}
+
+/// Publishes current measurements and indexes of installations as Home Assistant sensors
+pub struct Publisher<S: Read + Write> {
+    connection: Connection<S>,
+    topics: Topics,
+    units: Vec<types::MeasurementType>,
+    retain: bool,
+}
+
+impl<S: Read + Write> Publisher<S> {
+    /// Constructs Publisher
+    ///
+    /// # Arguments:
+    /// * connection - connection to the broker
+    /// * topics - topic layout
+    /// * units - measurement types as returned by AirlyClient::get_meta_measurements, source of sensor units
+    ///
+    pub fn new(connection: Connection<S>, topics: Topics, units: Vec<types::MeasurementType>) -> Self {
+        Self {
+            connection,
+            topics,
+            units,
+            retain: true,
+        }
+    }
+
+    /// Sets whether states are retained by broker, true by default
+    pub fn with_retain(mut self, retain: bool) -> Self {
+        self.retain = retain;
+        self
+    }
+
+    /// Publishes discovery configs for every measurement and index present in current values
+    ///
+    /// # Arguments:
+    /// * installation - installation registered as Home Assistant device
+    /// * current - current values of the installation
+    ///
+    pub fn announce(
+        &mut self,
+        installation: &types::Installation,
+        current: &types::AveragedValues,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        for (topic, config) in homeassistant::discovery(&self.topics, installation, current, &self.units) {
+            self.connection.publish(&topic, config.to_string().as_bytes(), true)?;
+        }
+        Ok(())
+    }
+
+    /// Publishes state of every measurement and index of current values
+    ///
+    /// # Arguments:
+    /// * id - installation id
+    /// * current - current values of the installation
+    ///
+    pub fn publish(
+        &mut self,
+        id: i32,
+        current: &types::AveragedValues,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let values = current.values.iter().map(|v| (&v.name, v.value));
+        let indexes = current.indexes.iter().map(|i| (&i.name, i.value));
+        for (name, value) in values.chain(indexes) {
+            if let (Some(name), Some(value)) = (name, value) {
+                let topic = self.topics.state(id, name);
+                self.connection.publish(&topic, value.to_string().as_bytes(), self.retain)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Disconnects from broker
+    pub fn close(self) -> Result<(), Box<dyn std::error::Error>> {
+        self.connection.disconnect()
+    }
+}
+
+#[cfg(test)]
+mod test_mqtt {
+    use super::{encode_string, Connection, Publisher, Topics};
+    use crate::clock::FakeClock;
+    use crate::stub::{INSTALLATION_JSON, MEASUREMENTS_JSON};
+    use crate::types::{Installation, MeasurementType, Measurements};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    type Published = Vec<(String, String, bool)>;
+
+    /// What broker received over one client connection
+    struct Session {
+        published: Published,
+        pings: usize,
+    }
+
+    /// Accepts given number of client connections one after another, answers CONNECT and PINGREQ
+    /// and collects published topics, payloads and retain flags
+    fn broker(return_code: u8, connections: usize) -> (String, thread::JoinHandle<Vec<Session>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let mut sessions = Vec::new();
+            for _ in 0..connections {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut session = Session {
+                    published: Vec::new(),
+                    pings: 0,
+                };
+                loop {
+                    let mut header = [0u8; 1];
+                    if stream.read_exact(&mut header).is_err() {
+                        break;
+                    }
+                    let (mut length, mut multiplier) = (0usize, 1usize);
+                    loop {
+                        let mut byte = [0u8; 1];
+                        stream.read_exact(&mut byte).unwrap();
+                        length += (byte[0] & 0x7F) as usize * multiplier;
+                        multiplier *= 128;
+                        if byte[0] & 0x80 == 0 {
+                            break;
+                        }
+                    }
+                    let mut body = vec![0u8; length];
+                    stream.read_exact(&mut body).unwrap();
+                    match header[0] & 0xF0 {
+                        0x10 => {
+                            assert_eq!(&body[2..6], b"MQTT");
+                            stream.write_all(&[0x20, 0x02, 0x00, return_code]).unwrap();
+                        }
+                        0x30 => {
+                            let topic_len = (body[0] as usize) << 8 | body[1] as usize;
+                            let topic = String::from_utf8(body[2..2 + topic_len].to_vec()).unwrap();
+                            let payload = String::from_utf8(body[2 + topic_len..].to_vec()).unwrap();
+                            session.published.push((topic, payload, header[0] & 0x01 == 1));
+                        }
+                        0xC0 => {
+                            session.pings += 1;
+                            stream.write_all(&[0xD0, 0x00]).unwrap();
+                        }
+                        _ => break,
+                    }
+                }
+                sessions.push(session);
+            }
+            sessions
+        });
+        (addr, handle)
+    }
+
+    #[test]
+    fn test_publish_discovery_and_states() {
+        let (addr, broker) = broker(0, 1);
+        let installation: Installation = serde_json::from_str(INSTALLATION_JSON).unwrap();
+        let measurements: Measurements = serde_json::from_str(MEASUREMENTS_JSON).unwrap();
+        let current = measurements.current.unwrap();
+        let units = vec![MeasurementType {
+            name: Some("PM25".to_string()),
+            label: Some("PM2.5".to_string()),
+            unit: Some("µg/m³".to_string()),
+        }];
+        let connection = Connection::connect(&addr, "airly_rs_test", Some(("user", "pass"))).unwrap();
+        let mut publisher = Publisher::new(connection, Topics::default(), units).with_retain(false);
+        publisher.announce(&installation, &current).unwrap();
+        publisher.publish(installation.id, &current).unwrap();
+        publisher.close().unwrap();
+
+        let published = broker.join().unwrap().remove(0).published;
+        let (_, config, retained) = published
+            .iter()
+            .find(|(topic, _, _)| topic == "homeassistant/sensor/airly_18_pm25/config")
+            .unwrap();
+        assert!(retained);
+        let config: serde_json::Value = serde_json::from_str(config).unwrap();
+        assert_eq!(config["state_topic"], "airly/18/pm25");
+        assert_eq!(config["unit_of_measurement"], "µg/m³");
+        assert_eq!(config["device_class"], "pm25");
+        assert_eq!(config["device"]["identifiers"][0], "airly_18");
+        assert!(published.contains(&("airly/18/pm25".to_string(), "19.58".to_string(), false)));
+        assert!(published.contains(&("airly/18/airly_caqi".to_string(), "37.98".to_string(), false)));
+    }
+
+    #[test]
+    fn test_refused_connection() {
+        let (addr, broker) = broker(5, 1);
+        assert!(Connection::connect(&addr, "airly_rs_test", None).is_err());
+        broker.join().unwrap();
+    }
+
+    #[test]
+    fn test_keep_alive_pings_and_reconnects() {
+        let (addr, broker) = broker(0, 2);
+        let clock = FakeClock::new(0);
+        let mut connection = Connection::connect(&addr, "airly_rs_test", None)
+            .unwrap()
+            .with_clock(clock.clone());
+        connection.publish("airly/18/pm25", b"19.58", false).unwrap();
+        clock.advance(40);
+        connection.publish("airly/18/pm25", b"20.1", false).unwrap();
+        clock.advance(3600);
+        connection.publish("airly/18/pm25", b"21.3", false).unwrap();
+        connection.disconnect().unwrap();
+
+        let sessions = broker.join().unwrap();
+        assert_eq!(sessions[0].pings, 1);
+        assert_eq!(sessions[0].published.len(), 2);
+        assert_eq!(sessions[1].pings, 0);
+        assert_eq!(
+            sessions[1].published,
+            vec![("airly/18/pm25".to_string(), "21.3".to_string(), false)]
+        );
+    }
+
+    #[test]
+    fn test_too_long_string() {
+        assert_eq!(encode_string("airly").unwrap(), b"\x00\x05airly".to_vec());
+        assert!(encode_string(&"a".repeat(70_000)).is_err());
+    }
+}
@@ -0,0 +1,106 @@
+use super::TrackPoint;
+use crate::types::GeoPoint;
+use chrono::DateTime;
+use std::io::{Error, ErrorKind};
+
+/// Reads track points, or route points if there is no track, of GPX document
+///
+/// # Arguments:
+/// * xml - GPX 1.0 or 1.1 document
+///
+/// # Returns points in document order with their times if recorded, Error if document has no points or
+/// holds invalid coordinates
+///
+pub fn parse(xml: &str) -> Result<Vec<TrackPoint>, Box<dyn std::error::Error>> {
+    let mut points = parse_elements(xml, "trkpt")?;
+    if points.is_empty() {
+        points = parse_elements(xml, "rtept")?;
+    }
+    if points.is_empty() {
+        return Err(Box::new(Error::new(ErrorKind::InvalidData, "GPX has no track or route points")));
+    }
+    Ok(points)
+}
+
+fn parse_elements(xml: &str, name: &str) -> Result<Vec<TrackPoint>, Box<dyn std::error::Error>> {
+    let open = format!("<{}", name);
+    let close = format!("</{}>", name);
+    let mut points = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        let tag_end = rest
+            .find('>')
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("Unclosed <{}> tag", name)))?;
+        let tag = &rest[..tag_end];
+        let body = if tag.ends_with('/') {
+            ""
+        } else {
+            let body_end = rest.find(&close).unwrap_or(tag_end);
+            &rest[tag_end..body_end]
+        };
+        let lat = coordinate(tag, "lat")?;
+        let lon = coordinate(tag, "lon")?;
+        points.push(TrackPoint {
+            point: GeoPoint::new(lat, lon)?,
+            time: element_text(body, "time")
+                .and_then(|time| DateTime::parse_from_rfc3339(time.trim()).ok())
+                .map(|time| time.timestamp()),
+        });
+        rest = &rest[tag_end..];
+    }
+    Ok(points)
+}
+
+fn coordinate(tag: &str, name: &str) -> Result<f32, Box<dyn std::error::Error>> {
+    let tag = tag.replace(char::is_whitespace, " ");
+    for quote in ['"', '\''].iter() {
+        let prefix = format!(" {}={}", name, quote);
+        if let Some(start) = tag.find(&prefix).map(|at| at + prefix.len()) {
+            if let Some(len) = tag[start..].find(*quote) {
+                return Ok(tag[start..start + len].trim().parse()?);
+            }
+        }
+    }
+    Err(Box::new(Error::new(
+        ErrorKind::InvalidData,
+        format!("GPX point without {} attribute: <{}>", name, tag.trim()),
+    )))
+}
+
+fn element_text<'a>(body: &'a str, name: &str) -> Option<&'a str> {
+    let start = body.find(&format!("<{}>", name))? + name.len() + 2;
+    let len = body[start..].find(&format!("</{}>", name))?;
+    Some(&body[start..start + len])
+}
+
+#[cfg(test)]
+mod test_gpx {
+    use super::parse;
+
+    const TRACK: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1">
+  <trk><name>Commute</name><trkseg>
+    <trkpt lat="50.0614" lon="19.9366"><ele>219</ele><time>2019-12-10T07:30:00Z</time></trkpt>
+    <trkpt lon='19.9500' lat='50.0650'><time>2019-12-10T07:35:00Z</time></trkpt>
+    <trkpt lat="50.0700" lon="19.9600"/>
+  </trkseg></trk>
+</gpx>"#;
+
+    #[test]
+    fn test_parse_track_and_route() {
+        let points = parse(TRACK).unwrap();
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0].point.get_lat(), 50.0614);
+        assert_eq!(points[1].point.get_lng(), 19.95);
+        assert_eq!(points[0].time, Some(1_575_963_000));
+        assert_eq!(points[1].time, Some(1_575_963_300));
+        assert_eq!(points[2].time, None);
+
+        let route = r#"<gpx><rte><rtept lat="50.06" lon="19.93"/><rtept lat="50.07" lon="19.94"/></rte></gpx>"#;
+        assert_eq!(parse(route).unwrap().len(), 2);
+        assert!(parse("<gpx></gpx>").is_err());
+        assert!(parse(r#"<gpx><trk><trkseg><trkpt lat="50.06"/></trkseg></trk></gpx>"#).is_err());
+        assert!(parse(r#"<gpx><trk><trkseg><trkpt lat="95" lon="19.9"/></trkseg></trk></gpx>"#).is_err());
+    }
+}
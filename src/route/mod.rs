@@ -0,0 +1,524 @@
+use crate::client::AirlyClient;
+use crate::transport::Transport;
+use crate::types::{AveragedValues, GeoCircle, GeoPoint, IndexType, MeasurementKind, Measurements};
+use std::io::{Error, ErrorKind};
+
+pub mod gpx;
+pub mod polyline;
+
+const SECONDS_PER_HOUR: f64 = 3600.0;
+const DEFAULT_SPACING_KM: f64 = 0.5;
+const DEFAULT_REUSE_KM: f64 = 1.0;
+/// Average speed of city cycling, used when route has no times
+const DEFAULT_SPEED_KMH: f64 = 15.0;
+
+/// Point of route with time it was passed, if recorded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackPoint {
+    pub point: GeoPoint,
+    /// Unix timestamp in seconds
+    pub time: Option<i64>,
+}
+
+/// Point sampled along route
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RouteSample {
+    pub point: GeoPoint,
+    /// Distance from start of route along it
+    pub distance_km: f64,
+    /// Unix timestamp in seconds, interpolated between track points with times
+    pub time: Option<i64>,
+}
+
+/// Route read from GPX track or polyline
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    points: Vec<TrackPoint>,
+}
+
+fn interpolate(from: GeoPoint, till: GeoPoint, share: f64) -> Result<GeoPoint, Box<dyn std::error::Error>> {
+    let along = |a: f32, b: f32| (f64::from(a) + (f64::from(b) - f64::from(a)) * share) as f32;
+    GeoPoint::new(along(from.get_lat(), till.get_lat()), along(from.get_lng(), till.get_lng()))
+}
+
+impl Route {
+    /// Constructs route from points
+    ///
+    /// # Returns Route or Error if there are no points
+    ///
+    pub fn new(points: Vec<TrackPoint>) -> Result<Self, Box<dyn std::error::Error>> {
+        if points.is_empty() {
+            return Err(Box::new(Error::new(ErrorKind::InvalidInput, "Route needs at least one point")));
+        }
+        Ok(Self { points })
+    }
+
+    /// Reads route from GPX track, see gpx::parse
+    pub fn from_gpx(xml: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Route::new(gpx::parse(xml)?)
+    }
+
+    /// Reads route from encoded polyline, see polyline::decode
+    pub fn from_polyline(encoded: &str, precision: u32) -> Result<Self, Box<dyn std::error::Error>> {
+        let points = polyline::decode(encoded, precision)?
+            .into_iter()
+            .map(|point| TrackPoint { point, time: None })
+            .collect();
+        Route::new(points)
+    }
+
+    pub fn points(&self) -> &[TrackPoint] {
+        &self.points
+    }
+
+    /// # Returns length of route in km
+    pub fn length_km(&self) -> f64 {
+        self.points
+            .windows(2)
+            .map(|pair| pair[0].point.distance_km(pair[1].point))
+            .sum()
+    }
+
+    /// Samples points along route
+    ///
+    /// # Arguments:
+    /// * spacing_km - distance between samples along route
+    ///
+    /// # Returns samples every spacing_km from start, start and end of route included
+    ///
+    pub fn sample(&self, spacing_km: f64) -> Result<Vec<RouteSample>, Box<dyn std::error::Error>> {
+        let spacing_km = spacing_km.max(0.01);
+        let first = self.points[0];
+        let mut samples = vec![RouteSample {
+            point: first.point,
+            distance_km: 0.0,
+            time: first.time,
+        }];
+        let mut start_km = 0.0;
+        let mut next_km = spacing_km;
+        for pair in self.points.windows(2) {
+            let length = pair[0].point.distance_km(pair[1].point);
+            while next_km < start_km + length {
+                let share = (next_km - start_km) / length;
+                samples.push(RouteSample {
+                    point: interpolate(pair[0].point, pair[1].point, share)?,
+                    distance_km: next_km,
+                    time: match (pair[0].time, pair[1].time) {
+                        (Some(from), Some(till)) => Some(from + ((till - from) as f64 * share).round() as i64),
+                        _ => None,
+                    },
+                });
+                next_km += spacing_km;
+            }
+            start_km += length;
+        }
+        let last = self.points[self.points.len() - 1];
+        if start_km > samples[samples.len() - 1].distance_km {
+            samples.push(RouteSample {
+                point: last.point,
+                distance_km: start_km,
+                time: last.time,
+            });
+        }
+        Ok(samples)
+    }
+}
+
+/// How concentrations along route are looked up in api
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lookup {
+    /// Measurements interpolated for point, get_measurements_point
+    Point,
+    /// Measurements of the nearest installation within radius, get_measurements_nearest
+    Nearest { radius_km: u32 },
+}
+
+/// Settings of exposure estimate
+#[derive(Debug, Clone)]
+pub struct ExposureOptions {
+    pollutant: MeasurementKind,
+    spacing_km: f64,
+    reuse_km: f64,
+    speed_kmh: f64,
+    lookup: Lookup,
+    index_type: String,
+}
+
+impl Default for ExposureOptions {
+    fn default() -> Self {
+        Self {
+            pollutant: MeasurementKind::Pm25,
+            spacing_km: DEFAULT_SPACING_KM,
+            reuse_km: DEFAULT_REUSE_KM,
+            speed_kmh: DEFAULT_SPEED_KMH,
+            lookup: Lookup::Point,
+            index_type: "AIRLY_CAQI".to_string(),
+        }
+    }
+}
+
+impl ExposureOptions {
+    /// Sets pollutant exposure is estimated for, PM25 by default
+    pub fn with_pollutant(mut self, pollutant: MeasurementKind) -> Self {
+        self.pollutant = pollutant;
+        self
+    }
+
+    /// Sets distance between samples along route, 0.5 km by default
+    pub fn with_spacing_km(mut self, spacing_km: f64) -> Self {
+        self.spacing_km = spacing_km;
+        self
+    }
+
+    /// Sets distance within which already looked up sample is reused instead of asking api again, 1 km by default
+    pub fn with_reuse_km(mut self, reuse_km: f64) -> Self {
+        self.reuse_km = reuse_km;
+        self
+    }
+
+    /// Sets speed used for routes without times, 15 km/h by default
+    pub fn with_speed_kmh(mut self, speed_kmh: f64) -> Self {
+        self.speed_kmh = speed_kmh;
+        self
+    }
+
+    /// Sets how concentrations are looked up in api, interpolated for point by default
+    pub fn with_lookup(mut self, lookup: Lookup) -> Self {
+        self.lookup = lookup;
+        self
+    }
+}
+
+/// Concentration of pollutant at looked up point
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Concentration {
+    /// Latest value, used for samples without time or with time out of periods
+    pub current: Option<f64>,
+    /// Values of averaging periods with their bounds, unix timestamps in seconds
+    pub periods: Vec<(i64, i64, f64)>,
+}
+
+impl From<f64> for Concentration {
+    fn from(value: f64) -> Self {
+        Self {
+            current: Some(value),
+            periods: Vec::new(),
+        }
+    }
+}
+
+impl Concentration {
+    /// Reads pollutant from current values and history of measurements
+    ///
+    /// # Arguments:
+    /// * measurements - measurements of looked up point
+    /// * pollutant - kind of measurement
+    ///
+    pub fn from_measurements(measurements: &Measurements, pollutant: &MeasurementKind) -> Self {
+        let period = |values: &AveragedValues| Some((values.from_timestamp()?, values.till_timestamp()?, values.value(pollutant)?));
+        Self {
+            current: measurements.current.as_ref().and_then(|current| current.value(pollutant)),
+            periods: measurements.history.iter().chain(measurements.current.iter()).filter_map(period).collect(),
+        }
+    }
+
+    /// # Returns value of period covering time, current value if there is none or time is unknown
+    pub fn at(&self, time: Option<i64>) -> Option<f64> {
+        time.and_then(|time| {
+            self.periods
+                .iter()
+                .find(|(from, till, _)| *from <= time && time < *till)
+                .map(|(_, _, value)| *value)
+        })
+        .or(self.current)
+    }
+}
+
+/// Part of route between two samples
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    pub from: RouteSample,
+    pub till: RouteSample,
+    /// Mean concentration of both ends in µg/m³
+    pub concentration: f64,
+    /// Time spent on segment
+    pub hours: f64,
+}
+
+impl Segment {
+    /// # Returns exposure on segment in µg/m³·h
+    pub fn exposure(&self) -> f64 {
+        self.concentration * self.hours
+    }
+}
+
+/// Estimated exposure along route
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exposure {
+    pub pollutant: MeasurementKind,
+    /// Cumulative exposure in µg/m³·h
+    pub total: f64,
+    /// Time spent on segments with known concentration
+    pub hours: f64,
+    /// Segments with known concentration in order along route
+    pub segments: Vec<Segment>,
+    /// Length of route without concentration at either end of segment
+    pub uncovered_km: f64,
+    /// Number of lookups made, the rest of samples reused nearby ones
+    pub lookups: usize,
+    /// Number of lookups that failed, their samples count as unknown
+    pub failed_lookups: usize,
+    pub samples: usize,
+}
+
+impl Exposure {
+    /// # Returns concentration averaged over time, None without covered segments
+    pub fn mean_concentration(&self) -> Option<f64> {
+        if self.hours <= 0.0 {
+            return None;
+        }
+        Some(self.total / self.hours)
+    }
+
+    /// # Returns up to count segments with the highest concentration, the worst first
+    pub fn worst(&self, count: usize) -> Vec<&Segment> {
+        let mut segments: Vec<&Segment> = self.segments.iter().collect();
+        segments.sort_by(|a, b| b.concentration.partial_cmp(&a.concentration).unwrap_or(std::cmp::Ordering::Equal));
+        segments.truncate(count);
+        segments
+    }
+}
+
+/// Estimates exposure along route with concentrations from given lookup
+///
+/// # Arguments:
+/// * route - route travelled
+/// * options - sampling, reuse distance and speed
+/// * lookup - concentration at point, called once per sample not within reuse distance of sample looked up
+///   before, samples with time take value of period they were passed in
+///
+/// # Returns Exposure, failed lookups are logged and their samples count as unknown, Error if route cannot be
+/// sampled
+///
+pub fn exposure<F>(route: &Route, options: &ExposureOptions, mut lookup: F) -> Result<Exposure, Box<dyn std::error::Error>>
+where
+    F: FnMut(GeoPoint) -> Result<Concentration, Box<dyn std::error::Error>>,
+{
+    let samples = route.sample(options.spacing_km)?;
+    let mut looked_up: Vec<(GeoPoint, Concentration)> = Vec::new();
+    let mut failed_lookups = 0;
+    let mut concentrations = Vec::with_capacity(samples.len());
+    for sample in samples.iter() {
+        let nearby = looked_up
+            .iter()
+            .map(|(point, concentration)| (point.distance_km(sample.point), concentration))
+            .filter(|(distance, _)| *distance <= options.reuse_km)
+            .fold(None, |nearest: Option<(f64, &Concentration)>, candidate| match nearest {
+                Some(nearest) if nearest.0 <= candidate.0 => Some(nearest),
+                _ => Some(candidate),
+            })
+            .map(|(_, concentration)| concentration.at(sample.time));
+        let value = match nearby {
+            Some(value) => value,
+            None => {
+                let concentration = lookup(sample.point).unwrap_or_else(|err| {
+                    warn!("Concentration lookup at {:?} failed: {}", sample.point, err);
+                    failed_lookups += 1;
+                    Concentration::default()
+                });
+                let value = concentration.at(sample.time);
+                looked_up.push((sample.point, concentration));
+                value
+            }
+        };
+        concentrations.push(value);
+    }
+    let mut segments = Vec::new();
+    let mut uncovered_km = 0.0;
+    for (pair, values) in samples.windows(2).zip(concentrations.windows(2)) {
+        let length = pair[1].distance_km - pair[0].distance_km;
+        let concentration = match (values[0], values[1]) {
+            (Some(a), Some(b)) => (a + b) / 2.0,
+            (Some(value), None) | (None, Some(value)) => value,
+            (None, None) => {
+                uncovered_km += length;
+                continue;
+            }
+        };
+        let hours = match (pair[0].time, pair[1].time) {
+            (Some(from), Some(till)) if till > from => (till - from) as f64 / SECONDS_PER_HOUR,
+            _ => length / options.speed_kmh,
+        };
+        segments.push(Segment {
+            from: pair[0],
+            till: pair[1],
+            concentration,
+            hours,
+        });
+    }
+    Ok(Exposure {
+        pollutant: options.pollutant.clone(),
+        total: segments.iter().map(Segment::exposure).sum(),
+        hours: segments.iter().map(|segment| segment.hours).sum(),
+        segments,
+        uncovered_km,
+        lookups: looked_up.len(),
+        failed_lookups,
+        samples: samples.len(),
+    })
+}
+
+/// Estimates exposure along route with measurements fetched from api, see exposure
+///
+/// Samples passed within the last 24 hours take values of the hour they were passed in from history, other
+/// samples and routes without times take current values. Samples without installation in radius of
+/// Lookup::Nearest, or whose request fails, count as unknown.
+///
+/// # Returns Exposure, Error if route cannot be sampled
+///
+pub fn exposure_from_api<T: Transport>(
+    client: &AirlyClient<T>,
+    route: &Route,
+    options: &ExposureOptions,
+) -> Result<Exposure, Box<dyn std::error::Error>> {
+    exposure(route, options, |point| {
+        let index_type = IndexType {
+            name: Some(options.index_type.clone()),
+            level: None,
+        };
+        let measurements: Measurements = match options.lookup {
+            Lookup::Point => client.clone().get_measurements_point(index_type, point)?,
+            Lookup::Nearest { radius_km } => client
+                .clone()
+                .get_measurements_nearest(index_type, GeoCircle::new(point, radius_km)?)?,
+        };
+        Ok(Concentration::from_measurements(&measurements, &options.pollutant))
+    })
+}
+
+#[cfg(test)]
+mod test_route {
+    use super::{exposure, exposure_from_api, polyline, Concentration, ExposureOptions, Lookup, Route, TrackPoint};
+    use crate::mock::MockAirly;
+    use crate::stub::MEASUREMENTS_JSON;
+    use crate::types::{GeoPoint, MeasurementKind, Measurements};
+    use std::cell::Cell;
+
+    /// Just under 5 km due east through Kraków
+    fn commute() -> Route {
+        let points = [GeoPoint::new(50.06, 19.90).unwrap(), GeoPoint::new(50.06, 19.97).unwrap()];
+        Route::from_polyline(&polyline::encode(&points, 5), 5).unwrap()
+    }
+
+    #[test]
+    fn test_sampling() {
+        let route = commute();
+        assert!((route.length_km() - 5.0).abs() < 0.05);
+        let samples = route.sample(0.5).unwrap();
+        assert_eq!(samples.len(), 11);
+        assert_eq!(samples[1].distance_km, 0.5);
+        assert!(samples[1].point.get_lng() > 19.906 && samples[1].point.get_lng() < 19.908);
+        assert_eq!(samples[10].point, route.points()[1].point);
+
+        let timed = Route::new(vec![
+            TrackPoint {
+                point: GeoPoint::new(50.06, 19.90).unwrap(),
+                time: Some(0),
+            },
+            TrackPoint {
+                point: GeoPoint::new(50.06, 19.97).unwrap(),
+                time: Some(1200),
+            },
+        ])
+        .unwrap();
+        let samples = timed.sample(2.5).unwrap();
+        assert_eq!(samples.len(), 3);
+        assert!((samples[1].time.unwrap() - 600).abs() <= 10);
+        assert!(Route::new(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_exposure_reuses_nearby_samples() {
+        let calls = Cell::new(0);
+        // clean west, smoggy east
+        let lookup = |point: GeoPoint| {
+            calls.set(calls.get() + 1);
+            Ok(Concentration::from(if point.get_lng() < 19.95 { 10.0 } else { 50.0 }))
+        };
+        let options = ExposureOptions::default().with_reuse_km(0.75);
+        let estimate = exposure(&commute(), &options, lookup).unwrap();
+        assert_eq!(estimate.samples, 11);
+        assert_eq!(estimate.lookups, 6);
+        assert_eq!(calls.get(), 6);
+        assert!((estimate.hours - commute().length_km() / 15.0).abs() < 1e-9);
+        let mean = estimate.mean_concentration().unwrap();
+        assert!(mean > 10.0 && mean < 50.0);
+        let worst = estimate.worst(2);
+        assert_eq!(worst.len(), 2);
+        assert_eq!(worst[0].concentration, 50.0);
+        assert!(worst[0].from.point.get_lng() >= 19.95);
+        assert_eq!(estimate.uncovered_km, 0.0);
+
+        let unknown = exposure(&commute(), &options, |_| Ok(Concentration::default())).unwrap();
+        assert!(unknown.segments.is_empty());
+        assert!((unknown.uncovered_km - commute().length_km()).abs() < 1e-9);
+        // api failing for the west half
+        let partial = exposure(&commute(), &options, |point| match point.get_lng() < 19.935 {
+            true => Err("api down".into()),
+            false => Ok(Concentration::from(50.0)),
+        })
+        .unwrap();
+        assert_eq!(partial.failed_lookups, 3);
+        assert!(partial.uncovered_km > 2.0 && partial.uncovered_km < 3.0);
+        assert!(partial.segments.iter().all(|segment| segment.concentration == 50.0));
+    }
+
+    #[test]
+    fn test_exposure_from_api() {
+        let mock = MockAirly::start().unwrap();
+        let measurements: Measurements = serde_json::from_str(MEASUREMENTS_JSON).unwrap();
+        mock.set_point_measurements(measurements);
+        let client = mock.client("0123456789abcdef0123456789abcdef").unwrap();
+        let options = ExposureOptions::default().with_spacing_km(1.0).with_reuse_km(1.5);
+        let estimate = exposure_from_api(&client, &commute(), &options).unwrap();
+        assert_eq!(mock.requests_to("measurements/point"), estimate.lookups);
+        assert!(estimate.lookups < estimate.samples);
+        assert!((estimate.mean_concentration().unwrap() - 19.58).abs() < 1e-9);
+        let nearest = options.with_lookup(Lookup::Nearest { radius_km: 2 });
+        let nowhere = exposure_from_api(&client, &commute(), &nearest).unwrap();
+        assert_eq!(nowhere.failed_lookups, nowhere.lookups);
+        assert!((nowhere.uncovered_km - commute().length_km()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_timed_route_takes_history_of_hour_passed() {
+        let measurements: Measurements = serde_json::from_str(MEASUREMENTS_JSON).unwrap();
+        let concentration = Concentration::from_measurements(&measurements, &MeasurementKind::Pm25);
+        // 2019-12-09T09:30:00Z is in history, 10:30 in current period, other hours take current value
+        assert_eq!(concentration.at(Some(1_575_883_800)), Some(21.3));
+        assert_eq!(concentration.at(Some(1_575_887_400)), Some(19.58));
+        assert_eq!(concentration.at(Some(1_575_800_000)), Some(19.58));
+        assert_eq!(concentration.at(None), Some(19.58));
+
+        let mock = MockAirly::start().unwrap();
+        mock.set_point_measurements(measurements);
+        let client = mock.client("0123456789abcdef0123456789abcdef").unwrap();
+        let ride = |from: i64| {
+            Route::new(vec![
+                TrackPoint {
+                    point: GeoPoint::new(50.06, 19.90).unwrap(),
+                    time: Some(from),
+                },
+                TrackPoint {
+                    point: GeoPoint::new(50.06, 19.97).unwrap(),
+                    time: Some(from + 1200),
+                },
+            ])
+            .unwrap()
+        };
+        let morning = exposure_from_api(&client, &ride(1_575_882_000), &ExposureOptions::default()).unwrap();
+        assert!((morning.hours - 1.0 / 3.0).abs() < 1e-9);
+        assert!((morning.mean_concentration().unwrap() - 21.3).abs() < 1e-9);
+        assert!((morning.total - 21.3 / 3.0).abs() < 1e-9);
+    }
+}
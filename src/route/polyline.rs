@@ -0,0 +1,109 @@
+use crate::types::GeoPoint;
+use std::io::{Error, ErrorKind};
+
+/// Precision of Google encoded polylines, OSRM and Valhalla use 6
+pub const DEFAULT_PRECISION: u32 = 5;
+
+/// Decodes encoded polyline
+///
+/// # Arguments:
+/// * encoded - polyline in Google encoded polyline format
+/// * precision - number of decimal places coordinates were encoded with, usually 5
+///
+/// # Returns points of polyline or Error if it is truncated or holds invalid coordinates
+///
+pub fn decode(encoded: &str, precision: u32) -> Result<Vec<GeoPoint>, Box<dyn std::error::Error>> {
+    let factor = 10f64.powi(precision as i32);
+    let mut bytes = encoded.bytes();
+    let mut points = Vec::new();
+    let (mut lat, mut lng) = (0i64, 0i64);
+    while let Some(d_lat) = next_value(&mut bytes)? {
+        let d_lng = next_value(&mut bytes)?.ok_or_else(|| {
+            Error::new(ErrorKind::InvalidData, "Polyline ends in the middle of coordinate pair")
+        })?;
+        lat += d_lat;
+        lng += d_lng;
+        points.push(GeoPoint::new((lat as f64 / factor) as f32, (lng as f64 / factor) as f32)?);
+    }
+    Ok(points)
+}
+
+fn next_value(bytes: &mut std::str::Bytes) -> Result<Option<i64>, Error> {
+    let mut result = 0i64;
+    let mut shift = 0;
+    loop {
+        let byte = match bytes.next() {
+            Some(byte) => byte,
+            None if shift == 0 => return Ok(None),
+            None => return Err(Error::new(ErrorKind::InvalidData, "Polyline ends in the middle of value")),
+        };
+        if !(63..127).contains(&byte) || shift > 60 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid polyline character: {}", byte as char),
+            ));
+        }
+        let chunk = i64::from(byte - 63);
+        result |= (chunk & 0x1f) << shift;
+        shift += 5;
+        if chunk < 0x20 {
+            break;
+        }
+    }
+    Ok(Some(if result & 1 == 1 { !(result >> 1) } else { result >> 1 }))
+}
+
+/// Encodes points as polyline
+///
+/// # Arguments:
+/// * points - points of polyline
+/// * precision - number of decimal places kept, usually 5
+///
+/// # Returns polyline in Google encoded polyline format
+///
+pub fn encode(points: &[GeoPoint], precision: u32) -> String {
+    let factor = 10f64.powi(precision as i32);
+    let mut encoded = String::new();
+    let (mut last_lat, mut last_lng) = (0i64, 0i64);
+    for point in points {
+        let lat = (f64::from(point.get_lat()) * factor).round() as i64;
+        let lng = (f64::from(point.get_lng()) * factor).round() as i64;
+        push_value(&mut encoded, lat - last_lat);
+        push_value(&mut encoded, lng - last_lng);
+        last_lat = lat;
+        last_lng = lng;
+    }
+    encoded
+}
+
+fn push_value(encoded: &mut String, value: i64) {
+    let mut value = if value < 0 { !(value << 1) } else { value << 1 };
+    while value >= 0x20 {
+        encoded.push((((value & 0x1f) | 0x20) as u8 + 63) as char);
+        value >>= 5;
+    }
+    encoded.push((value as u8 + 63) as char);
+}
+
+#[cfg(test)]
+mod test_polyline {
+    use super::{decode, encode, DEFAULT_PRECISION};
+
+    const EXAMPLE: &str = "_p~iF~ps|U_ulLnnqC_mqNvxq`@";
+
+    #[test]
+    fn test_decode_and_encode() {
+        let points = decode(EXAMPLE, DEFAULT_PRECISION).unwrap();
+        let expected = [(38.5, -120.2), (40.7, -120.95), (43.252, -126.453)];
+        assert_eq!(points.len(), 3);
+        for (point, (lat, lng)) in points.iter().zip(expected.iter()) {
+            assert!((point.get_lat() - lat).abs() < 1e-4);
+            assert!((point.get_lng() - lng).abs() < 1e-4);
+        }
+        assert_eq!(encode(&points, DEFAULT_PRECISION), EXAMPLE);
+        assert!(decode("", DEFAULT_PRECISION).unwrap().is_empty());
+        assert!(decode("_p~iF", DEFAULT_PRECISION).is_err());
+        assert!(decode("_p~iF~ps|", DEFAULT_PRECISION).is_err());
+        assert!(decode("_p~iF ps|U", DEFAULT_PRECISION).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+use crate::types;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+const SECONDS_PER_HOUR: i64 = 3600;
+
+/// Origin of archived measurements
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Source {
+    /// Measurements of installation with given id
+    Installation(u32),
+    /// Interpolated measurements for given point on map
+    Point(types::GeoPoint),
+}
+
+impl Source {
+    /// # Returns key uniquely naming this source, safe to be used as a file name
+    pub fn key(&self) -> String {
+        match self {
+            Source::Installation(id) => format!("installation-{}", id),
+            Source::Point(point) => format!("point-{}_{}", point.get_lat(), point.get_lng()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Record {
+    /// Where measurements come from
+    pub source: Source,
+    /// Unix timestamp in seconds of the moment measurements were fetched
+    pub fetched_at: i64,
+    /// Measurements as returned by the api
+    pub measurements: types::Measurements,
+}
+
+/// Hourly period missing from archived series
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gap {
+    /// First missing hour, unix timestamp in seconds, inclusive
+    pub from: i64,
+    /// End of missing period, unix timestamp in seconds, exclusive
+    pub till: i64,
+}
+
+impl Gap {
+    /// # Returns number of missing hours
+    pub fn hours(&self) -> i64 {
+        (self.till - self.from) / SECONDS_PER_HOUR
+    }
+}
+
+/// Archive of fetched measurements
+pub trait Store {
+    /// Appends record to the archive
+    fn save(&mut self, record: &Record) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Loads all records of given source in order they were saved
+    fn load(&self, source: &Source) -> Result<Vec<Record>, Box<dyn std::error::Error>>;
+
+    /// Builds hourly series of given source out of history and current values of all records
+    ///
+    /// # Returns values sorted by from_date_time, for repeated hours the most recently fetched wins
+    ///
+    fn series(
+        &self,
+        source: &Source,
+    ) -> Result<Vec<types::AveragedValues>, Box<dyn std::error::Error>> {
+        let mut by_hour = BTreeMap::new();
+        for record in self.load(source)? {
+            let measurements = record.measurements;
+            for values in measurements.history.into_iter().chain(measurements.current) {
+                if let Some(from) = values.from_timestamp() {
+                    by_hour.insert(from, values);
+                }
+            }
+        }
+        Ok(by_hour.into_values().collect())
+    }
+}
+
+/// Finds hours missing in between values of hourly series
+///
+/// # Arguments:
+/// * series - values sorted by from_date_time, as returned by Store::series
+///
+/// # Returns list of gaps, empty if series is continuous
+///
+pub fn find_gaps(series: &[types::AveragedValues]) -> Vec<Gap> {
+    let hours: Vec<i64> = series.iter().filter_map(|v| v.from_timestamp()).collect();
+    hour_gaps(&hours)
+}
+
+/// Finds hours missing in between sorted hour timestamps
+pub(crate) fn hour_gaps(hours: &[i64]) -> Vec<Gap> {
+    hours
+        .windows(2)
+        .filter(|pair| pair[1] - pair[0] > SECONDS_PER_HOUR)
+        .map(|pair| Gap {
+            from: pair[0] + SECONDS_PER_HOUR,
+            till: pair[1],
+        })
+        .collect()
+}
+
+/// Store keeping records in memory, lost when dropped
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    records: HashMap<String, Vec<Record>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Store for MemoryStore {
+    fn save(&mut self, record: &Record) -> Result<(), Box<dyn std::error::Error>> {
+        self.records
+            .entry(record.source.key())
+            .or_default()
+            .push(record.clone());
+        Ok(())
+    }
+
+    fn load(&self, source: &Source) -> Result<Vec<Record>, Box<dyn std::error::Error>> {
+        Ok(self.records.get(&source.key()).cloned().unwrap_or_default())
+    }
+}
+
+/// Store keeping records of each source as json lines in separate file of a directory
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    /// Constructs FileStore, creates directory if missing
+    ///
+    /// # Arguments:
+    /// * dir - directory to keep archive files in
+    ///
+    /// # Returns FileStore if directory is accessible Error otherwise
+    ///
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, source: &Source) -> PathBuf {
+        self.dir.join(format!("{}.jsonl", source.key()))
+    }
+}
+
+impl Store for FileStore {
+    fn save(&mut self, record: &Record) -> Result<(), Box<dyn std::error::Error>> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path(&record.source))?;
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+        Ok(())
+    }
+
+    fn load(&self, source: &Source) -> Result<Vec<Record>, Box<dyn std::error::Error>> {
+        let path = self.path(source);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let mut records = Vec::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                records.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod test_store {
+    use super::{find_gaps, FileStore, MemoryStore, Record, Source, Store};
+    use crate::types::{AveragedValues, Measurements};
+    use std::env;
+
+    fn hour(from: &str) -> AveragedValues {
+        AveragedValues {
+            from_date_time: Some(from.to_string()),
+            till_date_time: None,
+            values: Vec::new(),
+            indexes: Vec::new(),
+            standards: Vec::new(),
+        }
+    }
+
+    fn record(fetched_at: i64, history: Vec<AveragedValues>) -> Record {
+        Record {
+            source: Source::Installation(18),
+            fetched_at,
+            measurements: Measurements {
+                current: None,
+                history,
+                forecast: Vec::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_series_merges_records_and_finds_gaps() {
+        let mut store = MemoryStore::new();
+        store
+            .save(&record(1, vec![hour("2019-12-09T10:00:00.000Z"), hour("2019-12-09T11:00:00.000Z")]))
+            .unwrap();
+        store
+            .save(&record(2, vec![hour("2019-12-09T11:00:00.000Z"), hour("2019-12-09T14:00:00.000Z")]))
+            .unwrap();
+        let series = store.series(&Source::Installation(18)).unwrap();
+        assert_eq!(series.len(), 3);
+        let gaps = find_gaps(&series);
+        assert_eq!(gaps.len(), 1);
+        assert_eq!(gaps[0].hours(), 2);
+        assert!(store.load(&Source::Installation(19)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_file_store_round_trip() {
+        let dir = env::temp_dir().join(format!("airly_rs_store_{}", std::process::id()));
+        let mut store = FileStore::new(&dir).unwrap();
+        store.save(&record(1, vec![hour("2019-12-09T10:00:00.000Z")])).unwrap();
+        store.save(&record(2, Vec::new())).unwrap();
+        let records = FileStore::new(&dir).unwrap().load(&Source::Installation(18)).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].fetched_at, 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
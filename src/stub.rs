@@ -0,0 +1,232 @@
+//! Local stand-in for Airly api used by unit tests
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+pub const MEASUREMENTS_JSON: &str = r##"{
+  "current": {
+    "fromDateTime": "2019-12-09T10:00:00.000Z",
+    "tillDateTime": "2019-12-09T11:00:00.000Z",
+    "values": [
+      {"name": "PM1", "value": 13.61},
+      {"name": "PM25", "value": 19.58},
+      {"name": "PM10", "value": 29.98},
+      {"name": "PRESSURE", "value": 1020.38},
+      {"name": "HUMIDITY", "value": 83.68},
+      {"name": "TEMPERATURE", "value": 5.04}
+    ],
+    "indexes": [
+      {"name": "AIRLY_CAQI", "value": 37.98, "level": "LOW", "description": "Air is quite good.", "advice": "Take a breath!", "color": "#D1CF1E"}
+    ],
+    "standards": [
+      {"name": "WHO", "pollutant": "PM25", "limit": 25.0, "percent": 78.32},
+      {"name": "WHO", "pollutant": "PM10", "limit": 50.0, "percent": 59.96}
+    ]
+  },
+  "history": [
+    {
+      "fromDateTime": "2019-12-09T09:00:00.000Z",
+      "tillDateTime": "2019-12-09T10:00:00.000Z",
+      "values": [{"name": "PM25", "value": 21.3}, {"name": "PM10", "value": 31.2}],
+      "indexes": [{"name": "AIRLY_CAQI", "value": 40.1, "level": "LOW", "description": null, "advice": null, "color": "#D1CF1E"}],
+      "standards": []
+    }
+  ],
+  "forecast": [
+    {
+      "fromDateTime": "2019-12-09T11:00:00.000Z",
+      "tillDateTime": "2019-12-09T12:00:00.000Z",
+      "values": [{"name": "PM25", "value": 18.4}, {"name": "PM10", "value": 27.9}],
+      "indexes": [{"name": "AIRLY_CAQI", "value": 35.2, "level": "LOW", "description": null, "advice": null, "color": "#D1CF1E"}],
+      "standards": []
+    }
+  ]
+}"##;
+
+pub const INSTALLATION_JSON: &str = r##"{
+  "id": 18,
+  "location": {"latitude": 50.062006, "longitude": 19.940984},
+  "address": {
+    "country": "Poland",
+    "city": "Kraków",
+    "street": "Mikołajska",
+    "number": "4",
+    "displayAddress1": "Kraków",
+    "displayAddress2": "Mikołajska"
+  },
+  "elevation": 220.38,
+  "airly": true,
+  "sponsor": {"id": 7, "name": "Airly", "description": "Airly Sensor's sponsor", "logo": null, "link": null}
+}"##;
+
+/// Request received by StubServer
+#[derive(Debug, Clone)]
+pub struct StubRequest {
+    pub url: String,
+    /// Header names lowercased with their values
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl StubRequest {
+    /// # Returns value of header with given lowercase name if it was sent
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Status code, headers and body of stub response
+pub type StubResponse = (u16, Vec<(String, String)>, String);
+
+/// Minimal http server answering requests with whatever handler returns for requested url
+pub struct StubServer {
+    addr: SocketAddr,
+    received: Arc<Mutex<Vec<StubRequest>>>,
+    running: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl StubServer {
+    /// Starts server on random local port
+    ///
+    /// # Arguments:
+    /// * handler - maps requested url (path with query) to status code and json body
+    ///
+    pub fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&str) -> (u16, String) + Send + Sync + 'static,
+    {
+        StubServer::start_with(move |request| {
+            let (status, body) = handler(&request.url);
+            (status, Vec::new(), body)
+        })
+    }
+
+    /// Starts server on random local port
+    ///
+    /// # Arguments:
+    /// * handler - maps whole request to status code, headers and body
+    ///
+    pub fn start_with<F>(handler: F) -> Self
+    where
+        F: Fn(&StubRequest) -> StubResponse + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let running = Arc::new(AtomicBool::new(true));
+        let (seen, alive, handler) = (received.clone(), running.clone(), Arc::new(handler));
+        let thread = thread::spawn(move || {
+            while alive.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let (seen, handler) = (seen.clone(), handler.clone());
+                        thread::spawn(move || serve(stream, &*handler, &seen));
+                    }
+                    Err(_) => thread::sleep(Duration::from_millis(5)),
+                }
+            }
+        });
+        Self {
+            addr,
+            received,
+            running,
+            thread: Some(thread),
+        }
+    }
+
+    /// Closes listening socket, following requests fail to connect, simulates api outage
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+
+    /// # Returns api root to be passed to AirlyClient::with_base_url
+    pub fn base_url(&self) -> String {
+        format!("http://{}/v2", self.addr)
+    }
+
+    /// # Returns all requests received so far
+    pub fn received(&self) -> Vec<StubRequest> {
+        self.received.lock().unwrap().clone()
+    }
+
+    /// # Returns urls of all requests received so far
+    pub fn requests(&self) -> Vec<String> {
+        self.received().into_iter().map(|request| request.url).collect()
+    }
+
+    /// # Returns non-empty bodies of all requests received so far
+    pub fn bodies(&self) -> Vec<String> {
+        self.received()
+            .into_iter()
+            .map(|request| request.body)
+            .filter(|body| !body.is_empty())
+            .collect()
+    }
+}
+
+/// Answers single request, connection is closed afterwards so none outlives stopped server
+fn serve(
+    stream: TcpStream,
+    handler: &dyn Fn(&StubRequest) -> StubResponse,
+    seen: &Mutex<Vec<StubRequest>>,
+) -> Option<()> {
+    stream.set_nonblocking(false).ok()?;
+    let mut reader = BufReader::new(stream.try_clone().ok()?);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let url = line.split_whitespace().nth(1)?.to_string();
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line).ok()?;
+        match line.trim_end().split_once(':') {
+            Some((name, value)) => headers.push((name.trim().to_lowercase(), value.trim().to_string())),
+            None => break,
+        }
+    }
+    let length = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body).ok()?;
+    let request = StubRequest {
+        url,
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    };
+    let (status, headers, body) = handler(&request);
+    seen.lock().unwrap().push(request);
+    let mut response = format!(
+        "HTTP/1.1 {} Stub\r\nContent-Length: {}\r\nConnection: close\r\n",
+        status,
+        body.len()
+    );
+    for (name, value) in headers {
+        response.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    response.push_str("\r\n");
+    response.push_str(&body);
+    let mut stream = stream;
+    stream.write_all(response.as_bytes()).ok()?;
+    stream.flush().ok()
+}
+
+impl Drop for StubServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,56 @@
+use super::{HttpRequest, HttpResponse, Transport};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Default)]
+struct State {
+    routes: Vec<(String, HttpResponse)>,
+    requests: Vec<HttpRequest>,
+}
+
+/// Transport answering from canned responses without network, clones share responses and sent requests
+#[derive(Debug, Clone, Default)]
+pub struct MemoryTransport {
+    state: Arc<Mutex<State>>,
+}
+
+impl MemoryTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers requests which url ends with given suffix, later routes take precedence
+    ///
+    /// # Arguments:
+    /// * url_suffix - end of url, query included, e.g. installations/18
+    /// * response - response to answer with
+    ///
+    pub fn with_response(self, url_suffix: &str, response: HttpResponse) -> Self {
+        self.state
+            .lock()
+            .unwrap()
+            .routes
+            .push((url_suffix.to_string(), response));
+        self
+    }
+
+    /// # Returns all requests sent so far
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+impl Transport for MemoryTransport {
+    /// # Returns response of matching route, 404 if none matches
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse, Box<dyn std::error::Error>> {
+        let mut state = self.state.lock().unwrap();
+        let response = state
+            .routes
+            .iter()
+            .rev()
+            .find(|(suffix, _)| request.url.ends_with(suffix.as_str()))
+            .map(|(_, response)| response.clone())
+            .unwrap_or_else(|| HttpResponse::new(404, r#"{"errorCode": "NOT_FOUND"}"#));
+        state.requests.push(request);
+        Ok(response)
+    }
+}
@@ -0,0 +1,205 @@
+//! Http stack used by AirlyClient and by sinks and notifiers posting data out
+
+use std::fmt;
+
+mod memory;
+#[cfg(feature = "reqwest")]
+mod reqwest_transport;
+#[cfg(feature = "ureq")]
+mod ureq_transport;
+
+pub use self::memory::MemoryTransport;
+#[cfg(feature = "reqwest")]
+pub use self::reqwest_transport::ReqwestTransport;
+#[cfg(feature = "ureq")]
+pub use self::ureq_transport::UreqTransport;
+
+/// Transport used by AirlyClient::new, reqwest unless only ureq feature is enabled
+#[cfg(feature = "reqwest")]
+pub type DefaultTransport = ReqwestTransport;
+/// Transport used by AirlyClient::new, reqwest unless only ureq feature is enabled
+#[cfg(all(feature = "ureq", not(feature = "reqwest")))]
+pub type DefaultTransport = UreqTransport;
+
+#[cfg(not(any(feature = "reqwest", feature = "ureq")))]
+compile_error!("airly_rs needs an http stack, enable feature reqwest (default) or ureq");
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    Get,
+    Post,
+}
+
+impl Method {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+        }
+    }
+}
+
+/// Request sent by Transport, values of sensitive headers are not printed by Debug
+#[derive(Clone, PartialEq)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+    /// Names of headers holding secrets, e.g. api key
+    pub sensitive: Vec<String>,
+}
+
+impl fmt::Debug for HttpRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let headers: Vec<(&str, &str)> = self
+            .headers
+            .iter()
+            .map(|(name, value)| match self.is_sensitive(name) {
+                true => (name.as_str(), "[redacted]"),
+                false => (name.as_str(), value.as_str()),
+            })
+            .collect();
+        f.debug_struct("HttpRequest")
+            .field("method", &self.method)
+            .field("url", &self.url)
+            .field("headers", &headers)
+            .field("body", &self.body)
+            .finish()
+    }
+}
+
+impl HttpRequest {
+    pub fn get(url: &str) -> Self {
+        Self {
+            method: Method::Get,
+            url: url.to_string(),
+            headers: Vec::new(),
+            body: None,
+            sensitive: Vec::new(),
+        }
+    }
+
+    pub fn post(url: &str, body: String) -> Self {
+        Self {
+            method: Method::Post,
+            url: url.to_string(),
+            headers: Vec::new(),
+            body: Some(body),
+            sensitive: Vec::new(),
+        }
+    }
+
+    /// Posts value serialized to json with matching content type
+    pub fn post_json(url: &str, body: &serde_json::Value) -> Self {
+        HttpRequest::post(url, body.to_string()).with_header("Content-Type", "application/json")
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Adds header holding secret, transports mark it sensitive and Debug does not print its value
+    pub fn with_sensitive_header(mut self, name: &str, value: &str) -> Self {
+        self.sensitive.push(name.to_string());
+        self.with_header(name, value)
+    }
+
+    /// # Returns value of header with given name, compared case insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// # Returns true if header with given name holds secret
+    pub fn is_sensitive(&self, name: &str) -> bool {
+        self.sensitive.iter().any(|n| n.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Response received by Transport, any status including errors
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl HttpResponse {
+    pub fn new(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.to_string(),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// # Returns value of header with given name, compared case insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// # Returns true for 2xx status
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// Sends http requests
+pub trait Transport: Send + Sync + fmt::Debug {
+    /// Sends request
+    ///
+    /// # Returns response whatever its status is, Error only if no response was received
+    ///
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse, Box<dyn std::error::Error>>;
+}
+
+#[cfg(test)]
+mod test_transport {
+    use super::{DefaultTransport, HttpRequest, HttpResponse, MemoryTransport, Method, Transport};
+    use crate::client::AirlyClient;
+    use crate::stub::{StubServer, INSTALLATION_JSON};
+
+    const API_KEY: &str = "00000000000000000000000000000000";
+
+    #[test]
+    fn test_client_over_memory_transport() {
+        let transport = MemoryTransport::new()
+            .with_response("installations/18", HttpResponse::new(200, INSTALLATION_JSON))
+            .with_response("installations/19", HttpResponse::new(404, "{}"));
+        let client = AirlyClient::with_transport(API_KEY.to_string(), transport).unwrap();
+        assert_eq!(client.clone().get_installation(18).unwrap().id, 18);
+        assert!(client.clone().get_installation(19).is_err());
+        let requests = client.transport().requests();
+        assert_eq!(requests[0].url, "https://airapi.airly.eu/v2/installations/18");
+        assert_eq!(requests[0].header("APIKEY"), Some(API_KEY));
+        assert_eq!(requests[1].method, Method::Get);
+    }
+
+    #[test]
+    fn test_default_transport_headers_and_post() {
+        let server = StubServer::start_with(|request| {
+            let headers = vec![("X-Echo".to_string(), request.header("x-request").unwrap_or("").to_string())];
+            (201, headers, request.body.clone())
+        });
+        let request = HttpRequest::post_json(&server.base_url(), &serde_json::json!({"pm25": 19.58}))
+            .with_header("X-Request", "airly");
+        let response = DefaultTransport::default().send(request).unwrap();
+        assert!(response.is_success());
+        assert_eq!(response.header("x-echo"), Some("airly"));
+        assert_eq!(response.body, r#"{"pm25":19.58}"#);
+        assert_eq!(server.received()[0].header("content-type"), Some("application/json"));
+    }
+}
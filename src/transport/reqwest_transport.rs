@@ -0,0 +1,55 @@
+use super::{HttpRequest, HttpResponse, Method, Transport};
+use reqwest::blocking::Client;
+use reqwest::header::HeaderValue;
+
+/// Transport backed by blocking reqwest client
+#[derive(Debug, Clone)]
+pub struct ReqwestTransport {
+    client: Client,
+}
+
+impl ReqwestTransport {
+    pub fn new() -> Self {
+        ReqwestTransport::with_client(Client::new())
+    }
+
+    /// Uses client configured by caller, e.g. with proxy or timeouts
+    pub fn with_client(client: Client) -> Self {
+        Self { client }
+    }
+}
+
+impl Default for ReqwestTransport {
+    fn default() -> Self {
+        ReqwestTransport::new()
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse, Box<dyn std::error::Error>> {
+        let mut builder = match request.method {
+            Method::Get => self.client.get(&request.url),
+            Method::Post => self.client.post(&request.url),
+        };
+        for (name, value) in &request.headers {
+            let mut value = HeaderValue::from_str(value)?;
+            value.set_sensitive(request.is_sensitive(name));
+            builder = builder.header(name.as_str(), value);
+        }
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+        let res = builder.send()?;
+        let headers = res
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.as_str().to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let status = res.status().as_u16();
+        Ok(HttpResponse {
+            status,
+            headers,
+            body: res.text()?,
+        })
+    }
+}
@@ -0,0 +1,58 @@
+use super::{HttpRequest, HttpResponse, Transport};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Lightweight transport backed by ureq, for small binaries not otherwise depending on reqwest
+#[derive(Debug, Clone)]
+pub struct UreqTransport {
+    agent: ureq::Agent,
+}
+
+impl UreqTransport {
+    pub fn new() -> Self {
+        UreqTransport::with_agent(ureq::AgentBuilder::new().timeout(TIMEOUT).build())
+    }
+
+    /// Uses agent configured by caller, e.g. with proxy
+    pub fn with_agent(agent: ureq::Agent) -> Self {
+        Self { agent }
+    }
+}
+
+impl Default for UreqTransport {
+    fn default() -> Self {
+        UreqTransport::new()
+    }
+}
+
+impl Transport for UreqTransport {
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse, Box<dyn std::error::Error>> {
+        let mut req = self.agent.request(request.method.as_str(), &request.url);
+        for (name, value) in &request.headers {
+            req = req.set(name, value);
+        }
+        let result = match &request.body {
+            Some(body) => req.send_string(body),
+            None => req.call(),
+        };
+        let res = match result {
+            Ok(res) | Err(ureq::Error::Status(_, res)) => res,
+            Err(err) => return Err(Box::new(err)),
+        };
+        let headers = res
+            .headers_names()
+            .into_iter()
+            .filter_map(|name| {
+                let value = res.header(&name)?.to_string();
+                Some((name, value))
+            })
+            .collect();
+        let status = res.status();
+        Ok(HttpResponse {
+            status,
+            headers,
+            body: res.into_string()?,
+        })
+    }
+}
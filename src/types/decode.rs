@@ -0,0 +1,182 @@
+use serde::de::{self, Deserialize, DeserializeOwned, Deserializer};
+use serde_ignored::Path;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+
+/// Fields of api objects not known to this crate, keyed by json pointer, e.g. /address/postalCode
+pub type Extra = BTreeMap<String, serde_json::Value>;
+
+/// How strictly api responses are deserialized
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Decoding {
+    /// Required fields must be present and not null, unknown fields are collected in Decoded.unknown
+    #[default]
+    Default,
+    /// Nulls in required fields become empty values and elements of lists failing to deserialize are
+    /// skipped, both with warnings
+    Lenient,
+    /// Like Default, but unknown fields are rejected, for contract testing against the api
+    Strict,
+}
+
+/// Deserialized value with fields of api objects it has no place for
+#[derive(Debug, Clone)]
+pub struct Decoded<T> {
+    pub value: T,
+    /// Fields not known to this crate, in lenient mode those inside skipped or kept list elements are not seen
+    pub unknown: Extra,
+}
+
+thread_local! {
+    /// Set only while lenient from_str runs, helpers below read it to tolerate bad data
+    static LENIENT: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Restores previous mode when decoding ends, also on panic
+struct LenientGuard(bool);
+
+impl Drop for LenientGuard {
+    fn drop(&mut self) {
+        LENIENT.with(|lenient| lenient.set(self.0));
+    }
+}
+
+fn lenient() -> bool {
+    LENIENT.with(|lenient| lenient.get())
+}
+
+/// Deserializes json in given mode
+///
+/// # Arguments:
+/// * text - json text
+/// * decoding - how strictly text is deserialized
+///
+/// # Returns deserialized value with unknown fields or Error if text does not match T in given mode
+///
+pub fn from_str<T: DeserializeOwned>(text: &str, decoding: Decoding) -> Result<Decoded<T>, serde_json::Error> {
+    let json: serde_json::Value = serde_json::from_str(text)?;
+    let mut unknown = Extra::new();
+    let value = {
+        let _guard = LenientGuard(LENIENT.with(|lenient| lenient.replace(decoding == Decoding::Lenient)));
+        serde_ignored::deserialize(&json, |path| {
+            let pointer = pointer(&path);
+            let field = json.pointer(&pointer).cloned().unwrap_or(serde_json::Value::Null);
+            unknown.insert(pointer, field);
+        })?
+    };
+    if !unknown.is_empty() {
+        let names: Vec<&str> = unknown.keys().map(String::as_str).collect();
+        if decoding == Decoding::Strict {
+            return Err(de::Error::custom(format!("unknown fields: {}", names.join(", "))));
+        }
+        debug!("Unknown fields in api response: {}", names.join(", "));
+    }
+    Ok(Decoded { value, unknown })
+}
+
+/// # Returns json pointer of field at given path
+fn pointer(path: &Path) -> String {
+    match path {
+        Path::Root => String::new(),
+        Path::Seq { parent, index } => format!("{}/{}", pointer(parent), index),
+        Path::Map { parent, key } => format!("{}/{}", pointer(parent), key.replace('~', "~0").replace('/', "~1")),
+        Path::Some { parent } | Path::NewtypeStruct { parent } | Path::NewtypeVariant { parent } => pointer(parent),
+    }
+}
+
+/// List of api objects, in lenient mode elements failing to deserialize are skipped
+#[derive(Debug, Clone)]
+pub struct Elements<T>(pub Vec<T>);
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Elements<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        elements(deserializer).map(Elements)
+    }
+}
+
+/// Deserializes list, in lenient mode skipping elements that fail with warning
+pub(crate) fn elements<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    if !lenient() {
+        return Vec::<T>::deserialize(deserializer);
+    }
+    let raw = Vec::<serde_json::Value>::deserialize(deserializer)?;
+    let mut list = Vec::with_capacity(raw.len());
+    for (at, value) in raw.into_iter().enumerate() {
+        match T::deserialize(value) {
+            Ok(element) => list.push(element),
+            Err(err) => warn!("Skipping element {} of list: {}", at, err),
+        }
+    }
+    Ok(list)
+}
+
+/// Deserializes required value, in lenient mode null becomes default value with warning
+pub(crate) fn required<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    match Option::<T>::deserialize(deserializer)? {
+        Some(value) => Ok(value),
+        None if lenient() => {
+            warn!("Null in required field, using default value");
+            Ok(T::default())
+        }
+        None => Err(de::Error::invalid_type(de::Unexpected::Unit, &"value")),
+    }
+}
+
+#[cfg(test)]
+mod test_decode {
+    use super::{from_str, Decoding, Elements};
+    use crate::types::{Installation, Measurements};
+
+    const INSTALLATIONS: &str = r#"[
+        {"id": 18, "location": {"latitude": 50.06, "longitude": 19.94}, "elevation": null, "airly": true,
+         "address": {"country": "Poland", "city": "Kraków", "street": null, "number": null,
+                     "displayAddress1": null, "displayAddress2": null},
+         "sponsor": null},
+        {"id": 19, "location": null},
+        {"id": 20, "location": {"latitude": 50.07, "longitude": 19.95}, "elevation": 220.4, "airly": true,
+         "address": {"country": "Poland", "city": "Kraków", "street": "Mikołajska", "number": "4",
+                     "displayAddress1": "Kraków", "displayAddress2": "Mikołajska", "postalCode": "31-027"},
+         "sponsor": {"id": 7, "name": "Airly", "description": null, "logo": null, "link": null},
+         "locationId": 20}
+    ]"#;
+
+    #[test]
+    fn test_lenient_tolerates_nulls_and_skips_bad_elements() {
+        assert!(from_str::<Elements<Installation>>(INSTALLATIONS, Decoding::Default).is_err());
+        let installations = from_str::<Elements<Installation>>(INSTALLATIONS, Decoding::Lenient).unwrap().value.0;
+        assert_eq!(installations.iter().map(|i| i.id).collect::<Vec<i32>>(), vec![18, 20]);
+        assert_eq!(installations[0].address.street, "");
+        assert_eq!(installations[0].elevation, 0.0);
+        assert_eq!(installations[0].sponsor.name, "");
+    }
+
+    #[test]
+    fn test_unknown_fields_captured_or_rejected() {
+        let last = &INSTALLATIONS[INSTALLATIONS.rfind("{\"id\": 20").unwrap()..INSTALLATIONS.rfind(']').unwrap()];
+        let installation = from_str::<Installation>(last, Decoding::Default).unwrap();
+        assert_eq!(installation.value.id, 20);
+        assert_eq!(installation.unknown["/locationId"], 20);
+        assert_eq!(installation.unknown["/address/postalCode"], "31-027");
+        let err = from_str::<Installation>(last, Decoding::Strict).unwrap_err();
+        assert!(err.to_string().contains("unknown fields: /address/postalCode, /locationId"));
+
+        let measurements = r#"{"current": null, "history": [], "forecast": [], "hourly": true}"#;
+        assert!(from_str::<Measurements>(measurements, Decoding::Default).is_ok());
+        assert!(from_str::<Measurements>(measurements, Decoding::Strict).is_err());
+        assert_eq!(from_str::<Measurements>(measurements, Decoding::Lenient).unwrap().unknown.len(), 1);
+
+        let current = r#"{"current": {"fromDateTime": null, "tillDateTime": null, "values": [{"name": "PM25",
+            "value": 19.58, "unit": "ug/m3"}], "indexes": [], "standards": []}, "history": [], "forecast": []}"#;
+        let measurements = from_str::<Measurements>(current, Decoding::Default).unwrap();
+        assert_eq!(measurements.unknown["/current/values/0/unit"], "ug/m3");
+        assert_eq!(measurements.value.current.unwrap().values[0].value, Some(19.58));
+    }
+}
@@ -1,11 +1,17 @@
+use chrono::DateTime;
+use std::hash::{Hash, Hasher};
 use std::io::{Error, ErrorKind};
 
+pub mod decode;
+
+pub use self::decode::{Decoded, Decoding, Elements, Extra};
+
 const ERR_OUT_OF_BOUNDS: &str = "Value of passed argument out of bounds";
 const MAX_EARTH_RADIUS_KM: u32 = 6371;
 const MAX_LNG: f32 = 180.0;
 const MAX_LAT: f32 = 90.0;
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub struct GeoPoint {
     #[serde(rename = "latitude")]
     lat: f32,
@@ -13,6 +19,17 @@ pub struct GeoPoint {
     lng: f32,
 }
 
+// coordinates are never NaN, GeoPoint::new rejects them
+impl Eq for GeoPoint {}
+
+impl Hash for GeoPoint {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // adding 0.0 turns -0.0 into 0.0, which compare equal
+        (self.lat + 0.0).to_bits().hash(state);
+        (self.lng + 0.0).to_bits().hash(state);
+    }
+}
+
 impl GeoPoint {
     /// Creates new GeoPoint if passes arguments validation
     /// 
@@ -51,9 +68,25 @@ impl GeoPoint {
     pub fn get_lng(self) -> f32 {
         self.lng
     }
+
+    /// Great-circle distance to other point
+    ///
+    /// # Arguments:
+    ///
+    /// * other - point to measure distance to
+    ///
+    /// # Returns distance in km calculated with haversine formula
+    ///
+    pub fn distance_km(self, other: GeoPoint) -> f64 {
+        let (lat1, lat2) = (f64::from(self.lat).to_radians(), f64::from(other.lat).to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lng = f64::from(other.lng - self.lng).to_radians();
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lng / 2.0).sin().powi(2);
+        2.0 * f64::from(MAX_EARTH_RADIUS_KM) * a.sqrt().asin()
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct GeoCircle {
     point: GeoPoint,
     radius_km: u32,
@@ -99,11 +132,13 @@ impl GeoCircle {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Address {
     pub country: String,
     pub city: String,
+    #[serde(deserialize_with = "decode::required")]
     pub street: String,
+    #[serde(deserialize_with = "decode::required")]
     pub number: String,
     #[serde(rename = "displayAddress1")]
     pub display_address1: Option<String>,
@@ -111,7 +146,7 @@ pub struct Address {
     pub display_address2: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Sponsor {
     pub id: i32,
     pub name: String,
@@ -129,10 +164,12 @@ pub struct Installation {
     /// Address on which installation is registered
     pub address: Address,
     /// Elevation over the sea level
+    #[serde(deserialize_with = "decode::required")]
     pub elevation: f64,
     /// Indicates if this is Airly sensor
     pub airly: bool,
     /// Sponsor name if present
+    #[serde(deserialize_with = "decode::required")]
     pub sponsor: Sponsor,
 }
 
@@ -144,6 +181,76 @@ pub struct Value {
     pub value: Option<f64>,
 }
 
+/// Kind of measurement as named by Airly in Value.name and MeasurementType.name
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MeasurementKind {
+    Pm1,
+    Pm25,
+    Pm10,
+    Temperature,
+    Humidity,
+    Pressure,
+    WindSpeed,
+    WindBearing,
+    No2,
+    O3,
+    So2,
+    Co,
+    /// Measurement not known to this crate, holds its Airly name
+    Other(String),
+}
+
+impl MeasurementKind {
+    /// Maps Airly measurement name to its kind
+    ///
+    /// # Arguments:
+    /// * name - Airly name, e.g. PM25 or TEMPERATURE
+    ///
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "PM1" => MeasurementKind::Pm1,
+            "PM25" => MeasurementKind::Pm25,
+            "PM10" => MeasurementKind::Pm10,
+            "TEMPERATURE" => MeasurementKind::Temperature,
+            "HUMIDITY" => MeasurementKind::Humidity,
+            "PRESSURE" => MeasurementKind::Pressure,
+            "WIND_SPEED" => MeasurementKind::WindSpeed,
+            "WIND_BEARING" => MeasurementKind::WindBearing,
+            "NO2" => MeasurementKind::No2,
+            "O3" => MeasurementKind::O3,
+            "SO2" => MeasurementKind::So2,
+            "CO" => MeasurementKind::Co,
+            other => MeasurementKind::Other(other.to_string()),
+        }
+    }
+
+    /// # Returns Airly name of this kind
+    pub fn name(&self) -> &str {
+        match self {
+            MeasurementKind::Pm1 => "PM1",
+            MeasurementKind::Pm25 => "PM25",
+            MeasurementKind::Pm10 => "PM10",
+            MeasurementKind::Temperature => "TEMPERATURE",
+            MeasurementKind::Humidity => "HUMIDITY",
+            MeasurementKind::Pressure => "PRESSURE",
+            MeasurementKind::WindSpeed => "WIND_SPEED",
+            MeasurementKind::WindBearing => "WIND_BEARING",
+            MeasurementKind::No2 => "NO2",
+            MeasurementKind::O3 => "O3",
+            MeasurementKind::So2 => "SO2",
+            MeasurementKind::Co => "CO",
+            MeasurementKind::Other(name) => name,
+        }
+    }
+}
+
+impl Value {
+    /// # Returns kind of this measurement if it is named
+    pub fn kind(&self) -> Option<MeasurementKind> {
+        self.name.as_ref().map(|name| MeasurementKind::from_name(name))
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Index {
     /// Name of this index
@@ -160,6 +267,32 @@ pub struct Index {
     pub color: Option<String>,
 }
 
+/// Airly index level names from the best to the worst air quality
+pub const INDEX_LEVELS: [&str; 7] = [
+    "VERY_LOW",
+    "LOW",
+    "MEDIUM",
+    "HIGH",
+    "VERY_HIGH",
+    "EXTREME",
+    "AIRMAGEDDON",
+];
+
+/// Ranks index level name
+///
+/// # Returns position in INDEX_LEVELS, 0 for the best air, None for unknown level
+///
+pub fn level_rank(level: &str) -> Option<usize> {
+    INDEX_LEVELS.iter().position(|l| *l == level)
+}
+
+impl Index {
+    /// # Returns rank of this index level, see level_rank
+    pub fn level_rank(&self) -> Option<usize> {
+        self.level.as_deref().and_then(level_rank)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Standard {
     /// Name of this standard
@@ -181,17 +314,58 @@ pub struct AveragedValues {
     #[serde(rename = "tillDateTime")]
     pub till_date_time: Option<String>,
     /// List of raw measurements, averaged over specified period. Measurement types available in this list depend on the capabilities of the queried installation, e.g. particulate matter (PM1, PM25, PM10), gases (CO, NO2, SO2, O3) or weather conditions (temperature, humidity, pressure)
+    #[serde(deserialize_with = "decode::elements")]
     pub values: Vec<Value>,
     /// List of indexes calculated from the values available. Indexes are defined by relevant national and international institutions, e.g. EU, GIOŚ or US EPA
+    #[serde(deserialize_with = "decode::elements")]
     pub indexes: Vec<Index>,
     /// List of 'standard' values, or 'limits' for pollutants that should not be exceeded over certain period of time. Limits are defined by relevant national and international institutions, like e.g. WHO or EPA. For each standard limit in this list there is also a corresponding measurement expressed as a percent value of the limit
+    #[serde(deserialize_with = "decode::elements")]
     pub standards: Vec<Standard>,
 }
 
+impl AveragedValues {
+    /// Finds value of given measurement kind
+    ///
+    /// # Returns value if measured in this period None otherwise
+    ///
+    pub fn value(&self, kind: &MeasurementKind) -> Option<f64> {
+        self.values
+            .iter()
+            .find(|v| v.name.as_deref() == Some(kind.name()))
+            .and_then(|v| v.value)
+    }
+
+    /// Parses left bound of averaging period
+    ///
+    /// # Returns unix timestamp in seconds if from_date_time is present and valid None otherwise
+    ///
+    pub fn from_timestamp(&self) -> Option<i64> {
+        parse_timestamp(&self.from_date_time)
+    }
+
+    /// Parses right bound of averaging period
+    ///
+    /// # Returns unix timestamp in seconds if till_date_time is present and valid None otherwise
+    ///
+    pub fn till_timestamp(&self) -> Option<i64> {
+        parse_timestamp(&self.till_date_time)
+    }
+}
+
+fn parse_timestamp(date_time: &Option<String>) -> Option<i64> {
+    date_time
+        .as_ref()
+        .and_then(|d| DateTime::parse_from_rfc3339(d).ok())
+        .map(|d| d.timestamp())
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Measurements {
     pub current: Option<AveragedValues>,
+    #[serde(deserialize_with = "decode::elements")]
     pub history: Vec<AveragedValues>,
+    #[serde(deserialize_with = "decode::elements")]
     pub forecast: Vec<AveragedValues>,
 }
 
@@ -0,0 +1,334 @@
+//! Typed wind readings built from WIND_SPEED and WIND_BEARING values of measurements
+
+use crate::types::{AveragedValues, MeasurementKind};
+
+const KMH_PER_MS: f64 = 3.6;
+const KNOTS_PER_MS: f64 = 1.943_844;
+const FULL_CIRCLE_DEG: f64 = 360.0;
+/// Mean vectors shorter than this are treated as having no direction
+const CALM_RESULTANT: f64 = 1e-9;
+/// Upper bounds of Beaufort scale degrees in m/s, degree 12 above the last one
+const BEAUFORT_MS: [f64; 12] = [0.5, 1.5, 3.3, 5.5, 7.9, 10.7, 13.8, 17.1, 20.7, 24.4, 28.4, 32.6];
+
+/// # Returns speed in m/s of speed given in km/h
+pub fn kmh_to_ms(kmh: f64) -> f64 {
+    kmh / KMH_PER_MS
+}
+
+/// # Returns speed in km/h of speed given in m/s
+pub fn ms_to_kmh(ms: f64) -> f64 {
+    ms * KMH_PER_MS
+}
+
+/// # Returns speed in knots of speed given in m/s
+pub fn ms_to_knots(ms: f64) -> f64 {
+    ms * KNOTS_PER_MS
+}
+
+/// # Returns Beaufort scale degree, 0 to 12, of speed given in m/s
+pub fn beaufort(ms: f64) -> u8 {
+    BEAUFORT_MS.iter().take_while(|limit| ms >= **limit).count() as u8
+}
+
+/// # Returns bearing moved into range 0 inclusive to 360 exclusive
+pub fn normalize_bearing(bearing_deg: f64) -> f64 {
+    let bearing = bearing_deg.rem_euclid(FULL_CIRCLE_DEG);
+    if bearing >= FULL_CIRCLE_DEG {
+        0.0
+    } else {
+        bearing
+    }
+}
+
+/// Averages bearings on circle, so mean of 350 and 10 degrees is 0 not 180
+///
+/// # Arguments:
+/// * bearings_deg - bearings in degrees
+///
+/// # Returns mean bearing in degrees, None if there are no bearings or they cancel each other out
+///
+pub fn mean_bearing(bearings_deg: &[f64]) -> Option<f64> {
+    let (sin, cos) = bearings_deg.iter().fold((0.0, 0.0), |(sin, cos), bearing| {
+        let rad = bearing.to_radians();
+        (sin + rad.sin(), cos + rad.cos())
+    });
+    if bearings_deg.is_empty() || sin.hypot(cos) < CALM_RESULTANT * bearings_deg.len() as f64 {
+        return None;
+    }
+    Some(normalize_bearing(sin.atan2(cos).to_degrees()))
+}
+
+/// 16-point compass direction
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Compass {
+    N,
+    Nne,
+    Ne,
+    Ene,
+    E,
+    Ese,
+    Se,
+    Sse,
+    S,
+    Ssw,
+    Sw,
+    Wsw,
+    W,
+    Wnw,
+    Nw,
+    Nnw,
+}
+
+const COMPASS: [Compass; 16] = [
+    Compass::N,
+    Compass::Nne,
+    Compass::Ne,
+    Compass::Ene,
+    Compass::E,
+    Compass::Ese,
+    Compass::Se,
+    Compass::Sse,
+    Compass::S,
+    Compass::Ssw,
+    Compass::Sw,
+    Compass::Wsw,
+    Compass::W,
+    Compass::Wnw,
+    Compass::Nw,
+    Compass::Nnw,
+];
+
+const COMPASS_SECTOR_DEG: f64 = FULL_CIRCLE_DEG / 16.0;
+
+impl Compass {
+    /// # Returns compass point whose sector, 22.5 degrees wide, holds given bearing
+    pub fn from_bearing(bearing_deg: f64) -> Self {
+        let sector = (normalize_bearing(bearing_deg) / COMPASS_SECTOR_DEG).round() as usize % COMPASS.len();
+        COMPASS[sector]
+    }
+
+    /// # Returns all points clockwise from north
+    pub fn all() -> &'static [Compass] {
+        &COMPASS
+    }
+
+    /// # Returns bearing of sector center in degrees
+    pub fn bearing(self) -> f64 {
+        COMPASS.iter().position(|c| *c == self).unwrap_or(0) as f64 * COMPASS_SECTOR_DEG
+    }
+
+    /// # Returns abbreviated name, e.g. NNE
+    pub fn name(self) -> &'static str {
+        match self {
+            Compass::N => "N",
+            Compass::Nne => "NNE",
+            Compass::Ne => "NE",
+            Compass::Ene => "ENE",
+            Compass::E => "E",
+            Compass::Ese => "ESE",
+            Compass::Se => "SE",
+            Compass::Sse => "SSE",
+            Compass::S => "S",
+            Compass::Ssw => "SSW",
+            Compass::Sw => "SW",
+            Compass::Wsw => "WSW",
+            Compass::W => "W",
+            Compass::Wnw => "WNW",
+            Compass::Nw => "NW",
+            Compass::Nnw => "NNW",
+        }
+    }
+}
+
+/// Wind reading, bearing is direction wind blows from, clockwise from north as reported by Airly
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Wind {
+    #[serde(rename = "speedMs")]
+    speed_ms: f64,
+    #[serde(rename = "bearingDeg")]
+    bearing_deg: f64,
+}
+
+impl Wind {
+    /// Constructs Wind
+    ///
+    /// # Arguments:
+    /// * speed_ms - speed in m/s, negative speed reverses bearing
+    /// * bearing_deg - direction wind blows from in degrees, any value is normalized to 0..360
+    ///
+    pub fn new(speed_ms: f64, bearing_deg: f64) -> Self {
+        if speed_ms < 0.0 {
+            return Wind::new(-speed_ms, bearing_deg + FULL_CIRCLE_DEG / 2.0);
+        }
+        Self {
+            speed_ms,
+            bearing_deg: normalize_bearing(bearing_deg),
+        }
+    }
+
+    /// Constructs Wind from speed in km/h, unit Airly reports WIND_SPEED in
+    pub fn from_kmh(speed_kmh: f64, bearing_deg: f64) -> Self {
+        Wind::new(kmh_to_ms(speed_kmh), bearing_deg)
+    }
+
+    /// Constructs Wind from vector components
+    ///
+    /// # Arguments:
+    /// * u - eastward component in m/s
+    /// * v - northward component in m/s
+    ///
+    pub fn from_components(u: f64, v: f64) -> Self {
+        let speed_ms = u.hypot(v);
+        if speed_ms < CALM_RESULTANT {
+            return Wind::new(0.0, 0.0);
+        }
+        Wind::new(speed_ms, (-u).atan2(-v).to_degrees())
+    }
+
+    /// Averages winds, bearing is circular mean weighted by speed and speed is mean of speeds
+    ///
+    /// # Returns mean wind, None if winds is empty
+    ///
+    pub fn mean(winds: &[Wind]) -> Option<Self> {
+        if winds.is_empty() {
+            return None;
+        }
+        let count = winds.len() as f64;
+        let speed_ms = winds.iter().map(|w| w.speed_ms).sum::<f64>() / count;
+        let (u, v) = winds.iter().fold((0.0, 0.0), |(u, v), w| (u + w.u(), v + w.v()));
+        let bearing_deg = if u.hypot(v) < CALM_RESULTANT * count {
+            let bearings: Vec<f64> = winds.iter().map(|w| w.bearing_deg).collect();
+            mean_bearing(&bearings).unwrap_or(0.0)
+        } else {
+            Wind::from_components(u, v).bearing_deg
+        };
+        Some(Wind::new(speed_ms, bearing_deg))
+    }
+
+    pub fn speed_ms(self) -> f64 {
+        self.speed_ms
+    }
+
+    pub fn speed_kmh(self) -> f64 {
+        ms_to_kmh(self.speed_ms)
+    }
+
+    pub fn speed_knots(self) -> f64 {
+        ms_to_knots(self.speed_ms)
+    }
+
+    pub fn bearing_deg(self) -> f64 {
+        self.bearing_deg
+    }
+
+    pub fn compass(self) -> Compass {
+        Compass::from_bearing(self.bearing_deg)
+    }
+
+    pub fn beaufort(self) -> u8 {
+        beaufort(self.speed_ms)
+    }
+
+    /// # Returns eastward component in m/s, negative for wind blowing from east
+    pub fn u(self) -> f64 {
+        -self.speed_ms * self.bearing_deg.to_radians().sin()
+    }
+
+    /// # Returns northward component in m/s, negative for wind blowing from north
+    pub fn v(self) -> f64 {
+        -self.speed_ms * self.bearing_deg.to_radians().cos()
+    }
+}
+
+impl AveragedValues {
+    /// Reads wind of this period
+    ///
+    /// # Returns Wind if both WIND_SPEED and WIND_BEARING were measured None otherwise
+    ///
+    pub fn wind(&self) -> Option<Wind> {
+        let speed_kmh = self.value(&MeasurementKind::WindSpeed)?;
+        let bearing_deg = self.value(&MeasurementKind::WindBearing)?;
+        Some(Wind::from_kmh(speed_kmh, bearing_deg))
+    }
+}
+
+/// Averages wind over periods, e.g. Measurements.history, periods without wind are skipped
+///
+/// # Returns mean wind, see Wind::mean, None if no period has wind
+///
+pub fn mean_wind(periods: &[AveragedValues]) -> Option<Wind> {
+    let winds: Vec<Wind> = periods.iter().filter_map(AveragedValues::wind).collect();
+    Wind::mean(&winds)
+}
+
+#[cfg(test)]
+mod test_wind {
+    use super::{beaufort, mean_bearing, mean_wind, Compass, Wind};
+    use crate::cassette::{self, Cassette};
+    use crate::client::AirlyClient;
+    use crate::types::IndexType;
+
+    const EPSILON: f64 = 1e-9;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn test_compass_and_conversions() {
+        assert_eq!(Compass::from_bearing(0.0), Compass::N);
+        assert_eq!(Compass::from_bearing(11.24), Compass::N);
+        assert_eq!(Compass::from_bearing(11.26), Compass::Nne);
+        assert_eq!(Compass::from_bearing(234.5), Compass::Sw);
+        assert_eq!(Compass::from_bearing(355.0), Compass::N);
+        assert_eq!(Compass::from_bearing(-90.0), Compass::W);
+        assert_eq!(Compass::Wnw.bearing(), 292.5);
+        assert_eq!(Compass::Sse.name(), "SSE");
+        let wind = Wind::from_kmh(36.0, 90.0);
+        assert!(close(wind.speed_ms(), 10.0));
+        assert!(close(wind.speed_kmh(), 36.0));
+        assert_eq!(wind.beaufort(), 5);
+        assert!(close(wind.u(), -10.0) && wind.v().abs() < EPSILON);
+        let back = Wind::from_components(wind.u(), wind.v());
+        assert!(close(back.speed_ms(), 10.0) && close(back.bearing_deg(), 90.0));
+        assert!(close(Wind::new(-2.0, 45.0).bearing_deg(), 225.0));
+        assert_eq!(beaufort(0.2), 0);
+        assert_eq!(beaufort(40.0), 12);
+    }
+
+    #[test]
+    fn test_circular_means() {
+        assert!(close(mean_bearing(&[350.0, 10.0]).unwrap(), 0.0));
+        assert!(close(mean_bearing(&[80.0, 100.0, 90.0]).unwrap(), 90.0));
+        assert_eq!(mean_bearing(&[0.0, 180.0]), None);
+        assert_eq!(mean_bearing(&[]), None);
+        let mean = Wind::mean(&[Wind::new(4.0, 350.0), Wind::new(2.0, 20.0)]).unwrap();
+        assert!(close(mean.speed_ms(), 3.0));
+        assert!(mean.bearing_deg() > 350.0 || mean.bearing_deg() < 5.0);
+        assert_eq!(mean.compass(), Compass::N);
+    }
+
+    #[test]
+    fn test_wind_of_measurements() {
+        let cassette = Cassette::replay(cassette::fixture("installation_measurements")).unwrap();
+        let client = AirlyClient::new("00000000000000000000000000000000".to_string())
+            .unwrap()
+            .with_cassette(cassette);
+        let caqi = IndexType {
+            name: Some("AIRLY_CAQI".to_string()),
+            level: None,
+        };
+        let measurements = client
+            .clone()
+            .get_installation_measurements_with_wind(34, caqi.clone())
+            .unwrap();
+        let wind = measurements.current.as_ref().unwrap().wind().unwrap();
+        assert!(close(wind.speed_kmh(), 3.12));
+        assert_eq!(wind.compass(), Compass::Sw);
+        assert!(mean_wind(&measurements.history).is_some());
+        let without = client.get_installation_measurements_without_wind(34, caqi).unwrap();
+        assert!(without.current.unwrap().wind().is_none());
+        assert!(mean_wind(&without.history).is_none());
+    }
+}